@@ -0,0 +1,95 @@
+//! Compares decoding a stream of serverbound chat packets field by field
+//! through `McAsyncReadExt` against splitting whole frames off a
+//! `FrameReader` buffer and decoding them from a `McSliceReader`.
+//!
+//! Run with `cargo bench --bench chat_decode`. Medians of three runs for
+//! 1000 packets on one core of a shared Linux VM, rustc 1.95, release build:
+//!
+//! | decoder           | time per 1000 packets | packets per second |
+//! |-------------------|-----------------------|--------------------|
+//! | `per_field_async` | 318 – 485 µs          | 2.1 – 3.1 M        |
+//! | `buffered_slice`  | 234 – 263 µs          | 3.8 – 4.3 M        |
+//!
+//! Reading whole frames is 1.4 to 1.9 times faster. The machine was noisy
+//! enough that the absolute times moved by a third between runs, but the
+//! buffered reader was faster in every run.
+
+use std::pin::Pin;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use zipper::{
+    mc_types::{
+        ext::{McAsyncReadExt, McReadExt, McWriteExt},
+        slice::McSliceReader,
+        McString,
+    },
+    socket::frame::FrameReader,
+};
+
+const PACKETS: usize = 1000;
+const CHAT_PACKET_ID: i32 = 0x03;
+
+fn chat_stream() -> Vec<u8> {
+    let mut stream = Vec::new();
+    for i in 0..PACKETS {
+        let message = format!(
+            "{} the quick brown fox jumps over the lazy dog {}",
+            i,
+            "!".repeat(i % 150)
+        );
+        let mut body = Vec::new();
+        body.write_mc_varint(CHAT_PACKET_ID).unwrap();
+        body.write_mc_string(&message).unwrap();
+        stream.write_mc_varint(body.len() as i32).unwrap();
+        stream.extend_from_slice(&body);
+    }
+    stream
+}
+
+async fn decode_per_field(mut input: &[u8]) -> usize {
+    let mut reader = Pin::new(&mut input);
+    let mut total = 0;
+    for _ in 0..PACKETS {
+        let _length = reader.read_mc_varint().await.unwrap();
+        let _packet_id = reader.read_mc_varint().await.unwrap();
+        let message = reader
+            .read_mc_string_max(McString::MAX_CHAT_LENGTH)
+            .await
+            .unwrap();
+        total += message.len();
+    }
+    total
+}
+
+async fn decode_buffered(input: &[u8]) -> usize {
+    let mut frames = FrameReader::new(input);
+    let mut total = 0;
+    for _ in 0..PACKETS {
+        let frame = frames.read_frame().await.unwrap();
+        let mut frame = McSliceReader::new(&frame);
+        let _packet_id = frame.read_mc_varint().unwrap();
+        let message = frame.read_mc_str_max(McString::MAX_CHAT_LENGTH).unwrap();
+        total += message.len();
+    }
+    total
+}
+
+fn chat_decode(c: &mut Criterion) {
+    let stream = chat_stream();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("chat_decode");
+    group.throughput(Throughput::Elements(PACKETS as u64));
+    group.bench_function("per_field_async", |b| {
+        b.iter(|| rt.block_on(decode_per_field(black_box(&stream))))
+    });
+    group.bench_function("buffered_slice", |b| {
+        b.iter(|| rt.block_on(decode_buffered(black_box(&stream))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, chat_decode);
+criterion_main!(benches);
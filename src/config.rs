@@ -0,0 +1,503 @@
+//! Server settings.
+//!
+//! Settings are read from a YAML file, `zipper.yaml` unless another one is
+//! given on the command line. Every setting but `registries_report` is
+//! optional:
+//!
+//! ```yaml
+//! registries:
+//!   # Start from the dimension types and biomes compiled into the binary
+//!   embedded_defaults: true
+//!   # YAML files applied over the defaults
+//!   dimension_types: config/dimensions.yaml
+//!   biomes: config/biomes.yaml
+//!   # Every datapack directory and zip file in here, in file name order
+//!   datapacks: datapacks
+//!   # The vanilla 1.18 blocks.json report, for global block state IDs
+//!   blocks_report: generated/reports/blocks.json
+//!   # The same report from 1.20.5, whose clients can't join without it
+//!   blocks_report_1_20_5: generated-1.20.5/reports/blocks.json
+//!   # The vanilla registries.json report, for item, entity type and other
+//!   # IDs. The server doesn't start without it
+//!   registries_report: generated/reports/registries.json
+//!   # The same report from 1.20.5. Their inventories look empty without it
+//!   registries_report_1_20_5: generated-1.20.5/reports/registries.json
+//! world:
+//!   # The world players join, and its dimension type
+//!   name: zipperland:steveland
+//!   dimension_type: minecraft:overworld
+//!   # flat or noise
+//!   generator: noise
+//!   # A number, or text that is hashed into one. Random when left out
+//!   seed: zipper
+//!   # A vanilla world directory to serve. Chunks it doesn't have are generated
+//!   directory: world
+//!   # Seconds between saves of changed chunks, 0 to only save on shutdown
+//!   autosave_interval: 300
+//!   # The randomTickSpeed gamerule: blocks picked in each section every
+//!   # tick for crops to grow, leaves to decay and the like
+//!   random_tick_speed: 3
+//!   # Vanilla's flat preset format: layers from the bottom up, biome and
+//!   # structures
+//!   flat_preset: "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains"
+//!   # What players moving into the world with /world keep: all,
+//!   # attributes (with the inventory), metadata or none
+//!   keep: all
+//! # More worlds, sharing the seed of the first
+//! worlds:
+//!   zipperland:nether:
+//!     dimension_type: minecraft:the_nether
+//!     generator: flat
+//!     flat_preset: "minecraft:bedrock,60*minecraft:netherrack;minecraft:nether_wastes"
+//!     # Within the world directory. Vanilla's DIM-1, DIM1 or
+//!     # dimensions/<namespace>/<name> when left out
+//!     directory: DIM-1
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use yaml_rust::Yaml;
+
+use crate::{
+    mc_types::McIdentifier,
+    world::{
+        blocks::BlockRegistry,
+        datapack::find_datapacks,
+        generator::{flat::CLASSIC_FLAT_PRESET, parse_seed},
+        ids::StaticRegistries,
+        player::DataKept,
+        registry::RegistrySource,
+        schema::{load_entries, EntryFields, RegistryError, RegistryErrors},
+    },
+};
+
+pub const DEFAULT_CONFIG_FILE: &str = "zipper.yaml";
+
+/// Where the registries are loaded from. Sources are applied in the order of
+/// the fields, so datapacks override the YAML files, which override the
+/// embedded defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryConfig {
+    pub embedded_defaults: bool,
+    pub dimension_types: Option<PathBuf>,
+    pub biomes: Option<PathBuf>,
+    pub datapacks: Option<PathBuf>,
+    /// Without a report only the block states embedded in the binary exist.
+    pub blocks_report: Option<PathBuf>,
+    /// How 1.20.5 clients number block states. Without it they can't join.
+    pub blocks_report_1_20_5: Option<PathBuf>,
+    /// Required: the embedded excerpt lacks most items, every particle and
+    /// every sound event.
+    pub registries_report: Option<PathBuf>,
+    /// How 1.20.5 clients number items and the like.
+    pub registries_report_1_20_5: Option<PathBuf>,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        RegistryConfig {
+            embedded_defaults: true,
+            dimension_types: None,
+            biomes: None,
+            datapacks: Some(PathBuf::from("datapacks")),
+            blocks_report: None,
+            blocks_report_1_20_5: None,
+            registries_report: None,
+            registries_report_1_20_5: None,
+        }
+    }
+}
+
+impl RegistryConfig {
+    /// The configured block report, or the embedded excerpt.
+    pub fn block_registry(&self) -> Result<BlockRegistry, RegistryErrors> {
+        match &self.blocks_report {
+            Some(file) => BlockRegistry::load(&file.to_string_lossy()),
+            None => Ok(BlockRegistry::embedded()),
+        }
+    }
+
+    /// The configured 1.20.5 block report, if any.
+    pub fn block_registry_1_20_5(&self) -> Option<Result<BlockRegistry, RegistryErrors>> {
+        let file = self.blocks_report_1_20_5.as_ref()?;
+        Some(BlockRegistry::load(&file.to_string_lossy()))
+    }
+
+    /// The configured registries report.
+    pub fn static_registries(&self) -> Result<StaticRegistries, RegistryErrors> {
+        match &self.registries_report {
+            Some(file) => StaticRegistries::load(&file.to_string_lossy()),
+            None => Err(RegistryError::Io {
+                file: "registries.registries_report".to_owned(),
+                error: io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "not set; generate registries.json with `java \
+                     -DbundlerMainClass=net.minecraft.data.Main -jar server.jar --reports`",
+                ),
+            }
+            .into()),
+        }
+    }
+
+    /// The configured 1.20.5 registries report, if any.
+    pub fn static_registries_1_20_5(&self) -> Option<Result<StaticRegistries, RegistryErrors>> {
+        let file = self.registries_report_1_20_5.as_ref()?;
+        Some(StaticRegistries::load(&file.to_string_lossy()))
+    }
+
+    /// The registry sources, with the datapack directory expanded into the
+    /// packs it currently holds.
+    pub fn sources(&self) -> Result<Vec<RegistrySource>, RegistryError> {
+        let mut sources = Vec::new();
+        if self.embedded_defaults {
+            sources.push(RegistrySource::Embedded);
+        }
+        if let Some(file) = &self.dimension_types {
+            sources.push(RegistrySource::DimensionTypes(file.clone()));
+        }
+        if let Some(file) = &self.biomes {
+            sources.push(RegistrySource::Biomes(file.clone()));
+        }
+        if let Some(dir) = &self.datapacks {
+            let packs = find_datapacks(dir).map_err(|error| RegistryError::Io {
+                file: dir.display().to_string(),
+                error,
+            })?;
+            sources.extend(packs.into_iter().map(RegistrySource::Datapack));
+        }
+        Ok(sources)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorKind {
+    Flat,
+    Noise,
+}
+
+/// One of the worlds the server hosts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelConfig {
+    pub name: McIdentifier,
+    pub dimension_type: McIdentifier,
+    pub generator: GeneratorKind,
+    pub flat_preset: String,
+    /// Within the world directory, the folder holding `region`.
+    pub directory: Option<PathBuf>,
+    /// What players keep when they move into the world.
+    pub keep: DataKept,
+}
+
+impl LevelConfig {
+    /// Where the world's region files are, following vanilla's layout
+    /// unless a directory is configured.
+    pub fn region_dir(&self, world_dir: &Path) -> PathBuf {
+        let dir = match &self.directory {
+            Some(dir) => world_dir.join(dir),
+            None if self.dimension_type == McIdentifier::minecraft("the_nether").unwrap() => {
+                world_dir.join("DIM-1")
+            }
+            None if self.dimension_type == McIdentifier::minecraft("the_end").unwrap() => {
+                world_dir.join("DIM1")
+            }
+            None => world_dir
+                .join("dimensions")
+                .join(self.name.namespace())
+                .join(self.name.name()),
+        };
+        dir.join("region")
+    }
+}
+
+/// How the worlds are generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorldConfig {
+    pub name: McIdentifier,
+    pub dimension_type: McIdentifier,
+    pub generator: GeneratorKind,
+    pub seed: Option<String>,
+    pub directory: Option<PathBuf>,
+    /// In seconds, 0 when disabled.
+    pub autosave_interval: u64,
+    /// Shared by every world, like vanilla's gamerules.
+    pub random_tick_speed: u32,
+    /// Parsed once the block registry is installed, since it names blocks.
+    pub flat_preset: String,
+    pub keep: DataKept,
+    /// The worlds besides the first.
+    pub worlds: Vec<LevelConfig>,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig {
+            name: McIdentifier::new("zipperland", "steveland").unwrap(),
+            dimension_type: McIdentifier::minecraft("overworld").unwrap(),
+            generator: GeneratorKind::Flat,
+            seed: None,
+            directory: None,
+            autosave_interval: 300,
+            random_tick_speed: 3,
+            flat_preset: CLASSIC_FLAT_PRESET.to_owned(),
+            keep: DataKept::ALL,
+            worlds: Vec::new(),
+        }
+    }
+}
+
+impl WorldConfig {
+    /// The configured seed, or a random one.
+    pub fn seed(&self) -> i64 {
+        match self.seed.as_deref() {
+            Some(seed) if !seed.is_empty() => parse_seed(seed),
+            _ => rand::random(),
+        }
+    }
+
+    /// Every world, the one players join first. It keeps its region files
+    /// at the top of the world directory.
+    pub fn levels(&self) -> Vec<LevelConfig> {
+        let first = LevelConfig {
+            name: self.name.clone(),
+            dimension_type: self.dimension_type.clone(),
+            generator: self.generator,
+            flat_preset: self.flat_preset.clone(),
+            directory: Some(PathBuf::new()),
+            keep: self.keep,
+        };
+        std::iter::once(first)
+            .chain(self.worlds.iter().cloned())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub registries: RegistryConfig,
+    pub world: WorldConfig,
+}
+
+/// Loads the settings in `file`. A missing file leaves every setting at its
+/// default.
+pub fn load_config(file: &Path) -> Result<Config, RegistryErrors> {
+    let mut config = Config::default();
+    if !file.exists() {
+        return Ok(config);
+    }
+    let name = file.display().to_string();
+    let mut errors = Vec::new();
+    for (section, doc) in load_entries(&name)? {
+        let mut fields = EntryFields::new(&name, &section, &doc, &mut errors);
+        match section.as_str() {
+            "registries" => read_registries(&mut fields, &mut config.registries),
+            "world" => read_world(&mut fields, &mut config.world),
+            "worlds" => read_worlds(&mut fields, &doc, &mut config.world),
+            _ => fields.error("", "unknown section"),
+        }
+        fields.finish();
+    }
+    if errors.is_empty() {
+        Ok(config)
+    } else {
+        Err(RegistryErrors(errors))
+    }
+}
+
+fn read_registries(f: &mut EntryFields, config: &mut RegistryConfig) {
+    let path = |f: &mut EntryFields, k: &str| f.string(k, None).map(PathBuf::from);
+    if let Some(embedded) = f.bool("embedded_defaults", Some(config.embedded_defaults)) {
+        config.embedded_defaults = embedded;
+    }
+    if let Some(file) = f.optional("dimension_types", path).flatten() {
+        config.dimension_types = Some(file);
+    }
+    if let Some(file) = f.optional("biomes", path).flatten() {
+        config.biomes = Some(file);
+    }
+    if let Some(dir) = f.optional("datapacks", path).flatten() {
+        config.datapacks = Some(dir);
+    }
+    if let Some(file) = f.optional("blocks_report", path).flatten() {
+        config.blocks_report = Some(file);
+    }
+    if let Some(file) = f.optional("blocks_report_1_20_5", path).flatten() {
+        config.blocks_report_1_20_5 = Some(file);
+    }
+    if let Some(file) = f.optional("registries_report", path).flatten() {
+        config.registries_report = Some(file);
+    }
+    if let Some(file) = f.optional("registries_report_1_20_5", path).flatten() {
+        config.registries_report_1_20_5 = Some(file);
+    }
+}
+
+fn read_generator(f: &mut EntryFields) -> Option<GeneratorKind> {
+    match f.string("generator", Some("flat")).as_deref() {
+        Some("flat") => Some(GeneratorKind::Flat),
+        Some("noise") => Some(GeneratorKind::Noise),
+        Some(_) => {
+            f.error("generator", "expected flat or noise");
+            None
+        }
+        None => None,
+    }
+}
+
+fn read_keep(f: &mut EntryFields) -> Option<DataKept> {
+    match f.string("keep", Some("all")).as_deref() {
+        Some("all") => Some(DataKept::ALL),
+        Some("attributes") => Some(DataKept::ATTRIBUTES),
+        Some("metadata") => Some(DataKept::METADATA),
+        Some("none") => Some(DataKept::NONE),
+        Some(_) => {
+            f.error("keep", "expected all, attributes, metadata or none");
+            None
+        }
+        None => None,
+    }
+}
+
+fn read_identifier(f: &mut EntryFields, k: &str, default: &McIdentifier) -> Option<McIdentifier> {
+    let default = default.to_string();
+    f.identifier(k, Some(&default), false)?.parse().ok()
+}
+
+fn read_world(f: &mut EntryFields, config: &mut WorldConfig) {
+    if let Some(name) = read_identifier(f, "name", &config.name) {
+        config.name = name;
+    }
+    if let Some(dimension_type) = read_identifier(f, "dimension_type", &config.dimension_type) {
+        config.dimension_type = dimension_type;
+    }
+    if let Some(generator) = read_generator(f) {
+        config.generator = generator;
+    }
+    if let Some(seed) = f.optional("seed", |f, k| f.text(k, None)).flatten() {
+        config.seed = Some(seed);
+    }
+    let path = |f: &mut EntryFields, k: &str| f.string(k, None).map(PathBuf::from);
+    if let Some(dir) = f.optional("directory", path).flatten() {
+        config.directory = Some(dir);
+    }
+    let interval = config.autosave_interval as i64;
+    if let Some(interval) = f.int("autosave_interval", Some(interval), 0, i32::MAX as i64) {
+        config.autosave_interval = interval as u64;
+    }
+    let speed = config.random_tick_speed as i64;
+    if let Some(speed) = f.int("random_tick_speed", Some(speed), 0, 4096) {
+        config.random_tick_speed = speed as u32;
+    }
+    if let Some(preset) = f.string("flat_preset", Some(&config.flat_preset)) {
+        config.flat_preset = preset;
+    }
+    if let Some(keep) = read_keep(f) {
+        config.keep = keep;
+    }
+}
+
+fn read_worlds(f: &mut EntryFields, doc: &Yaml, config: &mut WorldConfig) {
+    let names: Vec<String> = match doc.as_hash() {
+        Some(hash) => hash
+            .keys()
+            .filter_map(|k| k.as_str())
+            .map(String::from)
+            .collect(),
+        None => return,
+    };
+    for name in names {
+        let parsed = match name.parse::<McIdentifier>() {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                f.error(&name, &e.to_string());
+                f.ignore(&name);
+                continue;
+            }
+        };
+        if parsed == config.name || config.worlds.iter().any(|w| w.name == parsed) {
+            f.error(&name, "there already is a world with this name");
+            f.ignore(&name);
+            continue;
+        }
+        let mut w = match f.nested(&name) {
+            Some(w) => w,
+            None => continue,
+        };
+        let overworld = McIdentifier::minecraft("overworld").unwrap();
+        let dimension_type = read_identifier(&mut w, "dimension_type", &overworld);
+        let generator = read_generator(&mut w);
+        let flat_preset = w.string("flat_preset", Some(CLASSIC_FLAT_PRESET));
+        let path = |f: &mut EntryFields, k: &str| f.string(k, None).map(PathBuf::from);
+        let directory = w.optional("directory", path);
+        let keep = read_keep(&mut w);
+        w.finish();
+        if let (
+            Some(dimension_type),
+            Some(generator),
+            Some(flat_preset),
+            Some(directory),
+            Some(keep),
+        ) = (dimension_type, generator, flat_preset, directory, keep)
+        {
+            config.worlds.push(LevelConfig {
+                name: parsed,
+                dimension_type,
+                generator,
+                flat_preset,
+                directory,
+                keep,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config() {
+        assert_eq!(
+            load_config(Path::new("does/not/exist.yaml")).unwrap(),
+            Config::default()
+        );
+
+        let file = std::env::temp_dir().join(format!("zipper-config-{}.yaml", std::process::id()));
+        std::fs::write(
+            &file,
+            "registries:\n  embedded_defaults: false\n  biomes: src/world/biomes.yaml\n  \
+             datapacks: does/not/exist\nworld:\n  generator: noise\n  seed: 12345\n  \
+             autosave_interval: 0\n  random_tick_speed: 0\nworlds:\n  zipperland:nether:\n    \
+             dimension_type: the_nether\n    keep: metadata\n",
+        )
+        .unwrap();
+        let config = load_config(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(
+            config.registries.sources().unwrap(),
+            vec![RegistrySource::Biomes("src/world/biomes.yaml".into())]
+        );
+        assert!(config
+            .registries
+            .static_registries()
+            .unwrap_err()
+            .to_string()
+            .starts_with("registries.registries_report: not set"));
+        assert_eq!(config.world.generator, GeneratorKind::Noise);
+        assert_eq!(config.world.seed(), 12345);
+        assert_eq!(config.world.autosave_interval, 0);
+        assert_eq!(config.world.random_tick_speed, 0);
+        let levels = config.world.levels();
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].region_dir(Path::new("w")), Path::new("w/region"));
+        assert_eq!(levels[1].name.to_string(), "zipperland:nether");
+        assert_eq!(levels[0].keep, DataKept::ALL);
+        assert_eq!(levels[1].keep, DataKept::METADATA);
+        assert_eq!(
+            levels[1].region_dir(Path::new("w")),
+            Path::new("w/DIM-1/region")
+        );
+    }
+}
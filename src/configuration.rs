@@ -0,0 +1,274 @@
+//! Packets of the Configuration state, as of protocol 766 (1.20.5).
+//!
+//! After Login Acknowledged the server sends Feature Flags and its Known
+//! Packs. Once the client answers with the packs it knows, the server sends
+//! one Registry Data packet per registry, Update Tags and Finish
+//! Configuration. The dimension types and biomes come from the same
+//! `RegistrySet` that the Join Game codec of older versions is built from,
+//! in their 1.20.5 format.
+
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    mc_types::{
+        ext::{McReadExt, McWriteExt},
+        McIdentifier, McString,
+    },
+    world::registry::{Registry, RegistrySet, ToNbt},
+};
+
+/// The feature flags enabled on this server.
+pub const FEATURE_FLAGS: &[&str] = &["minecraft:vanilla"];
+
+/// The most packs a client may list in Known Packs.
+pub const MAX_KNOWN_PACKS: i32 = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPack {
+    pub namespace: String,
+    pub id: String,
+    pub version: String,
+}
+
+pub fn prepare_feature_flags(buf: &mut impl Write, flags: &[McIdentifier]) -> io::Result<usize> {
+    let mut count = buf.write_mc_varint(flags.len() as i32)?;
+    for flag in flags {
+        count += buf.write_mc_identifier(flag)?;
+    }
+    Ok(count)
+}
+
+pub fn prepare_known_packs(buf: &mut impl Write, packs: &[KnownPack]) -> io::Result<usize> {
+    let mut count = buf.write_mc_varint(packs.len() as i32)?;
+    for pack in packs {
+        count += buf.write_mc_string(&pack.namespace)?;
+        count += buf.write_mc_string(&pack.id)?;
+        count += buf.write_mc_string(&pack.version)?;
+    }
+    Ok(count)
+}
+
+/// Reads the client's Known Packs.
+pub fn read_known_packs(reader: &mut impl Read) -> io::Result<Vec<KnownPack>> {
+    let length = reader.read_mc_varint()?;
+    if !(0..=MAX_KNOWN_PACKS).contains(&length) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} known packs", length),
+        ));
+    }
+    let mut packs = Vec::with_capacity(length as usize);
+    for _ in 0..length {
+        packs.push(KnownPack {
+            namespace: reader.read_mc_string_max(McString::MAX_LENGTH)?,
+            id: reader.read_mc_string_max(McString::MAX_LENGTH)?,
+            version: reader.read_mc_string_max(McString::MAX_LENGTH)?,
+        });
+    }
+    Ok(packs)
+}
+
+/// Vanilla's 1.20.5 entries for the registries that clients need but the
+/// server has no use for, as `{registry: {entry: element}}`. Wolf variants
+/// spawn in no biomes, since the biomes are configurable.
+pub const FIXED_REGISTRIES: &str = include_str!("world/fixed_registries.json");
+
+/// Registry Data for one registry, with every element included.
+pub fn prepare_registry_data<T: ToNbt>(
+    buf: &mut impl Write,
+    registry: &Registry<T>,
+) -> io::Result<usize> {
+    let mut count = buf.write_mc_identifier(registry.registry_type())?;
+    count += buf.write_mc_varint(registry.len() as i32)?;
+    for entry in registry.iter() {
+        count += buf.write_mc_identifier(&entry.name)?;
+        count += buf.write_mc_bool(true)?; // has data
+        count += buf.write_mc_network_nbt(&entry.element.to_registry_data())?;
+    }
+    Ok(count)
+}
+
+fn invalid_fixed_registries(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse_identifier(name: &str) -> io::Result<McIdentifier> {
+    name.parse()
+        .map_err(|e| invalid_fixed_registries(format!("{}: {}", name, e)))
+}
+
+/// JSON numbers become `TAG_Int`, `TAG_Long` or `TAG_Double` and booleans
+/// `TAG_Byte`, which the client's codecs accept for any number or boolean.
+fn json_to_nbt(json: &serde_json::Value) -> io::Result<nbt::Value> {
+    use serde_json::Value;
+    Ok(match json {
+        Value::Null => return Err(invalid_fixed_registries("null element".to_owned())),
+        Value::Bool(b) => nbt::Value::Byte(*b as i8),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) if i32::try_from(i).is_ok() => nbt::Value::Int(i as i32),
+            Some(i) => nbt::Value::Long(i),
+            None => nbt::Value::Double(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => nbt::Value::String(s.clone()),
+        Value::Array(array) => {
+            nbt::Value::List(array.iter().map(json_to_nbt).collect::<io::Result<_>>()?)
+        }
+        Value::Object(object) => {
+            let mut map = nbt::Map::new();
+            for (k, v) in object {
+                map.insert(k.clone(), json_to_nbt(v)?);
+            }
+            nbt::Value::Compound(map)
+        }
+    })
+}
+
+/// Registry Data for every registry in `FIXED_REGISTRIES`.
+fn prepare_fixed_registry_data() -> io::Result<Vec<Vec<u8>>> {
+    let registries: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(FIXED_REGISTRIES)
+            .map_err(|e| invalid_fixed_registries(e.to_string()))?;
+    let mut packets = Vec::new();
+    for (registry, entries) in &registries {
+        let entries = entries
+            .as_object()
+            .ok_or_else(|| invalid_fixed_registries(format!("{} is not an object", registry)))?;
+        let mut buf = Vec::new();
+        buf.write_mc_identifier(&parse_identifier(registry)?)?;
+        buf.write_mc_varint(entries.len() as i32)?;
+        for (name, element) in entries {
+            buf.write_mc_identifier(&parse_identifier(name)?)?;
+            buf.write_mc_bool(true)?; // has data
+            buf.write_mc_network_nbt(&json_to_nbt(element)?)?;
+        }
+        packets.push(buf);
+    }
+    Ok(packets)
+}
+
+/// The bodies of the Registry Data packets for every registry the client
+/// needs: the dimension types and biomes of the set, then the fixed ones.
+pub fn prepare_registry_data_packets(registries: &RegistrySet) -> io::Result<Vec<Vec<u8>>> {
+    let mut dimension_types = Vec::new();
+    prepare_registry_data(&mut dimension_types, &registries.dimension_types)?;
+    let mut biomes = Vec::new();
+    prepare_registry_data(&mut biomes, &registries.biomes)?;
+    let mut packets = vec![dimension_types, biomes];
+    packets.extend(prepare_fixed_registry_data()?);
+    Ok(packets)
+}
+
+fn write_tags<T>(buf: &mut impl Write, registry: &Registry<T>) -> io::Result<usize> {
+    let mut count = buf.write_mc_identifier(registry.registry_type())?;
+    count += buf.write_mc_varint(registry.tags().count() as i32)?;
+    for (tag, ids) in registry.tags() {
+        count += buf.write_mc_identifier(tag)?;
+        count += buf.write_mc_varint(ids.len() as i32)?;
+        for &id in ids {
+            count += buf.write_mc_varint(id)?;
+        }
+    }
+    Ok(count)
+}
+
+pub fn prepare_update_tags(buf: &mut impl Write, registries: &RegistrySet) -> io::Result<usize> {
+    let mut count = buf.write_mc_varint(2)?; // registry count
+    count += write_tags(buf, &registries.dimension_types)?;
+    count += write_tags(buf, &registries.biomes)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mc_types::slice::McSliceReader, world::registry::RegistrySource};
+
+    #[test]
+    fn test_known_packs_round_trip() {
+        let packs = vec![KnownPack {
+            namespace: "minecraft".to_owned(),
+            id: "core".to_owned(),
+            version: "1.20.6".to_owned(),
+        }];
+        let mut buf = Vec::new();
+        let count = prepare_known_packs(&mut buf, &packs).unwrap();
+        assert_eq!(count, buf.len());
+        let mut reader = McSliceReader::new(&buf);
+        assert_eq!(read_known_packs(&mut reader).unwrap(), packs);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_registry_data() {
+        let registries = RegistrySet::load(&[RegistrySource::Embedded]).unwrap();
+        let packets = prepare_registry_data_packets(&registries).unwrap();
+        let mut contents = Vec::new();
+        for packet in &packets {
+            let mut reader = McSliceReader::new(packet);
+            let registry = reader.read_mc_identifier().unwrap().to_string();
+            let mut entries = Vec::new();
+            for _ in 0..reader.read_mc_varint().unwrap() {
+                let name = reader.read_mc_identifier().unwrap().to_string();
+                assert!(reader.read_mc_bool().unwrap());
+                entries.push((name, reader.read_mc_network_nbt().unwrap()));
+            }
+            assert!(reader.is_empty());
+            contents.push((registry, entries));
+        }
+        let names: Vec<&str> = contents.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "minecraft:dimension_type",
+                "minecraft:worldgen/biome",
+                "minecraft:banner_pattern",
+                "minecraft:chat_type",
+                "minecraft:damage_type",
+                "minecraft:painting_variant",
+                "minecraft:trim_material",
+                "minecraft:trim_pattern",
+                "minecraft:wolf_variant",
+            ]
+        );
+
+        let field = |entry: &nbt::Value, k: &str| match entry {
+            nbt::Value::Compound(map) => map.get(k).cloned(),
+            _ => panic!("{:?} is not a compound", entry),
+        };
+        let (name, overworld) = &contents[0].1[0];
+        assert_eq!(name, "minecraft:overworld");
+        assert_eq!(
+            field(overworld, "infiniburn"),
+            Some(nbt::Value::String(
+                "#minecraft:infiniburn_overworld".to_owned()
+            ))
+        );
+        assert!(field(overworld, "monster_spawn_light_level").is_some());
+        assert!(field(overworld, "monster_spawn_block_light_limit").is_some());
+
+        let biomes = &contents[1].1;
+        assert_eq!(biomes.len(), registries.biomes.len());
+        for (_, biome) in biomes {
+            assert!(field(biome, "has_precipitation").is_some());
+            for removed in ["precipitation", "category", "depth", "scale"] {
+                assert_eq!(field(biome, removed), None);
+            }
+        }
+
+        let damage_types = &contents[4].1;
+        let (name, in_fire) = damage_types
+            .iter()
+            .find(|(name, _)| name == "minecraft:in_fire")
+            .unwrap();
+        assert_eq!(name, "minecraft:in_fire");
+        assert_eq!(
+            field(in_fire, "message_id"),
+            Some(nbt::Value::String("inFire".to_owned()))
+        );
+        assert_eq!(field(in_fire, "exhaustion"), Some(nbt::Value::Double(0.1)));
+        assert!(contents.iter().all(|(_, entries)| !entries.is_empty()));
+    }
+}
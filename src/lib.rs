@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate num_derive;
+extern crate lazy_static;
+
+pub mod config;
+pub mod configuration;
+pub mod mc_types;
+pub mod packet;
+pub mod socket;
+pub mod world;
@@ -1,36 +1,68 @@
-#[macro_use]
-extern crate num_derive;
-extern crate lazy_static;
-
-mod mc_types;
-mod packet;
-mod socket;
-mod world;
-
 use lazy_static::lazy_static;
-use mc_types::McIdentifier;
 use nbt::Blob;
 use rand::prelude::*;
 use std::{
     fs::{read_to_string, File},
     io::{self, Cursor, Read, Write},
+    path::Path,
     pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 // use std::net::{TcpListener, TcpStream};
 use tokio::{
     io::{AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream},
+    sync::broadcast::{self, error::TryRecvError},
 };
 
-use crate::{
-    mc_types::ext::{McAsyncReadExt, McAsyncWriteExt, McReadExt, McWriteExt},
-    packet::{LoginPacketOut, SocketMode},
-    socket::{compression::McNoCompression, passthrough::McPassthrough, McSocket},
+use zipper::{
+    config::{self, GeneratorKind, DEFAULT_CONFIG_FILE},
+    configuration,
+    mc_types::{
+        self,
+        ext::{McAsyncWriteExt, McReadExt, McWriteExt},
+        slice::McSliceReader,
+        McIdentifier, McString,
+    },
+    packet::{
+        is_supported_protocol, ConfigurationPacket, ConfigurationPacketOut, GameMode,
+        HandshakingPacket, LoginPacket, LoginPacketOut, PlayPacket, PlayPacketOut, SocketMode,
+        CONFIGURATION_PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS,
+    },
+    socket::{
+        compression::McNoCompression, frame::FrameReader, passthrough::McPassthrough, McSocket,
+    },
+    world::{
+        self,
+        anvil::RegionStorage,
+        behavior::BlockBehaviors,
+        blocks::{client_state_ids, ClientStateIds},
+        generator::{
+            flat::{FlatGenerator, FlatSettings},
+            hashed_seed,
+            terrain::{NoiseGenerator, NoiseSettings},
+            ChunkGenerator,
+        },
+        ids::configured_static_registries,
+        inventory::{write_slot, write_slot_configured, ItemStack},
+        level::{Level, LevelChanges, LevelSet},
+        player::{DataKept, Player},
+        registry::{RegistrySet, ToNbt},
+        tick::BlockPos,
+        tracking::ChunkTracker,
+    },
 };
-use packet::{GameMode, HandshakingPacket, LoginPacket};
 
 const ENCRYPTION_ENABLED: bool = false;
+/// In chunks, lowered to the client's if theirs is shorter.
+const VIEW_DISTANCE: i32 = 16;
+/// The most chunks sent to a player each tick.
+const CHUNKS_PER_TICK: usize = 16;
+const TICK: Duration = Duration::from_millis(50);
+/// Game Event "start waiting for level chunks", since 1.20.3.
+const GAME_EVENT_WAIT_FOR_CHUNKS: u8 = 13;
 
 lazy_static! {
     static ref PUBLIC_KEY: Vec<u8> = {
@@ -69,20 +101,305 @@ fn prepare_encryption_request(buf: &mut impl Write) -> io::Result<()> {
     Ok(())
 }
 
-fn prepare_login_success(buf: &mut impl Write) -> io::Result<usize> {
+fn prepare_login_success(buf: &mut impl Write, protocol_version: i32) -> io::Result<usize> {
     let mut count = 0;
-    let uuid = mc_types::McUUID {
-        most: 64,
-        least: 64,
-    };
+    let uuid = mc_types::McUUID::offline_player("Shifty");
     count += buf.write_mc_uuid(&uuid)?;
     count += buf.write_mc_string("Shifty")?;
+    if protocol_version >= CONFIGURATION_PROTOCOL_VERSION {
+        count += buf.write_mc_varint(0)?; // properties
+        count += buf.write_mc_bool(false)?; // strict error handling
+    }
+    Ok(count)
+}
+
+/// Whether a client can join: its protocol is supported and its block state
+/// IDs are known.
+fn can_join(protocol_version: i32) -> bool {
+    is_supported_protocol(protocol_version) && client_state_ids(protocol_version).is_some()
+}
+
+/// Login Disconnect telling the player which versions can join.
+fn prepare_unsupported_version(buf: &mut impl Write) -> io::Result<usize> {
+    let versions: Vec<&str> = SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .filter(|&&(version, _)| can_join(version))
+        .map(|&(_, name)| name)
+        .collect();
+    let reason = serde_json::json!({
+        "text": format!("This server supports Minecraft {}", versions.join(" and "))
+    });
+    buf.write_mc_string(&reason.to_string())
+}
+
+fn prepare_feature_flags(buf: &mut impl Write) -> io::Result<usize> {
+    let flags: Vec<McIdentifier> = configuration::FEATURE_FLAGS
+        .iter()
+        .map(|flag| flag.parse().unwrap())
+        .collect();
+    configuration::prepare_feature_flags(buf, &flags)
+}
+
+/// Join Game for clients that received the registries during Configuration.
+fn prepare_join_game_configured(
+    buf: &mut impl Write,
+    registries: &RegistrySet,
+    worlds: &LevelSet,
+    level: &Level,
+) -> io::Result<usize> {
+    let dimension_type = registries
+        .dimension_types
+        .id_of(level.dimension_type())
+        .unwrap_or(0);
+    let mut count = 0;
+    // player Entity ID
+    count += buf.write_mc_int(24)?;
+    count += buf.write_mc_bool(false)?; // hardcore
+    count += write_world_names(buf, worlds)?;
+    let world_id = level.name();
+    count += buf.write_mc_varint(32)?; // max players
+    count += buf.write_mc_varint(VIEW_DISTANCE)?; // view distance
+    count += buf.write_mc_varint(16)?; // simulation distance
+    count += buf.write_mc_bool(false)?; // reduced debug info
+    count += buf.write_mc_bool(true)?; // respawn screen
+    count += buf.write_mc_bool(false)?; // limited crafting
+    count += buf.write_mc_varint(dimension_type)?;
+    count += buf.write_mc_identifier(world_id)?; // world name
+    count += buf.write_mc_long(level.hashed_seed())?;
+    // current gamemode
+    count += buf.write_mc_ubyte(num::ToPrimitive::to_u8(&GameMode::Survival).unwrap())?;
+    count += buf.write_mc_byte(num::ToPrimitive::to_i8(&GameMode::NoPreviousGameMode).unwrap())?;
+    count += buf.write_mc_bool(false)?; // debug world
+    count += buf.write_mc_bool(level.is_flat())?; // superflat
+    count += buf.write_mc_bool(false)?; // death location
+    count += buf.write_mc_varint(0)?; // portal cooldown
+    count += buf.write_mc_bool(false)?; // enforces secure chat
+    Ok(count)
+}
+
+fn write_world_names(buf: &mut impl Write, worlds: &LevelSet) -> io::Result<usize> {
+    let mut count = buf.write_mc_varint(worlds.names().len() as i32)?;
+    for name in worlds.names() {
+        count += buf.write_mc_identifier(name)?;
+    }
+    Ok(count)
+}
+
+/// Since 1.20.2 the dimension type is sent as its registry ID, and since
+/// 1.20 every data kept flag is.
+fn prepare_respawn(
+    buf: &mut impl Write,
+    registries: &RegistrySet,
+    level: &Level,
+    kept: DataKept,
+    protocol_version: i32,
+) -> io::Result<usize> {
+    let configured = protocol_version >= CONFIGURATION_PROTOCOL_VERSION;
+    let mut count = 0;
+    if configured {
+        let dimension_type = registries
+            .dimension_types
+            .id_of(level.dimension_type())
+            .unwrap_or(0);
+        count += buf.write_mc_varint(dimension_type)?;
+    } else {
+        count += buf.write_mc_nbt(&level.dimension().to_nbt())?;
+    }
+    count += buf.write_mc_identifier(level.name())?; // world name
+    count += buf.write_mc_long(level.hashed_seed())?;
+    // current gamemode
+    count += buf.write_mc_ubyte(num::ToPrimitive::to_u8(&GameMode::Survival).unwrap())?;
+    count += buf.write_mc_byte(num::ToPrimitive::to_i8(&GameMode::Survival).unwrap())?;
+    count += buf.write_mc_bool(false)?; // debug world
+    count += buf.write_mc_bool(level.is_flat())?; // superflat
+    if configured {
+        count += buf.write_mc_bool(false)?; // death location
+        count += buf.write_mc_varint(0)?; // portal cooldown
+        count += buf.write_mc_ubyte(kept.bits())?;
+    } else {
+        count += buf.write_mc_bool(kept.keeps_metadata())?; // copy metadata
+    }
+    Ok(count)
+}
+
+/// 1.20.5 clients get their own item IDs, and empty slots without them.
+fn write_client_slot(
+    buf: &mut impl Write,
+    slot: Option<&ItemStack>,
+    protocol_version: i32,
+) -> io::Result<usize> {
+    if protocol_version < CONFIGURATION_PROTOCOL_VERSION {
+        return write_slot(buf, slot);
+    }
+    match configured_static_registries() {
+        Some(registries) => write_slot_configured(buf, slot, &registries.items),
+        None => buf.write_mc_varint(0), // empty slot
+    }
+}
+
+fn prepare_window_items(
+    buf: &mut impl Write,
+    inventory: &[Option<ItemStack>],
+    protocol_version: i32,
+) -> io::Result<usize> {
+    let mut count = 0;
+    count += buf.write_mc_ubyte(0)?; // player inventory
+    count += buf.write_mc_varint(0)?; // state ID
+    count += buf.write_mc_varint(inventory.len() as i32)?;
+    for slot in inventory {
+        count += write_client_slot(buf, slot.as_ref(), protocol_version)?;
+    }
+    count += write_client_slot(buf, None, protocol_version)?; // carried item
+    Ok(count)
+}
+
+fn prepare_update_view_position(buf: &mut impl Write, x: i32, z: i32) -> io::Result<usize> {
+    let mut count = buf.write_mc_varint(x)?;
+    count += buf.write_mc_varint(z)?;
     Ok(count)
 }
 
-fn prepare_join_game(buf: &mut impl Write) -> io::Result<usize> {
-    let mut f = File::create("nbt.txt").unwrap();
+/// Since 1.20.2 the chunk position is sent as one long, Z in the high half.
+fn prepare_unload_chunk(
+    buf: &mut impl Write,
+    x: i32,
+    z: i32,
+    protocol_version: i32,
+) -> io::Result<usize> {
+    if protocol_version >= CONFIGURATION_PROTOCOL_VERSION {
+        let mut count = buf.write_mc_int(z)?;
+        count += buf.write_mc_int(x)?;
+        return Ok(count);
+    }
+    let mut count = buf.write_mc_int(x)?;
+    count += buf.write_mc_int(z)?;
+    Ok(count)
+}
+
+fn prepare_game_event(buf: &mut impl Write, event: u8, value: f32) -> io::Result<usize> {
+    let mut count = buf.write_mc_ubyte(event)?;
+    count += buf.write_mc_float(value)?;
+    Ok(count)
+}
 
+fn prepare_block_change(buf: &mut impl Write, pos: BlockPos, state: i32) -> io::Result<usize> {
+    let (x, y, z) = pos;
+    let position =
+        ((x as i64 & 0x3ff_ffff) << 38) | ((z as i64 & 0x3ff_ffff) << 12) | (y as i64 & 0xfff);
+    let mut count = buf.write_mc_long(position)?;
+    count += buf.write_mc_varint(state)?;
+    Ok(count)
+}
+
+/// What a player sees of the level they are in.
+struct LevelView {
+    tracker: ChunkTracker,
+    /// Each tick's changes to the level, shared by everyone in it.
+    changes: broadcast::Receiver<Arc<LevelChanges>>,
+}
+
+/// Unloads the chunks that left the player's view and sends this tick's
+/// share of new ones. Blocks that changed in chunks already sent get a
+/// Block Change, and those chunks an Update Light if their light changed,
+/// from a block change or because a neighbour was just loaded.
+async fn stream_chunks<S>(
+    view: &mut LevelView,
+    level: &Mutex<Level>,
+    stream: &mut Pin<&mut S>,
+    protocol_version: i32,
+) -> io::Result<()>
+where
+    S: AsyncWrite + Send,
+{
+    let tracker = &mut view.tracker;
+    loop {
+        match view.changes.try_recv() {
+            Ok(changes) => tracker.add_changes(&changes),
+            // Too far behind to catch up change by change
+            Err(TryRecvError::Lagged(_)) => tracker.resend_all(),
+            Err(_) => break,
+        }
+    }
+    for (x, z) in tracker.take_unloads() {
+        let mut cur = buffer_cursor();
+        prepare_unload_chunk(&mut cur, x, z, protocol_version)?;
+        let pid = PlayPacketOut::UnloadChunk.id(protocol_version);
+        send_packet_uncompressed(pid, stream, cur.get_ref()).await?;
+    }
+    let ids = client_state_ids(protocol_version)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown block state IDs"))?;
+    let batch = tracker.next_batch();
+    let mut packets = Vec::new();
+    let mut changes = Vec::new();
+    let mut relit = Vec::new();
+    {
+        let mut level = level.lock().unwrap();
+        for &(x, z) in &batch {
+            level.chunk(x, z);
+        }
+        for &(x, z) in &batch {
+            let mut cur = buffer_cursor();
+            level
+                .chunk(x, z)
+                .write_chunk_data(&mut cur, protocol_version, ids)?;
+            packets.push(cur.into_inner());
+        }
+        // Only chunks sent before this batch have changes kept
+        let kept = tracker.take_changes();
+        for (x, y, z) in kept.blocks {
+            let mut cur = buffer_cursor();
+            let state = ids.get(level.get_block(x, y, z).id());
+            prepare_block_change(&mut cur, (x, y, z), state)?;
+            changes.push(cur.into_inner());
+        }
+        for (x, z) in kept.light {
+            let mut cur = buffer_cursor();
+            level
+                .chunk(x, z)
+                .write_update_light(&mut cur, protocol_version)?;
+            relit.push(cur.into_inner());
+        }
+    }
+    for packet in packets {
+        let pid = PlayPacketOut::ChunkDataAndUpdateLight.id(protocol_version);
+        send_packet_uncompressed(pid, stream, &packet).await?;
+    }
+    for packet in changes {
+        let pid = PlayPacketOut::BlockChange.id(protocol_version);
+        send_packet_uncompressed(pid, stream, &packet).await?;
+    }
+    for packet in relit {
+        let pid = PlayPacketOut::UpdateLight.id(protocol_version);
+        send_packet_uncompressed(pid, stream, &packet).await?;
+    }
+    Ok(())
+}
+
+fn prepare_player_position(
+    buf: &mut impl Write,
+    (x, y, z): (f64, f64, f64),
+    protocol_version: i32,
+) -> io::Result<usize> {
+    let mut count = 0;
+    count += buf.write_mc_double(x)?;
+    count += buf.write_mc_double(y)?;
+    count += buf.write_mc_double(z)?;
+    count += buf.write_mc_float(0.0)?; // yaw
+    count += buf.write_mc_float(0.0)?; // pitch
+    count += buf.write_mc_byte(0)?; // flags, all absolute
+    count += buf.write_mc_varint(1)?; // teleport ID
+    if protocol_version < CONFIGURATION_PROTOCOL_VERSION {
+        count += buf.write_mc_bool(false)?; // dismount vehicle
+    }
+    Ok(count)
+}
+
+fn prepare_join_game(
+    buf: &mut impl Write,
+    registries: &RegistrySet,
+    worlds: &LevelSet,
+    level: &Level,
+) -> io::Result<usize> {
     let mut count = 0;
     // player Entity ID
     count += buf.write_mc_int(24)?;
@@ -90,28 +407,96 @@ fn prepare_join_game(buf: &mut impl Write) -> io::Result<usize> {
     // current gamemode
     count += buf.write_mc_ubyte(num::ToPrimitive::to_u8(&GameMode::Survival).unwrap())?;
     count += buf.write_mc_byte(num::ToPrimitive::to_i8(&GameMode::Survival).unwrap())?;
-    count += buf.write_mc_varint(1)?; // world count
-    let world_id = McIdentifier {
-        namespace: Some("zipperland".to_owned()),
-        name: "steveland".to_owned(),
-    };
-    count += buf.write_mc_identifier(&world_id)?; // world names
-    count += buf.write_mc_nbt(&world::dimensions::DEFAULT_DIMENSION_CODEC)?;
-    f.write_mc_nbt(&world::dimensions::DEFAULT_DIMENSION_CODEC)?;
-    count += buf.write_mc_nbt(&world::dimensions::DEFAULT_DIMENSION_TYPE)?;
-    f.write_mc_nbt(&world::dimensions::DEFAULT_DIMENSION_TYPE)?;
-    count += buf.write_mc_identifier(&world_id)?; // world name
-    count += buf.write_mc_long(56717237888581)?; // hashed seed
+    count += write_world_names(buf, worlds)?;
+    let world_id = level.name();
+    let codec = registries.to_codec();
+    count += buf.write_mc_nbt(&codec)?;
+    count += buf.write_mc_nbt(&level.dimension().to_nbt())?;
+    count += buf.write_mc_identifier(world_id)?; // world name
+    count += buf.write_mc_long(level.hashed_seed())?;
     count += buf.write_mc_varint(32)?; // max players
-    count += buf.write_mc_varint(16)?; // view distance
+    count += buf.write_mc_varint(VIEW_DISTANCE)?; // view distance
     count += buf.write_mc_varint(16)?; // simulation distance
     count += buf.write_mc_bool(false)?; // reduced debug info
     count += buf.write_mc_bool(true)?; // respawn screen
-    count += buf.write_mc_bool(true)?; // debug world
-    count += buf.write_mc_bool(false)?; // superflat
+    count += buf.write_mc_bool(false)?; // debug world
+    count += buf.write_mc_bool(level.is_flat())?; // superflat
     Ok(count)
 }
 
+/// Starts streaming `level` to a player who just arrived in it, and puts
+/// them on the surface at its spawn. `changes` must be subscribed before
+/// any chunk is sent so none of their later changes are missed.
+async fn enter_level<S>(
+    view_distance: i32,
+    level: &Mutex<Level>,
+    changes: broadcast::Receiver<Arc<LevelChanges>>,
+    stream: &mut Pin<&mut S>,
+    protocol_version: i32,
+) -> io::Result<LevelView>
+where
+    S: AsyncWrite + Send,
+{
+    let mut view = LevelView {
+        tracker: ChunkTracker::new(view_distance, CHUNKS_PER_TICK),
+        changes,
+    };
+    let (x, z) = view.tracker.move_to(8.5, 8.5).unwrap();
+    let mut cur = buffer_cursor();
+    prepare_update_view_position(&mut cur, x, z)?;
+    let pid = PlayPacketOut::UpdateViewPosition.id(protocol_version);
+    send_packet_uncompressed(pid, stream, cur.get_ref()).await?;
+    stream_chunks(&mut view, level, stream, protocol_version).await?;
+
+    let y = level.lock().unwrap().chunk(0, 0).surface_y(8, 8) as f64;
+    let mut cur = buffer_cursor();
+    prepare_player_position(&mut cur, (8.5, y, 8.5), protocol_version)?;
+    let pid = PlayPacketOut::PlayerPositionAndLook.id(protocol_version);
+    send_packet_uncompressed(pid, stream, cur.get_ref()).await?;
+    Ok(view)
+}
+
+/// Brings a player who finished logging in, or configuring on newer
+/// clients, into `level`: Join Game, then the spawn chunks and position.
+async fn join_game<S>(
+    name: &str,
+    registries: &RegistrySet,
+    worlds: &LevelSet,
+    level: &Mutex<Level>,
+    view_distance: i32,
+    stream: &mut Pin<&mut S>,
+    protocol_version: i32,
+) -> io::Result<(Player, LevelView)>
+where
+    S: AsyncWrite + Send,
+{
+    let configured = protocol_version >= CONFIGURATION_PROTOCOL_VERSION;
+    let mut cur = buffer_cursor();
+    let world = {
+        let level = level.lock().unwrap();
+        if configured {
+            prepare_join_game_configured(&mut cur, registries, worlds, &level)?;
+        } else {
+            prepare_join_game(&mut cur, registries, worlds, &level)?;
+        }
+        level.name().clone()
+    };
+    let pid = PlayPacketOut::JoinGame.id(protocol_version);
+    send_packet_uncompressed(pid, stream, cur.get_ref()).await?;
+
+    if configured {
+        // Leaves the loading screen once the chunk it stands in arrives
+        let mut cur = buffer_cursor();
+        prepare_game_event(&mut cur, GAME_EVENT_WAIT_FOR_CHUNKS, 0.0)?;
+        let pid = PlayPacketOut::GameEvent.id(protocol_version);
+        send_packet_uncompressed(pid, stream, cur.get_ref()).await?;
+    }
+
+    let changes = worlds.subscribe(&world).unwrap();
+    let view = enter_level(view_distance, level, changes, stream, protocol_version).await?;
+    Ok((Player::new(name.to_owned(), world), view))
+}
+
 async fn send_packet_uncompressed<S>(
     pid: u8,
     stream: &mut Pin<&mut S>,
@@ -135,30 +520,53 @@ where
     Ok(())
 }
 
-async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
-    let stream_m = &mut stream;
-    let mut stream_m = Pin::new(stream_m);
-    // let socket = McSocket::new(stream_m, McNoCompression, McPassthrough);
-    let mut mode = SocketMode::Handshaking;
-
+async fn handle_client(
+    mut stream: TcpStream,
+    registries: Arc<RegistrySet>,
+    worlds: Arc<LevelSet>,
+) -> io::Result<()> {
     println!(
         "Received a stream {}",
-        stream_m.peer_addr().unwrap().ip().to_string()
+        stream.peer_addr().unwrap().ip().to_string()
     );
 
+    let (read_half, mut write_half) = stream.split();
+    let mut frames = FrameReader::new(read_half);
+    let mut stream_m = Pin::new(&mut write_half);
+    // let socket = McSocket::new(stream_m, McNoCompression, McPassthrough);
+    let mut mode = SocketMode::Handshaking;
+    let mut protocol_version = 0;
+    let mut level = worlds.first().clone();
+    let mut player_name = String::new();
+    let mut player: Option<Player> = None;
+    let mut view: Option<LevelView> = None;
+    let mut view_distance = VIEW_DISTANCE;
+    let mut ticker = tokio::time::interval(TICK);
+
     loop {
-        let length = stream_m.read_mc_varint().await?;
-        let packet_id_u8 = stream_m.read_mc_varint().await? as u8;
+        // Reading a frame can be cancelled without losing data
+        let packet = tokio::select! {
+            packet = frames.read_frame() => packet?,
+            _ = ticker.tick() => {
+                if let Some(view) = &mut view {
+                    stream_chunks(view, &level, &mut stream_m, protocol_version).await?;
+                }
+                continue;
+            }
+        };
+        let length = packet.len();
+        let mut reader = McSliceReader::new(&packet);
+        let packet_id_u8 = reader.read_mc_varint()? as u8;
         match mode {
             SocketMode::Handshaking => {
                 let packet_id = num::FromPrimitive::from_u8(packet_id_u8);
                 println!("Length {}, ID {}", length, packet_id_u8);
                 match packet_id {
                     Some(HandshakingPacket::Handshaking) => {
-                        let protocol_version = stream_m.read_mc_varint().await?;
-                        let addr = stream_m.read_mc_string().await?;
-                        let port = stream_m.read_mc_ushort().await?;
-                        let next_state = stream_m.read_mc_varint().await?;
+                        protocol_version = reader.read_mc_varint()?;
+                        let addr = reader.read_mc_str_max(McString::MAX_SERVER_ADDRESS_LENGTH)?;
+                        let port = reader.read_mc_ushort()?;
+                        let next_state = reader.read_mc_varint()?;
 
                         println!(
                             "Version {}, addr {}, port {}, next state {}",
@@ -175,11 +583,14 @@ async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
                 println!("Length {}, ID {}", length, packet_id_u8);
                 match packet_id {
                     Some(LoginPacket::LoginStart) => {
-                        let username = stream_m.read_mc_string().await;
+                        let username = reader.read_mc_str_max(McString::MAX_USERNAME_LENGTH);
                         match username {
-                            Ok(v) => println!("Username {}", v),
+                            Ok(v) => {
+                                println!("Username {}", v);
+                                player_name = v.to_owned();
+                            }
                             Err(e) => {
-                                println!("Username is invalid UTF-8: {:?}", e);
+                                println!("Username is invalid: {:?}", e);
                                 return Err(io::Error::new(
                                     io::ErrorKind::InvalidData,
                                     "Username is invalid",
@@ -187,54 +598,406 @@ async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
                             }
                         }
 
+                        if !can_join(protocol_version) {
+                            println!(
+                                "{} uses unsupported protocol {}",
+                                player_name, protocol_version
+                            );
+                            let mut cur = buffer_cursor();
+                            prepare_unsupported_version(&mut cur)?;
+                            let pid = LoginPacketOut::Disconnect as u8;
+                            send_packet_uncompressed(pid, &mut stream_m, cur.get_ref()).await?;
+                            return Ok(());
+                        }
+
                         // LOGIN
 
                         let mut cur = buffer_cursor();
                         if ENCRYPTION_ENABLED {
                             prepare_encryption_request(&mut cur).ok();
                         } else {
-                            prepare_login_success(&mut cur).ok();
+                            prepare_login_success(&mut cur, protocol_version).ok();
                         }
                         let cur = cur.get_ref();
                         let pid = LoginPacketOut::EncryptionRequest;
                         //send_packet_uncompressed(num::ToPrimitive::to_u8(&pid).unwrap(),
                         send_packet_uncompressed(0x02, &mut stream_m, cur).await?;
 
-                        // JOIN GAME
+                        // Newer clients continue with Login Acknowledged
+                        if protocol_version < CONFIGURATION_PROTOCOL_VERSION {
+                            mode = SocketMode::Play;
+                            let (joined, spawn) = join_game(
+                                &player_name,
+                                &registries,
+                                &worlds,
+                                &level,
+                                view_distance,
+                                &mut stream_m,
+                                protocol_version,
+                            )
+                            .await?;
+                            player = Some(joined);
+                            view = Some(spawn);
+                        }
+                    }
+                    Some(LoginPacket::LoginAcknowledged) => {
+                        mode = SocketMode::Configuration;
+
+                        let mut cur = buffer_cursor();
+                        prepare_feature_flags(&mut cur)?;
+                        let pid = ConfigurationPacketOut::FeatureFlags as u8;
+                        send_packet_uncompressed(pid, &mut stream_m, cur.get_ref()).await?;
 
+                        // We send every element, so there is no need to know any packs
                         let mut cur = buffer_cursor();
-                        prepare_join_game(&mut cur).ok();
-                        send_packet_uncompressed(0x26, &mut stream_m, cur.get_ref()).await?;
+                        configuration::prepare_known_packs(&mut cur, &[])?;
+                        let pid = ConfigurationPacketOut::KnownPacks as u8;
+                        send_packet_uncompressed(pid, &mut stream_m, cur.get_ref()).await?;
                     }
                     Some(LoginPacket::EncryptionResponse) => {}
                     Some(LoginPacket::LoginPluginResponse) => {}
                     None => println!("Unknown Login packet id {}", packet_id_u8),
                 }
             }
-            SocketMode::Play => {
-                println!("Play packet received");
+            SocketMode::Configuration => {
+                let packet_id = num::FromPrimitive::from_u8(packet_id_u8);
+                println!("Length {}, ID {}", length, packet_id_u8);
+                match packet_id {
+                    Some(ConfigurationPacket::ClientInformation) => {
+                        let _locale = reader.read_mc_str_max(16)?;
+                        view_distance = (reader.read_mc_byte()? as i32).clamp(2, VIEW_DISTANCE);
+                    }
+                    Some(ConfigurationPacket::KnownPacks) => {
+                        let packs = configuration::read_known_packs(&mut reader)?;
+                        println!("Client knows {} packs", packs.len());
+
+                        let pid = ConfigurationPacketOut::RegistryData as u8;
+                        for packet in configuration::prepare_registry_data_packets(&registries)? {
+                            send_packet_uncompressed(pid, &mut stream_m, &packet).await?;
+                        }
+
+                        let mut cur = buffer_cursor();
+                        configuration::prepare_update_tags(&mut cur, &registries)?;
+                        let pid = ConfigurationPacketOut::UpdateTags as u8;
+                        send_packet_uncompressed(pid, &mut stream_m, cur.get_ref()).await?;
+
+                        let pid = ConfigurationPacketOut::FinishConfiguration as u8;
+                        send_packet_uncompressed(pid, &mut stream_m, &[]).await?;
+                    }
+                    Some(ConfigurationPacket::AcknowledgeFinishConfiguration) => {
+                        mode = SocketMode::Play;
+
+                        let (joined, spawn) = join_game(
+                            &player_name,
+                            &registries,
+                            &worlds,
+                            &level,
+                            view_distance,
+                            &mut stream_m,
+                            protocol_version,
+                        )
+                        .await?;
+                        player = Some(joined);
+                        view = Some(spawn);
+                    }
+                    Some(_) => {}
+                    None => println!("Unknown Configuration packet id {}", packet_id_u8),
+                }
             }
+            SocketMode::Play => match PlayPacket::from_id(packet_id_u8, protocol_version) {
+                Some(PlayPacket::ClientSettings) => {
+                    let _locale = reader.read_mc_str_max(16)?;
+                    view_distance = (reader.read_mc_byte()? as i32).clamp(2, VIEW_DISTANCE);
+                    if let Some(view) = &mut view {
+                        view.tracker.set_view_distance(view_distance);
+                    }
+                }
+                Some(packet @ PlayPacket::ChatMessage) | Some(packet @ PlayPacket::ChatCommand) => {
+                    let message = reader.read_mc_str_max(McString::MAX_CHAT_LENGTH)?;
+                    // Newer clients send commands without the slash
+                    let prefix = match packet {
+                        PlayPacket::ChatCommand => "world ",
+                        _ => "/world ",
+                    };
+                    let name = match message.strip_prefix(prefix) {
+                        Some(name) => name.trim(),
+                        None => continue,
+                    };
+                    let (player, target) = match (&mut player, name.parse::<McIdentifier>()) {
+                        (Some(player), Ok(name)) => (player, name),
+                        _ => continue,
+                    };
+                    let next = match worlds.get(&target) {
+                        Some(next) if target != player.world => next.clone(),
+                        Some(_) => continue,
+                        None => {
+                            println!("{} asked for unknown world {}", player.name, target);
+                            continue;
+                        }
+                    };
+
+                    let kept = next.lock().unwrap().keep();
+                    player.change_world(target, kept);
+                    level = next;
+                    let mut cur = buffer_cursor();
+                    prepare_respawn(
+                        &mut cur,
+                        &registries,
+                        &level.lock().unwrap(),
+                        kept,
+                        protocol_version,
+                    )?;
+                    let pid = PlayPacketOut::Respawn.id(protocol_version);
+                    send_packet_uncompressed(pid, &mut stream_m, cur.get_ref()).await?;
+                    let changes = worlds.subscribe(&player.world).unwrap();
+                    view = Some(
+                        enter_level(
+                            view_distance,
+                            &level,
+                            changes,
+                            &mut stream_m,
+                            protocol_version,
+                        )
+                        .await?,
+                    );
+
+                    let mut cur = buffer_cursor();
+                    prepare_window_items(&mut cur, &player.inventory, protocol_version)?;
+                    let pid = PlayPacketOut::WindowItems.id(protocol_version);
+                    send_packet_uncompressed(pid, &mut stream_m, cur.get_ref()).await?;
+                    println!("{} moved to {}", player.name, player.world);
+                }
+                Some(PlayPacket::PlayerPosition) | Some(PlayPacket::PlayerPositionAndRotation) => {
+                    let x = reader.read_mc_double()?;
+                    let _y = reader.read_mc_double()?;
+                    let z = reader.read_mc_double()?;
+                    if let Some(center) = view.as_mut().and_then(|v| v.tracker.move_to(x, z)) {
+                        let mut cur = buffer_cursor();
+                        prepare_update_view_position(&mut cur, center.0, center.1)?;
+                        let pid = PlayPacketOut::UpdateViewPosition.id(protocol_version);
+                        send_packet_uncompressed(pid, &mut stream_m, cur.get_ref()).await?;
+                    }
+                }
+                Some(_) => {}
+                None => println!("Play packet received"),
+            },
         }
     }
 }
 
+/// Writes the level's changed chunks to its world directory. They are
+/// taken under the level's lock and written on a blocking thread without it.
+async fn save_level(level: &Arc<Mutex<Level>>) {
+    let (name, save) = {
+        let mut level = level.lock().unwrap();
+        (level.name().clone(), level.take_save())
+    };
+    let save = match save {
+        Some(save) if !save.is_empty() => save,
+        _ => return,
+    };
+    match tokio::task::spawn_blocking(move || save.write()).await {
+        Ok(Ok(count)) => println!("Saved {} chunks of {}", count, name),
+        Ok(Err(e)) => {
+            println!("Failed to save {}: {}", name, e.error);
+            level.lock().unwrap().mark_unsaved(e.unsaved);
+        }
+        Err(e) => println!("Failed to save {}: {}", name, e),
+    }
+}
+
 #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
 async fn main() -> std::io::Result<()> {
+    let config_file = std::env::args().nth(1);
+    let config_file = Path::new(config_file.as_deref().unwrap_or(DEFAULT_CONFIG_FILE));
+    let config = match config::load_config(config_file) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Invalid config:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    let sources = config.registries.sources().map_err(io::Error::from)?;
+    let registries = match RegistrySet::load(&sources) {
+        Ok(registries) => Arc::new(registries),
+        Err(e) => {
+            println!("Invalid registries:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    let block_registry = match config.registries.block_registry() {
+        Ok(block_registry) => block_registry,
+        Err(e) => {
+            println!("Invalid block report:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    let static_registries = match config.registries.static_registries() {
+        Ok(static_registries) => static_registries,
+        Err(e) => {
+            println!("Can't load the registries report:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Loaded {} dimension types, {} biomes, {} block states and {} items",
+        registries.dimension_types.len(),
+        registries.biomes.len(),
+        block_registry.state_count(),
+        static_registries.items.len()
+    );
+    match config.registries.block_registry_1_20_5() {
+        Some(Ok(client)) => {
+            let ids = ClientStateIds::matching(&block_registry, &client);
+            world::blocks::install_configured_state_ids(ids).ok();
+        }
+        Some(Err(e)) => {
+            println!("Invalid 1.20.5 block report:\n{}", e);
+            std::process::exit(1);
+        }
+        None => println!("1.20.5 clients can't join without blocks_report_1_20_5"),
+    }
+    match config.registries.static_registries_1_20_5() {
+        Some(Ok(client)) => {
+            world::ids::install_configured(client).ok();
+        }
+        Some(Err(e)) => {
+            println!("Invalid 1.20.5 registries report:\n{}", e);
+            std::process::exit(1);
+        }
+        None => println!("1.20.5 inventories look empty without registries_report_1_20_5"),
+    }
+    world::blocks::install(block_registry).ok();
+    world::ids::install(static_registries).ok();
+
+    let seed = config.world.seed();
+    println!("World seed {}", seed);
+    let behaviors = Arc::new(BlockBehaviors::vanilla());
+    let mut levels = Vec::new();
+    for level_config in config.world.levels() {
+        let dimension = match registries.dimension_types.get(&level_config.dimension_type) {
+            Some(entry) => &entry.element,
+            None => {
+                println!(
+                    "There is no {} dimension type for {}",
+                    level_config.dimension_type, level_config.name
+                );
+                std::process::exit(1);
+            }
+        };
+        let generator: Result<Arc<dyn ChunkGenerator>, String> = match level_config.generator {
+            GeneratorKind::Flat => level_config
+                .flat_preset
+                .parse()
+                .and_then(|settings: FlatSettings| {
+                    FlatGenerator::new(settings, dimension, &registries.biomes)
+                })
+                .map(|generator| Arc::new(generator) as Arc<dyn ChunkGenerator>)
+                .map_err(|e| format!("Invalid flat preset: {}", e)),
+            GeneratorKind::Noise => NoiseSettings::vanilla()
+                .map_err(Into::into)
+                .and_then(|settings| {
+                    NoiseGenerator::new(seed, settings, dimension, &registries.biomes)
+                })
+                .map(|generator| Arc::new(generator) as Arc<dyn ChunkGenerator>)
+                .map_err(|e| format!("Invalid noise generator: {}", e)),
+        };
+        let generator = match generator {
+            Ok(generator) => generator,
+            Err(e) => {
+                println!("{} of {}", e, level_config.name);
+                std::process::exit(1);
+            }
+        };
+
+        let mut level = Level::new(
+            level_config.name.clone(),
+            level_config.dimension_type.clone(),
+            registries.clone(),
+            hashed_seed(seed),
+            generator,
+        )
+        .unwrap()
+        .with_behaviors(behaviors.clone());
+        level.set_random_tick_speed(config.world.random_tick_speed);
+        level.set_keep(level_config.keep);
+        if let Some(dir) = &config.world.directory {
+            level = level.with_storage(RegionStorage::new(level_config.region_dir(dir)));
+        }
+        println!(
+            "Hosting {} ({})",
+            level_config.name, level_config.dimension_type
+        );
+        levels.push(level);
+    }
+    let worlds = match LevelSet::new(levels) {
+        Some(worlds) => Arc::new(worlds),
+        None => {
+            println!("Two worlds share a name");
+            std::process::exit(1);
+        }
+    };
+
+    for name in worlds.names() {
+        let worlds = worlds.clone();
+        let name = name.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(TICK);
+            loop {
+                ticker.tick().await;
+                worlds.tick(&name);
+            }
+        });
+    }
+
+    {
+        // One task saves, so saves are written in the order they are taken
+        let worlds = worlds.clone();
+        let autosave = config.world.autosave_interval > 0;
+        let period = Duration::from_secs(config.world.autosave_interval.max(1));
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            let shutdown = tokio::signal::ctrl_c();
+            tokio::pin!(shutdown);
+            let mut listening = true;
+            loop {
+                tokio::select! {
+                    _ = interval.tick(), if autosave => {
+                        for level in worlds.iter() {
+                            save_level(level).await;
+                        }
+                    }
+                    result = &mut shutdown, if listening => {
+                        if let Err(e) = result {
+                            println!("Not saving on Ctrl-C: {}", e);
+                            listening = false;
+                            continue;
+                        }
+                        println!("Shutting down");
+                        for level in worlds.iter() {
+                            save_level(level).await;
+                        }
+                        std::process::exit(0);
+                    }
+                    else => break,
+                }
+            }
+        });
+    }
+
     let listener = TcpListener::bind("127.0.0.1:25565").await?;
 
     println!("Continued");
 
     loop {
         let (socket, _) = listener.accept().await?;
-        handle_client(socket).await;
+        let registries = registries.clone();
+        let worlds = worlds.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(socket, registries, worlds).await {
+                println!("Connection closed: {}", e);
+            }
+        });
     }
-
-    // for stream in listener.incoming() {
-    //     let stream = stream.unwrap();
-    //     tokio::spawn(async move {
-    //         handle_client(stream);
-    //     });
-    // }
-
-    Ok(())
 }
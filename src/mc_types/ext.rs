@@ -1,3 +1,4 @@
+use super::nbt_io::*;
 use super::*;
 use async_trait::async_trait;
 use byteorder::ReadBytesExt;
@@ -42,6 +43,27 @@ pub trait McReadExt: io::Read {
         Ok(self.read_f64::<BE>()?)
     }
 
+    fn read_mc_angle(&mut self) -> io::Result<Angle>
+    where
+        Self: Sized,
+    {
+        Angle::read_from(self)
+    }
+
+    fn read_mc_velocity(&mut self) -> io::Result<Velocity>
+    where
+        Self: Sized,
+    {
+        Velocity::read_from(self)
+    }
+
+    fn read_mc_fixed_point(&mut self) -> io::Result<FixedPoint>
+    where
+        Self: Sized,
+    {
+        FixedPoint::read_from(self)
+    }
+
     fn read_mc_varint(&mut self) -> Result<i32, VarIntError>
     where
         Self: Sized,
@@ -56,12 +78,21 @@ pub trait McReadExt: io::Read {
         Ok(McString::read_from(self)?)
     }
 
+    /// Reads a string field with its own limit, such as
+    /// `McString::MAX_USERNAME_LENGTH`.
+    fn read_mc_string_max(&mut self, max_length: usize) -> Result<String, McStringError>
+    where
+        Self: Sized,
+    {
+        McString::read_from_limited(self, max_length)
+    }
+
     fn read_mc_identifier(&mut self) -> Result<McIdentifier, McStringError>
     where
         Self: Sized,
     {
         let string = self.read_mc_string()?;
-        McIdentifier::from_string(&string)
+        Ok(string.parse()?)
     }
 
     fn read_mc_uuid(&mut self) -> Result<McUUID, io::Error>
@@ -71,15 +102,22 @@ pub trait McReadExt: io::Read {
         Ok(McUUID::read_from(self)?)
     }
 
-    fn read_mc_nbt(&mut self) -> Result<nbt::Value, io::Error>
+    /// Reads a named root tag, discarding its name.
+    fn read_mc_nbt(&mut self) -> Result<nbt::Value, NbtError>
+    where
+        Self: Sized,
+    {
+        let (_, value) = read_nbt(self, NbtFormat::Named, &NbtLimits::default())?;
+        Ok(value)
+    }
+
+    /// Reads a nameless root tag, as sent by 1.20.2+ clients.
+    fn read_mc_network_nbt(&mut self) -> Result<nbt::Value, NbtError>
     where
         Self: Sized,
     {
-        let nbt = nbt::Value::from_reader(0x0a, self);
-        match nbt {
-            Ok(v) => Ok(v),
-            Err(e) => panic!("{}", e),
-        }
+        let (_, value) = read_nbt(self, NbtFormat::Network, &NbtLimits::default())?;
+        Ok(value)
     }
 }
 
@@ -131,6 +169,27 @@ pub trait McWriteExt: io::Write {
         Ok(8)
     }
 
+    fn write_mc_angle(&mut self, value: Angle) -> io::Result<usize>
+    where
+        Self: Sized,
+    {
+        Angle::write_to(self, value)
+    }
+
+    fn write_mc_velocity(&mut self, value: Velocity) -> io::Result<usize>
+    where
+        Self: Sized,
+    {
+        Velocity::write_to(self, value)
+    }
+
+    fn write_mc_fixed_point(&mut self, value: FixedPoint) -> io::Result<usize>
+    where
+        Self: Sized,
+    {
+        FixedPoint::write_to(self, value)
+    }
+
     fn write_mc_varint(&mut self, value: i32) -> io::Result<usize>
     where
         Self: Sized,
@@ -160,12 +219,20 @@ pub trait McWriteExt: io::Write {
         McUUID::write_to(self, value)
     }
 
+    /// Writes `value` as a root tag with an empty name.
     fn write_mc_nbt(&mut self, value: &nbt::Value) -> io::Result<usize>
     where
         Self: Sized,
     {
-        value.to_writer(self)?;
-        Ok(value.len_bytes())
+        Ok(write_nbt(self, "", value, NbtFormat::Named)?)
+    }
+
+    /// Writes `value` as a nameless root tag, as expected by 1.20.2+ clients.
+    fn write_mc_network_nbt(&mut self, value: &nbt::Value) -> io::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(write_nbt(self, "", value, NbtFormat::Network)?)
     }
 }
 
@@ -205,15 +272,25 @@ pub trait McAsyncReadExt: tokio::io::AsyncRead {
 
     async fn read_mc_float(self: &mut Pin<&mut Self>) -> io::Result<f32> {
         // No floating point in AsyncReadExt
-        let fake = self.read_i32().await?;
-        let f = unsafe { std::mem::transmute(fake) };
-        Ok(f)
+        let bits = self.read_u32().await?;
+        Ok(f32::from_bits(bits))
     }
 
     async fn read_mc_double(self: &mut Pin<&mut Self>) -> io::Result<f64> {
-        let fake = self.read_i64().await?;
-        let f = unsafe { std::mem::transmute(fake) };
-        Ok(f)
+        let bits = self.read_u64().await?;
+        Ok(f64::from_bits(bits))
+    }
+
+    async fn read_mc_angle(self: &mut Pin<&mut Self>) -> io::Result<Angle> {
+        Angle::read_from_async(self).await
+    }
+
+    async fn read_mc_velocity(self: &mut Pin<&mut Self>) -> io::Result<Velocity> {
+        Velocity::read_from_async(self).await
+    }
+
+    async fn read_mc_fixed_point(self: &mut Pin<&mut Self>) -> io::Result<FixedPoint> {
+        FixedPoint::read_from_async(self).await
     }
 
     async fn read_mc_varint(self: &mut Pin<&mut Self>) -> io::Result<i32> {
@@ -224,14 +301,41 @@ pub trait McAsyncReadExt: tokio::io::AsyncRead {
         Ok(McString::read_from_async(self).await?)
     }
 
+    /// Reads a string field with its own limit, such as
+    /// `McString::MAX_USERNAME_LENGTH`.
+    async fn read_mc_string_max(
+        self: &mut Pin<&mut Self>,
+        max_length: usize,
+    ) -> io::Result<String> {
+        Ok(McString::read_from_async_limited(self, max_length).await?)
+    }
+
     async fn read_mc_identifier(self: &mut Pin<&mut Self>) -> io::Result<McIdentifier> {
         let string = McString::read_from_async(self).await?;
-        Ok(McIdentifier::from_string(&string)?)
+        Ok(string.parse::<McIdentifier>()?)
     }
 
     async fn read_mc_uuid(self: &mut Pin<&mut Self>) -> io::Result<McUUID> {
         Ok(McUUID::read_from_async(self).await?)
     }
+
+    /// Reads a named root tag, discarding its name.
+    async fn read_mc_nbt(self: &mut Pin<&mut Self>) -> Result<nbt::Value, NbtError>
+    where
+        Self: Send,
+    {
+        let (_, value) = read_nbt_async(self, NbtFormat::Named, &NbtLimits::default()).await?;
+        Ok(value)
+    }
+
+    /// Reads a nameless root tag, as sent by 1.20.2+ clients.
+    async fn read_mc_network_nbt(self: &mut Pin<&mut Self>) -> Result<nbt::Value, NbtError>
+    where
+        Self: Send,
+    {
+        let (_, value) = read_nbt_async(self, NbtFormat::Network, &NbtLimits::default()).await?;
+        Ok(value)
+    }
 }
 
 impl<R: tokio::io::AsyncRead + ?Sized> McAsyncReadExt for R {}
@@ -268,13 +372,26 @@ pub trait McAsyncWriteExt: tokio::io::AsyncWrite {
     }
 
     async fn write_mc_float(self: &mut Pin<&mut Self>, value: f32) -> io::Result<()> {
-        let fake = unsafe { std::mem::transmute(value) };
-        self.write_i32(fake).await
+        self.write_u32(value.to_bits()).await
     }
 
     async fn write_mc_double(self: &mut Pin<&mut Self>, value: f64) -> io::Result<()> {
-        let fake = unsafe { std::mem::transmute(value) };
-        self.write_i64(fake).await
+        self.write_u64(value.to_bits()).await
+    }
+
+    async fn write_mc_angle(self: &mut Pin<&mut Self>, value: Angle) -> io::Result<usize> {
+        Angle::write_to_async(self, value).await
+    }
+
+    async fn write_mc_velocity(self: &mut Pin<&mut Self>, value: Velocity) -> io::Result<usize> {
+        Velocity::write_to_async(self, value).await
+    }
+
+    async fn write_mc_fixed_point(
+        self: &mut Pin<&mut Self>,
+        value: FixedPoint,
+    ) -> io::Result<usize> {
+        FixedPoint::write_to_async(self, value).await
     }
 
     async fn write_mc_varint(self: &mut Pin<&mut Self>, value: i32) -> io::Result<usize> {
@@ -296,6 +413,19 @@ pub trait McAsyncWriteExt: tokio::io::AsyncWrite {
     async fn write_mc_uuid(self: &mut Pin<&mut Self>, value: &McUUID) -> io::Result<usize> {
         McUUID::write_to_async(self, value).await
     }
+
+    /// Writes `value` as a root tag with an empty name.
+    async fn write_mc_nbt(self: &mut Pin<&mut Self>, value: &nbt::Value) -> io::Result<usize> {
+        Ok(write_nbt_async(self, "", value, NbtFormat::Named).await?)
+    }
+
+    /// Writes `value` as a nameless root tag, as expected by 1.20.2+ clients.
+    async fn write_mc_network_nbt(
+        self: &mut Pin<&mut Self>,
+        value: &nbt::Value,
+    ) -> io::Result<usize> {
+        Ok(write_nbt_async(self, "", value, NbtFormat::Network).await?)
+    }
 }
 
 impl<W: tokio::io::AsyncWrite + ?Sized> McAsyncWriteExt for W {}
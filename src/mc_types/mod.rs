@@ -1,13 +1,17 @@
 pub mod ext;
+pub mod nbt_io;
+pub mod slice;
+pub mod snbt;
 
 use byteorder::{BigEndian as BE, ReadBytesExt, WriteBytesExt};
 use num_traits::PrimInt;
 use std::{
     borrow::BorrowMut,
+    fmt,
     io::{self, Cursor, ErrorKind, Read, Write},
     ops::Add,
     pin::Pin,
-    str::{self, Utf8Error},
+    str::{self, FromStr, Utf8Error},
 };
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
@@ -109,6 +113,146 @@ impl MinecraftType<f64> for Double {
     }
 }
 
+/// A rotation in steps of 1/256 of a full turn, as sent in entity packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Angle(pub u8);
+
+impl Angle {
+    pub fn from_degrees(degrees: f32) -> Angle {
+        let steps = (degrees.rem_euclid(360.0) * 256.0 / 360.0).round() as u32;
+        Angle(steps as u8)
+    }
+
+    pub fn to_degrees(self) -> f32 {
+        self.0 as f32 * 360.0 / 256.0
+    }
+
+    pub fn from_radians(radians: f32) -> Angle {
+        Angle::from_degrees(radians.to_degrees())
+    }
+
+    pub fn to_radians(self) -> f32 {
+        self.to_degrees().to_radians()
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Angle> {
+        Ok(Angle(reader.read_u8()?))
+    }
+
+    pub fn write_to(writer: &mut impl Write, angle: Angle) -> io::Result<usize> {
+        writer.write_u8(angle.0)?;
+        Ok(1)
+    }
+
+    pub async fn read_from_async<R: AsyncRead + ?Sized>(
+        reader: &mut Pin<&mut R>,
+    ) -> io::Result<Angle> {
+        Ok(Angle(reader.read_u8().await?))
+    }
+
+    pub async fn write_to_async<W: AsyncWrite + ?Sized>(
+        writer: &mut Pin<&mut W>,
+        angle: Angle,
+    ) -> io::Result<usize> {
+        writer.write_u8(angle.0).await?;
+        Ok(1)
+    }
+}
+
+/// One axis of an entity's velocity, in 1/8000 of a block per tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Velocity(pub i16);
+
+impl Velocity {
+    pub const UNITS_PER_BLOCK: f64 = 8000.0;
+    /// The client clamps velocity to this many blocks per tick.
+    pub const MAX_BLOCKS_PER_TICK: f64 = 3.9;
+
+    pub fn from_blocks_per_tick(blocks: f64) -> Velocity {
+        let clamped = blocks.clamp(
+            -Velocity::MAX_BLOCKS_PER_TICK,
+            Velocity::MAX_BLOCKS_PER_TICK,
+        );
+        Velocity((clamped * Velocity::UNITS_PER_BLOCK).round() as i16)
+    }
+
+    pub fn to_blocks_per_tick(self) -> f64 {
+        self.0 as f64 / Velocity::UNITS_PER_BLOCK
+    }
+
+    /// Converts from blocks per second at the normal 20 ticks per second.
+    pub fn from_blocks_per_second(blocks: f64) -> Velocity {
+        Velocity::from_blocks_per_tick(blocks / 20.0)
+    }
+
+    pub fn to_blocks_per_second(self) -> f64 {
+        self.to_blocks_per_tick() * 20.0
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Velocity> {
+        Ok(Velocity(reader.read_i16::<BE>()?))
+    }
+
+    pub fn write_to(writer: &mut impl Write, velocity: Velocity) -> io::Result<usize> {
+        writer.write_i16::<BE>(velocity.0)?;
+        Ok(2)
+    }
+
+    pub async fn read_from_async<R: AsyncRead + ?Sized>(
+        reader: &mut Pin<&mut R>,
+    ) -> io::Result<Velocity> {
+        Ok(Velocity(reader.read_i16().await?))
+    }
+
+    pub async fn write_to_async<W: AsyncWrite + ?Sized>(
+        writer: &mut Pin<&mut W>,
+        velocity: Velocity,
+    ) -> io::Result<usize> {
+        writer.write_i16(velocity.0).await?;
+        Ok(2)
+    }
+}
+
+/// A position with 5 fractional bits, as used for absolute entity
+/// positions before 1.9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FixedPoint(pub i32);
+
+impl FixedPoint {
+    pub const FRACTION_BITS: u32 = 5;
+
+    pub fn from_f64(value: f64) -> FixedPoint {
+        FixedPoint((value * (1 << FixedPoint::FRACTION_BITS) as f64).floor() as i32)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / (1 << FixedPoint::FRACTION_BITS) as f64
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> io::Result<FixedPoint> {
+        Ok(FixedPoint(reader.read_i32::<BE>()?))
+    }
+
+    pub fn write_to(writer: &mut impl Write, value: FixedPoint) -> io::Result<usize> {
+        writer.write_i32::<BE>(value.0)?;
+        Ok(4)
+    }
+
+    pub async fn read_from_async<R: AsyncRead + ?Sized>(
+        reader: &mut Pin<&mut R>,
+    ) -> io::Result<FixedPoint> {
+        Ok(FixedPoint(reader.read_i32().await?))
+    }
+
+    pub async fn write_to_async<W: AsyncWrite + ?Sized>(
+        writer: &mut Pin<&mut W>,
+        value: FixedPoint,
+    ) -> io::Result<usize> {
+        writer.write_i32(value.0).await?;
+        Ok(4)
+    }
+}
+
 pub struct VarInt(pub i32);
 
 #[derive(Debug)]
@@ -236,6 +380,11 @@ pub enum McStringError {
     Io(io::Error),
     Utf8(Utf8Error),
     LengthMismatch,
+    NegativeLength(i32),
+    /// The string exceeds the field's limit in UTF-16 code units. For a
+    /// rejected length prefix, `actual` is the prefix in bytes.
+    TooLong { max: usize, actual: usize },
+    Identifier(McIdentifierError),
 }
 
 impl From<VarIntError> for McStringError {
@@ -250,6 +399,12 @@ impl From<io::Error> for McStringError {
     }
 }
 
+impl From<McIdentifierError> for McStringError {
+    fn from(e: McIdentifierError) -> Self {
+        McStringError::Identifier(e)
+    }
+}
+
 impl From<McStringError> for io::Error {
     fn from(e: McStringError) -> Self {
         match e {
@@ -259,21 +414,75 @@ impl From<McStringError> for io::Error {
             McStringError::LengthMismatch => {
                 io::Error::new(ErrorKind::InvalidData, "String length mismatch")
             }
+            McStringError::NegativeLength(len) => io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Negative string length {}", len),
+            ),
+            McStringError::TooLong { max, actual } => io::Error::new(
+                ErrorKind::InvalidData,
+                format!("String length {} exceeds limit {}", actual, max),
+            ),
+            McStringError::Identifier(e) => e.into(),
         }
     }
 }
 
 impl McString {
-    pub fn read_from(reader: &mut impl Read) -> Result<String, McStringError> {
-        let length = VarInt::read_from(reader)? as usize;
-        let mut buffer: Vec<u8> = vec![0; length];
-        reader.read_exact(&mut buffer)?;
+    /// Protocol maximum for a string field, in UTF-16 code units.
+    pub const MAX_LENGTH: usize = 32767;
+    pub const MAX_USERNAME_LENGTH: usize = 16;
+    pub const MAX_CHAT_LENGTH: usize = 256;
+    pub const MAX_SERVER_ADDRESS_LENGTH: usize = 255;
+
+    /// Checks the byte length prefix before anything is allocated. A UTF-16
+    /// code unit takes at most 3 bytes of UTF-8, which bounds the prefix.
+    fn check_prefix(length: i32, max_length: usize) -> Result<usize, McStringError> {
+        if length < 0 {
+            return Err(McStringError::NegativeLength(length));
+        }
+        let length = length as usize;
+        if length > max_length * 3 {
+            return Err(McStringError::TooLong {
+                max: max_length,
+                actual: length,
+            });
+        }
+        Ok(length)
+    }
 
-        let s = match str::from_utf8(&buffer) {
+    fn check_units(s: &str, max_length: usize) -> Result<(), McStringError> {
+        let units = s.encode_utf16().count();
+        if units > max_length {
+            return Err(McStringError::TooLong {
+                max: max_length,
+                actual: units,
+            });
+        }
+        Ok(())
+    }
+
+    fn decode(buffer: Vec<u8>, max_length: usize) -> Result<String, McStringError> {
+        let s = match String::from_utf8(buffer) {
             Ok(v) => v,
-            Err(u8err) => return Err(McStringError::Utf8(u8err)),
+            Err(u8err) => return Err(McStringError::Utf8(u8err.utf8_error())),
         };
-        Ok(s.to_owned())
+        McString::check_units(&s, max_length)?;
+        Ok(s)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<String, McStringError> {
+        McString::read_from_limited(reader, McString::MAX_LENGTH)
+    }
+
+    /// Reads a string of at most `max_length` UTF-16 code units.
+    pub fn read_from_limited(
+        reader: &mut impl Read,
+        max_length: usize,
+    ) -> Result<String, McStringError> {
+        let length = McString::check_prefix(VarInt::read_from(reader)?, max_length)?;
+        let mut buffer: Vec<u8> = vec![0; length];
+        reader.read_exact(&mut buffer)?;
+        McString::decode(buffer, max_length)
     }
 
     pub fn write_to(writer: &mut impl Write, string: &str) -> io::Result<usize> {
@@ -287,7 +496,16 @@ impl McString {
     pub async fn read_from_async<R: AsyncRead + ?Sized>(
         reader: &mut Pin<&mut R>,
     ) -> Result<String, McStringError> {
-        let length = VarInt::read_from_async(reader).await? as usize;
+        McString::read_from_async_limited(reader, McString::MAX_LENGTH).await
+    }
+
+    /// Reads a string of at most `max_length` UTF-16 code units.
+    pub async fn read_from_async_limited<R: AsyncRead + ?Sized>(
+        reader: &mut Pin<&mut R>,
+        max_length: usize,
+    ) -> Result<String, McStringError> {
+        let length = VarInt::read_from_async(reader).await?;
+        let length = McString::check_prefix(length, max_length)?;
         let mut buffer: Vec<u8> = vec![0; length];
         let actual_len = reader.read_exact(buffer.as_mut()).await?;
 
@@ -295,11 +513,7 @@ impl McString {
             return Err(McStringError::LengthMismatch);
         }
 
-        let s = match str::from_utf8(&buffer) {
-            Ok(v) => v,
-            Err(u8err) => return Err(McStringError::Utf8(u8err)),
-        };
-        Ok(s.to_owned())
+        McString::decode(buffer, max_length)
     }
 
     pub async fn write_to_async<W: AsyncWrite + ?Sized>(
@@ -314,45 +528,232 @@ impl McString {
     }
 }
 
+/// A namespaced resource location such as `minecraft:overworld`.
+///
+/// Identifiers are validated on construction, so any value of this type is
+/// safe to send to the client or to use as a registry key. An identifier
+/// without a namespace is normalized to the `minecraft` namespace, so
+/// `stone` and `minecraft:stone` compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct McIdentifier {
-    pub namespace: Option<String>,
-    pub name: String,
+    namespace: String,
+    name: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum McIdentifierError {
+    EmptyName,
+    /// More than one `:` separator.
+    TooManySeparators,
+    InvalidNamespaceChar(char),
+    InvalidNameChar(char),
+}
+
+impl fmt::Display for McIdentifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            McIdentifierError::EmptyName => write!(f, "identifier name is empty"),
+            McIdentifierError::TooManySeparators => {
+                write!(f, "identifier has more than one ':' separator")
+            }
+            McIdentifierError::InvalidNamespaceChar(c) => {
+                write!(f, "invalid character {:?} in identifier namespace", c)
+            }
+            McIdentifierError::InvalidNameChar(c) => {
+                write!(f, "invalid character {:?} in identifier name", c)
+            }
+        }
+    }
+}
+
+impl std::error::Error for McIdentifierError {}
+
+impl From<McIdentifierError> for io::Error {
+    fn from(e: McIdentifierError) -> Self {
+        io::Error::new(ErrorKind::InvalidData, e.to_string())
+    }
 }
 
 impl McIdentifier {
-    fn from_string(string: &str) -> Result<McIdentifier, McStringError> {
-        let mut split = string.split(':');
-        let first = split.next().ok_or(McStringError::LengthMismatch)?;
-        let second = split.next();
+    pub const DEFAULT_NAMESPACE: &'static str = "minecraft";
+
+    fn valid_namespace_char(c: char) -> bool {
+        matches!(c, 'a'..='z' | '0'..='9' | '_' | '.' | '-')
+    }
+
+    fn valid_name_char(c: char) -> bool {
+        McIdentifier::valid_namespace_char(c) || c == '/'
+    }
+
+    pub fn new(namespace: &str, name: &str) -> Result<McIdentifier, McIdentifierError> {
+        if let Some(c) = namespace
+            .chars()
+            .find(|&c| !McIdentifier::valid_namespace_char(c))
+        {
+            return Err(McIdentifierError::InvalidNamespaceChar(c));
+        }
+        if name.is_empty() {
+            return Err(McIdentifierError::EmptyName);
+        }
+        if let Some(c) = name.chars().find(|&c| !McIdentifier::valid_name_char(c)) {
+            return Err(McIdentifierError::InvalidNameChar(c));
+        }
+        let namespace = if namespace.is_empty() {
+            McIdentifier::DEFAULT_NAMESPACE
+        } else {
+            namespace
+        };
         Ok(McIdentifier {
-            namespace: if second.is_some() {
-                Some(first.to_owned())
-            } else {
-                None
-            },
-            name: second.unwrap_or(first).to_owned(),
+            namespace: namespace.to_owned(),
+            name: name.to_owned(),
         })
     }
 
-    fn to_string(&self) -> String {
-        let mut s = String::new();
-        if self.namespace.is_some() {
-            s.push_str(self.namespace.as_ref().unwrap().as_str());
-        } else {
-            s.push_str("minecraft");
+    /// An identifier in the `minecraft` namespace.
+    pub fn minecraft(name: &str) -> Result<McIdentifier, McIdentifierError> {
+        McIdentifier::new(McIdentifier::DEFAULT_NAMESPACE, name)
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for McIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.name)
+    }
+}
+
+impl FromStr for McIdentifier {
+    type Err = McIdentifierError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split(':');
+        let first = split.next().unwrap_or("");
+        match (split.next(), split.next()) {
+            (None, _) => McIdentifier::minecraft(first),
+            (Some(second), None) => McIdentifier::new(first, second),
+            (Some(_), Some(_)) => Err(McIdentifierError::TooManySeparators),
         }
-        s.push(':');
-        s.push_str(&self.name);
-        s
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct McUUID {
     pub most: u64,
     pub least: u64,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum McUUIDError {
+    /// The string is neither 32 hex digits nor the hyphenated 8-4-4-4-12 form.
+    BadLength(usize),
+    /// A hyphen is missing or misplaced in the hyphenated form.
+    MisplacedHyphen,
+    InvalidDigit(char),
+    /// The NBT value is not an IntArray of length 4.
+    NotIntArray,
+}
+
+impl fmt::Display for McUUIDError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            McUUIDError::BadLength(len) => write!(f, "invalid UUID length {}", len),
+            McUUIDError::MisplacedHyphen => write!(f, "invalid UUID group layout"),
+            McUUIDError::InvalidDigit(c) => write!(f, "invalid UUID digit {:?}", c),
+            McUUIDError::NotIntArray => write!(f, "UUID NBT must be an IntArray of length 4"),
+        }
+    }
+}
+
+impl std::error::Error for McUUIDError {}
+
+impl From<McUUIDError> for io::Error {
+    fn from(e: McUUIDError) -> Self {
+        io::Error::new(ErrorKind::InvalidData, e.to_string())
+    }
+}
+
 impl McUUID {
+    pub fn from_bytes(bytes: [u8; 16]) -> McUUID {
+        let n = u128::from_be_bytes(bytes);
+        McUUID {
+            most: (n >> 64) as u64,
+            least: n as u64,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 16] {
+        (((self.most as u128) << 64) | self.least as u128).to_be_bytes()
+    }
+
+    /// Stamps the RFC 4122 version nibble and variant bits onto raw bytes.
+    fn with_version(mut bytes: [u8; 16], version: u8) -> McUUID {
+        bytes[6] = (bytes[6] & 0x0f) | (version << 4);
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        McUUID::from_bytes(bytes)
+    }
+
+    /// A random (version 4) UUID.
+    pub fn new_v4() -> McUUID {
+        McUUID::with_version(rand::random(), 4)
+    }
+
+    /// A name-based (version 3, MD5) UUID, as produced by Java's
+    /// `UUID.nameUUIDFromBytes`.
+    pub fn new_v3(name: &[u8]) -> McUUID {
+        McUUID::with_version(md5::compute(name).0, 3)
+    }
+
+    /// The UUID an offline-mode server assigns to `username`.
+    pub fn offline_player(username: &str) -> McUUID {
+        McUUID::new_v3(format!("OfflinePlayer:{}", username).as_bytes())
+    }
+
+    pub fn version(self) -> u8 {
+        ((self.most >> 12) & 0xf) as u8
+    }
+
+    /// The 32 hex digit form without hyphens, as used by the Mojang API.
+    pub fn to_simple_string(self) -> String {
+        format!("{:016x}{:016x}", self.most, self.least)
+    }
+
+    /// The four big-endian ints used for UUIDs in player and entity NBT.
+    pub fn to_int_array(self) -> [i32; 4] {
+        [
+            (self.most >> 32) as i32,
+            self.most as i32,
+            (self.least >> 32) as i32,
+            self.least as i32,
+        ]
+    }
+
+    pub fn from_int_array(ints: [i32; 4]) -> McUUID {
+        McUUID {
+            most: ((ints[0] as u32 as u64) << 32) | ints[1] as u32 as u64,
+            least: ((ints[2] as u32 as u64) << 32) | ints[3] as u32 as u64,
+        }
+    }
+
+    pub fn to_nbt(self) -> nbt::Value {
+        nbt::Value::IntArray(self.to_int_array().to_vec())
+    }
+
+    pub fn from_nbt(value: &nbt::Value) -> Result<McUUID, McUUIDError> {
+        match value {
+            nbt::Value::IntArray(ints) if ints.len() == 4 => {
+                Ok(McUUID::from_int_array([ints[0], ints[1], ints[2], ints[3]]))
+            }
+            _ => Err(McUUIDError::NotIntArray),
+        }
+    }
+
     pub fn read_from(reader: &mut impl Read) -> io::Result<McUUID> {
         let most = reader.read_u64::<BE>()?;
         let least = reader.read_u64::<BE>()?;
@@ -379,8 +780,181 @@ impl McUUID {
         writer: &mut Pin<&mut W>,
         uuid: &McUUID,
     ) -> io::Result<usize> {
-        writer.write_u64(uuid.most).await.ok();
-        writer.write_u64(uuid.least).await.ok();
+        writer.write_u64(uuid.most).await?;
+        writer.write_u64(uuid.least).await?;
         Ok(16)
     }
 }
+
+impl fmt::Display for McUUID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            self.most >> 32,
+            (self.most >> 16) & 0xffff,
+            self.most & 0xffff,
+            self.least >> 48,
+            self.least & 0xffff_ffff_ffff
+        )
+    }
+}
+
+impl FromStr for McUUID {
+    type Err = McUUIDError;
+
+    /// Accepts both the hyphenated and the compact 32 digit form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits: String = match s.len() {
+            32 => s.to_owned(),
+            36 => {
+                for (i, c) in s.char_indices() {
+                    let hyphen = matches!(i, 8 | 13 | 18 | 23);
+                    if hyphen != (c == '-') {
+                        return Err(McUUIDError::MisplacedHyphen);
+                    }
+                }
+                s.chars().filter(|&c| c != '-').collect()
+            }
+            len => return Err(McUUIDError::BadLength(len)),
+        };
+
+        let mut n: u128 = 0;
+        for c in digits.chars() {
+            let digit = c.to_digit(16).ok_or(McUUIDError::InvalidDigit(c))?;
+            n = (n << 4) | digit as u128;
+        }
+        Ok(McUUID::from_bytes(n.to_be_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_angle_conversions() {
+        assert_eq!(Angle::from_degrees(0.0), Angle(0));
+        assert_eq!(Angle::from_degrees(90.0), Angle(64));
+        assert_eq!(Angle::from_degrees(-90.0), Angle(192));
+        assert_eq!(Angle::from_degrees(360.0), Angle(0));
+        assert_eq!(Angle::from_degrees(359.9), Angle(0));
+        assert_eq!(Angle(128).to_degrees(), 180.0);
+
+        let mut buf = Vec::new();
+        Angle::write_to(&mut buf, Angle(200)).unwrap();
+        assert_eq!(
+            Angle::read_from(&mut Cursor::new(&buf)).unwrap(),
+            Angle(200)
+        );
+    }
+
+    #[test]
+    fn test_velocity_and_fixed_point() {
+        assert_eq!(Velocity::from_blocks_per_tick(0.5), Velocity(4000));
+        assert_eq!(Velocity::from_blocks_per_tick(-10.0), Velocity(-31200));
+        assert_eq!(Velocity(-8000).to_blocks_per_tick(), -1.0);
+        assert_eq!(Velocity::from_blocks_per_second(20.0), Velocity(8000));
+
+        assert_eq!(FixedPoint::from_f64(1.5), FixedPoint(48));
+        assert_eq!(FixedPoint::from_f64(-0.01), FixedPoint(-1));
+        assert_eq!(FixedPoint(-16).to_f64(), -0.5);
+    }
+
+    #[test]
+    fn test_string_limits() {
+        let mut buf = Vec::new();
+        McString::write_to(&mut buf, "Shifty").unwrap();
+        let read = McString::read_from_limited(&mut Cursor::new(&buf), 16).unwrap();
+        assert_eq!(read, "Shifty");
+        assert!(matches!(
+            McString::read_from_limited(&mut Cursor::new(&buf), 5),
+            Err(McStringError::TooLong { max: 5, actual: 6 })
+        ));
+
+        // Multi-byte characters count as UTF-16 code units, not bytes.
+        let mut buf = Vec::new();
+        McString::write_to(&mut buf, "ääää").unwrap();
+        assert!(McString::read_from_limited(&mut Cursor::new(&buf), 4).is_ok());
+
+        // A huge prefix is rejected before the body is allocated or read.
+        let mut buf = Vec::new();
+        VarInt::write_to(&mut buf, i32::MAX).unwrap();
+        assert!(matches!(
+            McString::read_from(&mut Cursor::new(&buf)),
+            Err(McStringError::TooLong { .. })
+        ));
+        let mut buf = Vec::new();
+        VarInt::write_to(&mut buf, -1).unwrap();
+        assert!(matches!(
+            McString::read_from(&mut Cursor::new(&buf)),
+            Err(McStringError::NegativeLength(-1))
+        ));
+    }
+
+    #[test]
+    fn test_identifier_parse() {
+        let id: McIdentifier = "zipperland:steveland".parse().unwrap();
+        assert_eq!(id.namespace(), "zipperland");
+        assert_eq!(id.name(), "steveland");
+        assert_eq!(id.to_string(), "zipperland:steveland");
+
+        let stone: McIdentifier = "stone".parse().unwrap();
+        assert_eq!(stone, "minecraft:stone".parse().unwrap());
+        assert_eq!(
+            "worldgen/biome".parse::<McIdentifier>().unwrap().to_string(),
+            "minecraft:worldgen/biome"
+        );
+
+        assert_eq!(
+            "a:b:c".parse::<McIdentifier>(),
+            Err(McIdentifierError::TooManySeparators)
+        );
+        assert_eq!("a:".parse::<McIdentifier>(), Err(McIdentifierError::EmptyName));
+        assert_eq!(
+            "Zipper:x".parse::<McIdentifier>(),
+            Err(McIdentifierError::InvalidNamespaceChar('Z'))
+        );
+        assert_eq!(
+            "a/b:x".parse::<McIdentifier>(),
+            Err(McIdentifierError::InvalidNamespaceChar('/'))
+        );
+        assert_eq!(
+            "a:b c".parse::<McIdentifier>(),
+            Err(McIdentifierError::InvalidNameChar(' '))
+        );
+    }
+
+    #[test]
+    fn test_uuid_string_roundtrip() {
+        let text = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        let uuid: McUUID = text.parse().unwrap();
+        assert_eq!(uuid.to_string(), text);
+        assert_eq!(uuid.to_simple_string(), "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!("069a79f444e94726a5befca90e38aaf5".parse(), Ok(uuid));
+        assert_eq!(
+            "069a79f4-44e94-726-a5be-fca90e38aaf5".parse::<McUUID>(),
+            Err(McUUIDError::MisplacedHyphen)
+        );
+        assert_eq!(
+            "069a79f444e94726a5befca90e38aafg".parse::<McUUID>(),
+            Err(McUUIDError::InvalidDigit('g'))
+        );
+    }
+
+    #[test]
+    fn test_uuid_offline_player() {
+        let uuid = McUUID::offline_player("Notch");
+        assert_eq!(uuid.to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        assert_eq!(uuid.version(), 3);
+        assert_eq!(McUUID::new_v4().version(), 4);
+    }
+
+    #[test]
+    fn test_uuid_int_array() {
+        let uuid: McUUID = "b50ad385-829d-3141-a216-7e7d7539ba7f".parse().unwrap();
+        let ints = uuid.to_int_array();
+        assert_eq!(ints[0], 0xb50ad385u32 as i32);
+        assert_eq!(McUUID::from_nbt(&uuid.to_nbt()), Ok(uuid));
+    }
+}
@@ -0,0 +1,475 @@
+//! Bounded NBT reading and writing for the network and for files.
+//!
+//! `nbt::Value::from_reader` trusts its input completely: a list header can
+//! ask for two billion elements and compounds can nest until the stack
+//! overflows. The readers here enforce `NbtLimits` and report malformed
+//! data as an `NbtError` instead.
+
+use super::*;
+use std::{future::Future, io::ErrorKind};
+
+const TAG_END: u8 = 0x00;
+const TAG_BYTE: u8 = 0x01;
+const TAG_SHORT: u8 = 0x02;
+const TAG_INT: u8 = 0x03;
+const TAG_LONG: u8 = 0x04;
+const TAG_FLOAT: u8 = 0x05;
+const TAG_DOUBLE: u8 = 0x06;
+const TAG_BYTE_ARRAY: u8 = 0x07;
+const TAG_STRING: u8 = 0x08;
+const TAG_LIST: u8 = 0x09;
+const TAG_COMPOUND: u8 = 0x0a;
+const TAG_INT_ARRAY: u8 = 0x0b;
+const TAG_LONG_ARRAY: u8 = 0x0c;
+
+/// How the root tag is framed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NbtFormat {
+    /// Tag id, name and payload, as in files and in packets before 1.20.2.
+    Named,
+    /// Tag id and payload without a name, as in packets since 1.20.2.
+    Network,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NbtLimits {
+    /// Deepest nesting of lists and compounds below the root.
+    pub max_depth: usize,
+    /// Most elements in a single list or array.
+    pub max_list_length: usize,
+}
+
+impl Default for NbtLimits {
+    /// Vanilla's depth limit, and a length limit that keeps a single array
+    /// within the largest possible packet.
+    fn default() -> Self {
+        NbtLimits {
+            max_depth: 512,
+            max_list_length: 2097152,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NbtError {
+    Io(io::Error),
+    InvalidTypeId(u8),
+    /// The root tag is `TAG_End`.
+    InvalidRoot(u8),
+    TooDeep(usize),
+    ListTooLong(i32),
+    HeterogeneousList,
+    /// A string is not valid modified UTF-8.
+    InvalidString,
+}
+
+impl fmt::Display for NbtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NbtError::Io(e) => write!(f, "NBT IO error: {}", e),
+            NbtError::InvalidTypeId(id) => write!(f, "invalid NBT tag id {}", id),
+            NbtError::InvalidRoot(id) => write!(f, "invalid NBT root tag id {}", id),
+            NbtError::TooDeep(depth) => write!(f, "NBT nested deeper than {}", depth),
+            NbtError::ListTooLong(len) => write!(f, "NBT list length {} out of range", len),
+            NbtError::HeterogeneousList => write!(f, "NBT list elements differ in type"),
+            NbtError::InvalidString => write!(f, "NBT string is not modified UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for NbtError {}
+
+impl From<io::Error> for NbtError {
+    fn from(e: io::Error) -> Self {
+        NbtError::Io(e)
+    }
+}
+
+impl From<nbt::Error> for NbtError {
+    fn from(e: nbt::Error) -> Self {
+        match e {
+            nbt::Error::IoError(e) => NbtError::Io(e),
+            nbt::Error::HeterogeneousList => NbtError::HeterogeneousList,
+            nbt::Error::InvalidTypeId(id) => NbtError::InvalidTypeId(id),
+            e => NbtError::Io(io::Error::new(ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+}
+
+impl From<NbtError> for io::Error {
+    fn from(e: NbtError) -> Self {
+        match e {
+            NbtError::Io(e) => e,
+            e => io::Error::new(ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+/// Most array elements allocated ahead of reading them. Larger arrays grow
+/// as their data arrives, so a header alone can't claim megabytes.
+const ARRAY_CHUNK: usize = 4096;
+
+fn check_length(len: i32, limits: &NbtLimits) -> Result<usize, NbtError> {
+    if len < 0 || len as usize > limits.max_list_length {
+        return Err(NbtError::ListTooLong(len));
+    }
+    Ok(len as usize)
+}
+
+fn decode_string(bytes: Vec<u8>) -> Result<String, NbtError> {
+    match cesu8::from_java_cesu8(&bytes) {
+        Ok(s) => Ok(s.into_owned()),
+        Err(_) => Err(NbtError::InvalidString),
+    }
+}
+
+/// Reads `len` elements with `read_into`, a chunk at a time.
+fn read_array<R: Read, T: Copy + Default>(
+    reader: &mut R,
+    len: usize,
+    mut read_into: impl FnMut(&mut R, &mut [T]) -> io::Result<()>,
+) -> Result<Vec<T>, NbtError> {
+    let mut values = Vec::new();
+    while values.len() < len {
+        let start = values.len();
+        values.resize(start + (len - start).min(ARRAY_CHUNK), T::default());
+        read_into(reader, &mut values[start..])?;
+    }
+    Ok(values)
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, NbtError> {
+    let len = reader.read_u16::<BE>()? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    decode_string(bytes)
+}
+
+fn read_payload(
+    reader: &mut impl Read,
+    id: u8,
+    depth: usize,
+    limits: &NbtLimits,
+) -> Result<nbt::Value, NbtError> {
+    if depth > limits.max_depth {
+        return Err(NbtError::TooDeep(limits.max_depth));
+    }
+    Ok(match id {
+        TAG_BYTE => nbt::Value::Byte(reader.read_i8()?),
+        TAG_SHORT => nbt::Value::Short(reader.read_i16::<BE>()?),
+        TAG_INT => nbt::Value::Int(reader.read_i32::<BE>()?),
+        TAG_LONG => nbt::Value::Long(reader.read_i64::<BE>()?),
+        TAG_FLOAT => nbt::Value::Float(reader.read_f32::<BE>()?),
+        TAG_DOUBLE => nbt::Value::Double(reader.read_f64::<BE>()?),
+        TAG_BYTE_ARRAY => {
+            let len = check_length(reader.read_i32::<BE>()?, limits)?;
+            nbt::Value::ByteArray(read_array(reader, len, |r, buf| r.read_i8_into(buf))?)
+        }
+        TAG_STRING => nbt::Value::String(read_string(reader)?),
+        TAG_LIST => {
+            let element_id = reader.read_u8()?;
+            let len = check_length(reader.read_i32::<BE>()?, limits)?;
+            let mut list = Vec::new();
+            for _ in 0..len {
+                list.push(read_payload(reader, element_id, depth + 1, limits)?);
+            }
+            nbt::Value::List(list)
+        }
+        TAG_COMPOUND => {
+            let mut map = nbt::Map::new();
+            loop {
+                let id = reader.read_u8()?;
+                if id == TAG_END {
+                    break;
+                }
+                let name = read_string(reader)?;
+                map.insert(name, read_payload(reader, id, depth + 1, limits)?);
+            }
+            nbt::Value::Compound(map)
+        }
+        TAG_INT_ARRAY => {
+            let len = check_length(reader.read_i32::<BE>()?, limits)?;
+            nbt::Value::IntArray(read_array(reader, len, |r, buf| {
+                r.read_i32_into::<BE>(buf)
+            })?)
+        }
+        TAG_LONG_ARRAY => {
+            let len = check_length(reader.read_i32::<BE>()?, limits)?;
+            nbt::Value::LongArray(read_array(reader, len, |r, buf| {
+                r.read_i64_into::<BE>(buf)
+            })?)
+        }
+        id => return Err(NbtError::InvalidTypeId(id)),
+    })
+}
+
+/// Reads a root tag, returning its name (empty in the network format).
+pub fn read_nbt(
+    reader: &mut impl Read,
+    format: NbtFormat,
+    limits: &NbtLimits,
+) -> Result<(String, nbt::Value), NbtError> {
+    let id = reader.read_u8()?;
+    if id == TAG_END {
+        return Err(NbtError::InvalidRoot(id));
+    }
+    let name = match format {
+        NbtFormat::Named => read_string(reader)?,
+        NbtFormat::Network => String::new(),
+    };
+    Ok((name, read_payload(reader, id, 0, limits)?))
+}
+
+async fn read_string_async<R: AsyncRead + ?Sized>(
+    reader: &mut Pin<&mut R>,
+) -> Result<String, NbtError> {
+    let len = reader.read_u16().await? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes).await?;
+    decode_string(bytes)
+}
+
+type NbtFuture<'a> = Pin<Box<dyn Future<Output = Result<nbt::Value, NbtError>> + Send + 'a>>;
+
+fn read_payload_async<'a, 'b, R: AsyncRead + Send + ?Sized>(
+    reader: &'a mut Pin<&'b mut R>,
+    id: u8,
+    depth: usize,
+    limits: &'a NbtLimits,
+) -> NbtFuture<'a>
+where
+    'b: 'a,
+{
+    Box::pin(async move {
+        if depth > limits.max_depth {
+            return Err(NbtError::TooDeep(limits.max_depth));
+        }
+        Ok(match id {
+            TAG_BYTE => nbt::Value::Byte(reader.read_i8().await?),
+            TAG_SHORT => nbt::Value::Short(reader.read_i16().await?),
+            TAG_INT => nbt::Value::Int(reader.read_i32().await?),
+            TAG_LONG => nbt::Value::Long(reader.read_i64().await?),
+            TAG_FLOAT => nbt::Value::Float(f32::from_bits(reader.read_u32().await?)),
+            TAG_DOUBLE => nbt::Value::Double(f64::from_bits(reader.read_u64().await?)),
+            TAG_BYTE_ARRAY => {
+                let len = check_length(reader.read_i32().await?, limits)?;
+                let mut bytes = Vec::new();
+                while bytes.len() < len {
+                    let start = bytes.len();
+                    bytes.resize(start + (len - start).min(ARRAY_CHUNK), 0u8);
+                    reader.read_exact(&mut bytes[start..]).await?;
+                }
+                nbt::Value::ByteArray(bytes.into_iter().map(|b| b as i8).collect())
+            }
+            TAG_STRING => nbt::Value::String(read_string_async(reader).await?),
+            TAG_LIST => {
+                let element_id = reader.read_u8().await?;
+                let len = check_length(reader.read_i32().await?, limits)?;
+                let mut list = Vec::new();
+                for _ in 0..len {
+                    list.push(read_payload_async(reader, element_id, depth + 1, limits).await?);
+                }
+                nbt::Value::List(list)
+            }
+            TAG_COMPOUND => {
+                let mut map = nbt::Map::new();
+                loop {
+                    let id = reader.read_u8().await?;
+                    if id == TAG_END {
+                        break;
+                    }
+                    let name = read_string_async(reader).await?;
+                    let value = read_payload_async(reader, id, depth + 1, limits).await?;
+                    map.insert(name, value);
+                }
+                nbt::Value::Compound(map)
+            }
+            TAG_INT_ARRAY => {
+                let len = check_length(reader.read_i32().await?, limits)?;
+                let mut ints = Vec::new();
+                for _ in 0..len {
+                    ints.push(reader.read_i32().await?);
+                }
+                nbt::Value::IntArray(ints)
+            }
+            TAG_LONG_ARRAY => {
+                let len = check_length(reader.read_i32().await?, limits)?;
+                let mut longs = Vec::new();
+                for _ in 0..len {
+                    longs.push(reader.read_i64().await?);
+                }
+                nbt::Value::LongArray(longs)
+            }
+            id => return Err(NbtError::InvalidTypeId(id)),
+        })
+    })
+}
+
+/// Reads a root tag, returning its name (empty in the network format).
+pub async fn read_nbt_async<R: AsyncRead + Send + ?Sized>(
+    reader: &mut Pin<&mut R>,
+    format: NbtFormat,
+    limits: &NbtLimits,
+) -> Result<(String, nbt::Value), NbtError> {
+    let id = reader.read_u8().await?;
+    if id == TAG_END {
+        return Err(NbtError::InvalidRoot(id));
+    }
+    let name = match format {
+        NbtFormat::Named => read_string_async(reader).await?,
+        NbtFormat::Network => String::new(),
+    };
+    Ok((name, read_payload_async(reader, id, 0, limits).await?))
+}
+
+/// Counts bytes on their way to the inner writer, so the length of a
+/// written tree is known without walking it a second time.
+struct CountingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    count: usize,
+}
+
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes a root tag and returns the number of bytes written.
+pub fn write_nbt(
+    writer: &mut impl Write,
+    name: &str,
+    value: &nbt::Value,
+    format: NbtFormat,
+) -> Result<usize, NbtError> {
+    let mut writer = CountingWriter {
+        inner: writer,
+        count: 0,
+    };
+    writer.write_u8(value.id())?;
+    if format == NbtFormat::Named {
+        let name = cesu8::to_java_cesu8(name);
+        writer.write_u16::<BE>(name.len() as u16)?;
+        writer.write_all(&name)?;
+    }
+    value.to_writer(&mut writer)?;
+    Ok(writer.count)
+}
+
+/// Writes a root tag and returns the number of bytes written.
+pub async fn write_nbt_async<W: AsyncWrite + ?Sized>(
+    writer: &mut Pin<&mut W>,
+    name: &str,
+    value: &nbt::Value,
+    format: NbtFormat,
+) -> Result<usize, NbtError> {
+    let mut buf = Vec::new();
+    write_nbt(&mut buf, name, value, format)?;
+    writer.write_all(&buf).await?;
+    Ok(buf.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    fn sample() -> nbt::Value {
+        let mut inner = nbt::Map::new();
+        inner.insert("min_y".to_owned(), nbt::Value::Int(-64));
+        inner.insert(
+            "name".to_owned(),
+            nbt::Value::String("\u{1F600}".to_owned()),
+        );
+        inner.insert("heights".to_owned(), nbt::Value::LongArray(vec![1, 2, 3]));
+        let mut root = nbt::Map::new();
+        root.insert(
+            "value".to_owned(),
+            nbt::Value::List(vec![nbt::Value::Compound(inner)]),
+        );
+        root.insert("scale".to_owned(), nbt::Value::Float(8.0));
+        nbt::Value::Compound(root)
+    }
+
+    #[test]
+    fn test_nbt_roundtrip() {
+        let value = sample();
+        for &format in &[NbtFormat::Named, NbtFormat::Network] {
+            let mut buf = Vec::new();
+            let count = write_nbt(&mut buf, "", &value, format).unwrap();
+            assert_eq!(count, buf.len());
+            let (name, read) = read_nbt(&mut &buf[..], format, &NbtLimits::default()).unwrap();
+            assert_eq!(name, "");
+            assert_eq!(read, value);
+
+            let read = task::block_on(async {
+                let mut reader = &buf[..];
+                read_nbt_async(&mut Pin::new(&mut reader), format, &NbtLimits::default()).await
+            });
+            assert_eq!(read.unwrap().1, value);
+        }
+    }
+
+    #[test]
+    fn test_nbt_malformed() {
+        let limits = NbtLimits::default();
+        // Unknown tag id inside a compound
+        let buf = [TAG_COMPOUND, 0, 0, 0x0d, 0, 0];
+        assert!(matches!(
+            read_nbt(&mut &buf[..], NbtFormat::Named, &limits),
+            Err(NbtError::InvalidTypeId(0x0d))
+        ));
+        // Truncated input
+        let buf = [TAG_COMPOUND, 0, 0, TAG_INT, 0, 1, b'a', 0];
+        assert!(matches!(
+            read_nbt(&mut &buf[..], NbtFormat::Named, &limits),
+            Err(NbtError::Io(_))
+        ));
+        // A list header claiming more elements than allowed
+        let buf = [TAG_LIST, TAG_LONG, 0x7f, 0xff, 0xff, 0xff];
+        assert!(matches!(
+            read_nbt(&mut &buf[..], NbtFormat::Network, &limits),
+            Err(NbtError::ListTooLong(i32::MAX))
+        ));
+        // Arrays within the limit but longer than the input run out of data
+        // instead of allocating the whole claimed length
+        for &id in &[TAG_BYTE_ARRAY, TAG_INT_ARRAY, TAG_LONG_ARRAY] {
+            let buf = [id, 0x00, 0x20, 0x00, 0x00, 1, 2, 3, 4];
+            assert!(matches!(
+                read_nbt(&mut &buf[..], NbtFormat::Network, &limits),
+                Err(NbtError::Io(_))
+            ));
+            let read = task::block_on(async {
+                let mut reader = &buf[..];
+                read_nbt_async(&mut Pin::new(&mut reader), NbtFormat::Network, &limits).await
+            });
+            assert!(matches!(read, Err(NbtError::Io(_))));
+        }
+    }
+
+    #[test]
+    fn test_nbt_depth_limit() {
+        let mut value = nbt::Value::Compound(nbt::Map::new());
+        for _ in 0..10 {
+            value = nbt::Value::List(vec![value]);
+        }
+        let mut buf = Vec::new();
+        write_nbt(&mut buf, "", &value, NbtFormat::Network).unwrap();
+
+        let limits = NbtLimits {
+            max_depth: 5,
+            ..NbtLimits::default()
+        };
+        assert!(matches!(
+            read_nbt(&mut &buf[..], NbtFormat::Network, &limits),
+            Err(NbtError::TooDeep(5))
+        ));
+        assert!(read_nbt(&mut &buf[..], NbtFormat::Network, &NbtLimits::default()).is_ok());
+    }
+}
@@ -0,0 +1,111 @@
+//! Synchronous, borrowing decode of a buffered packet frame.
+//!
+//! A frame is read off the socket in one go (see `socket::frame`) and its
+//! fields are then decoded from memory. Numeric fields go through
+//! `McReadExt`, while strings and byte arrays are returned as slices of the
+//! frame instead of being copied.
+
+use super::*;
+
+pub struct McSliceReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> McSliceReader<'a> {
+    pub fn new(buf: &'a [u8]) -> McSliceReader<'a> {
+        McSliceReader { buf }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn unexpected_eof() -> io::Error {
+        io::Error::new(ErrorKind::UnexpectedEof, "Frame ended early")
+    }
+
+    /// Borrows the next `len` bytes of the frame.
+    pub fn read_mc_bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.buf.len() {
+            return Err(McSliceReader::unexpected_eof());
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    /// Borrows a VarInt length prefixed byte array.
+    pub fn read_mc_byte_array(&mut self) -> io::Result<&'a [u8]> {
+        let len = VarInt::read_from(self)?;
+        if len < 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Negative byte array length",
+            ));
+        }
+        self.read_mc_bytes(len as usize)
+    }
+
+    /// Borrows everything left in the frame, for trailing byte arrays.
+    pub fn read_mc_remaining(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+
+    pub fn read_mc_str(&mut self) -> Result<&'a str, McStringError> {
+        self.read_mc_str_max(McString::MAX_LENGTH)
+    }
+
+    /// Borrows a string of at most `max_length` UTF-16 code units.
+    pub fn read_mc_str_max(&mut self, max_length: usize) -> Result<&'a str, McStringError> {
+        let length = McString::check_prefix(VarInt::read_from(self)?, max_length)?;
+        let bytes = self
+            .read_mc_bytes(length)
+            .map_err(|_| McStringError::LengthMismatch)?;
+        let s = str::from_utf8(bytes).map_err(McStringError::Utf8)?;
+        McString::check_units(s, max_length)?;
+        Ok(s)
+    }
+}
+
+impl<'a> Read for McSliceReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Read::read(&mut self.buf, buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        Read::read_exact(&mut self.buf, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc_types::ext::{McReadExt, McWriteExt};
+
+    #[test]
+    fn test_borrowed_fields() {
+        let mut frame = Vec::new();
+        frame.write_mc_varint(0x03).unwrap();
+        frame.write_mc_string("hello there").unwrap();
+        frame.write_mc_long(1234).unwrap();
+        frame.write_mc_varint(3).unwrap();
+        frame.extend_from_slice(&[7, 8, 9]);
+        frame.extend_from_slice(&[1, 2]);
+
+        let mut reader = McSliceReader::new(&frame);
+        assert_eq!(reader.read_mc_varint().unwrap(), 0x03);
+        let text = reader.read_mc_str_max(McString::MAX_CHAT_LENGTH).unwrap();
+        assert_eq!(text, "hello there");
+        // The string points into the frame rather than a copy.
+        assert!(frame.as_ptr_range().contains(&text.as_ptr()));
+        assert_eq!(reader.read_mc_long().unwrap(), 1234);
+        assert_eq!(reader.read_mc_byte_array().unwrap(), &[7, 8, 9]);
+        assert_eq!(reader.read_mc_remaining(), &[1, 2]);
+        assert!(reader.is_empty());
+        assert!(reader.read_mc_bytes(1).is_err());
+    }
+}
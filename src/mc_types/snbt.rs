@@ -0,0 +1,525 @@
+//! Stringified NBT, the text form of NBT used by commands such as `/give`
+//! and `/data`, e.g. `{piglin_safe:1b,min_y:0,effects:"minecraft:overworld"}`.
+//!
+//! `parse` reads SNBT into an `nbt::Value` and `Snbt` prints one back. The
+//! printer sorts compound keys so that output is stable, and `{:#}` gives
+//! an indented multi-line form for reading large values like the codec.
+//!
+//! Since commands pass player input here, compounds and lists may only nest
+//! as deep as in binary NBT. SNBT has no literal for infinite or NaN floats,
+//! so values holding one can't be printed.
+
+use std::fmt;
+
+use super::nbt_io::NbtLimits;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnbtError {
+    /// Byte offset into the input where parsing failed.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SnbtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for SnbtError {}
+
+/// Parses a single SNBT value. Anything but whitespace after it is an error.
+pub fn parse(input: &str) -> Result<nbt::Value, SnbtError> {
+    let mut parser = Parser {
+        input,
+        pos: 0,
+        depth: 0,
+        max_depth: NbtLimits::default().max_depth,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < input.len() {
+        return Err(parser.error("Trailing data after value"));
+    }
+    Ok(value)
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    matches!(c, '0'..='9' | 'A'..='Z' | 'a'..='z' | '_' | '-' | '.' | '+')
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    /// Compounds and lists open around the current position.
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> SnbtError {
+        SnbtError {
+            position: self.pos,
+            message: message.to_owned(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Skips whitespace and consumes `expected` if it is next.
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SnbtError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", expected)))
+        }
+    }
+
+    /// Parses a compound or list one level deeper, with the same limit as
+    /// `NbtLimits::max_depth` below the root.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<nbt::Value, SnbtError>,
+    ) -> Result<nbt::Value, SnbtError> {
+        if self.depth > self.max_depth {
+            return Err(self.error(&format!("Nested deeper than {}", self.max_depth)));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn value(&mut self) -> Result<nbt::Value, SnbtError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Parser::compound),
+            Some('[') => self.nested(Parser::list_or_array),
+            Some('"') | Some('\'') => Ok(nbt::Value::String(self.quoted_string()?)),
+            Some(_) => {
+                let start = self.pos;
+                let token = self.unquoted_string();
+                if token.is_empty() {
+                    self.pos = start;
+                    return Err(self.error("Expected value"));
+                }
+                Ok(primitive(token))
+            }
+            None => Err(self.error("Expected value")),
+        }
+    }
+
+    fn unquoted_string(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !is_unquoted_char(c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        &self.input[start..self.pos]
+    }
+
+    fn quoted_string(&mut self) -> Result<String, SnbtError> {
+        let quote = self.peek().unwrap();
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("Unterminated string")),
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c) if c == quote || c == '\\' => {
+                            s.push(c);
+                            self.pos += 1;
+                        }
+                        _ => return Err(self.error("Invalid escape sequence")),
+                    }
+                }
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    if c == quote {
+                        return Ok(s);
+                    }
+                    s.push(c);
+                }
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, SnbtError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') | Some('\'') => self.quoted_string(),
+            _ => {
+                let key = self.unquoted_string();
+                if key.is_empty() {
+                    return Err(self.error("Expected key"));
+                }
+                Ok(key.to_owned())
+            }
+        }
+    }
+
+    fn compound(&mut self) -> Result<nbt::Value, SnbtError> {
+        self.expect('{')?;
+        let mut map = nbt::Map::new();
+        if self.eat('}') {
+            return Ok(nbt::Value::Compound(map));
+        }
+        loop {
+            let key = self.key()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            map.insert(key, value);
+            if self.eat(',') {
+                continue;
+            }
+            self.expect('}')?;
+            return Ok(nbt::Value::Compound(map));
+        }
+    }
+
+    /// Comma separated values up to and including the closing bracket.
+    fn elements(&mut self) -> Result<Vec<nbt::Value>, SnbtError> {
+        let mut elements = Vec::new();
+        if self.eat(']') {
+            return Ok(elements);
+        }
+        loop {
+            let start = self.pos;
+            let value = self.value()?;
+            if let Some(first) = elements.first() {
+                if first.id() != value.id() {
+                    self.pos = start;
+                    return Err(self.error("List elements must all have the same type"));
+                }
+            }
+            elements.push(value);
+            if self.eat(',') {
+                continue;
+            }
+            self.expect(']')?;
+            return Ok(elements);
+        }
+    }
+
+    fn list_or_array(&mut self) -> Result<nbt::Value, SnbtError> {
+        self.expect('[')?;
+        let rest = &self.input[self.pos..];
+        let array_type = match (rest.chars().next(), rest.chars().nth(1)) {
+            (Some(t @ 'B'), Some(';'))
+            | (Some(t @ 'I'), Some(';'))
+            | (Some(t @ 'L'), Some(';')) => Some(t),
+            _ => None,
+        };
+        let array_type = match array_type {
+            Some(t) => t,
+            None => return Ok(nbt::Value::List(self.elements()?)),
+        };
+        self.pos += 2;
+        let start = self.pos;
+        let elements = self.elements()?;
+        let mismatch = SnbtError {
+            position: start,
+            message: format!("Invalid element in {} array", array_type),
+        };
+        match array_type {
+            'B' => elements
+                .into_iter()
+                .map(|v| match v {
+                    nbt::Value::Byte(b) => Ok(b),
+                    _ => Err(mismatch.clone()),
+                })
+                .collect::<Result<_, _>>()
+                .map(nbt::Value::ByteArray),
+            'I' => elements
+                .into_iter()
+                .map(|v| match v {
+                    nbt::Value::Int(i) => Ok(i),
+                    _ => Err(mismatch.clone()),
+                })
+                .collect::<Result<_, _>>()
+                .map(nbt::Value::IntArray),
+            _ => elements
+                .into_iter()
+                .map(|v| match v {
+                    nbt::Value::Long(l) => Ok(l),
+                    _ => Err(mismatch.clone()),
+                })
+                .collect::<Result<_, _>>()
+                .map(nbt::Value::LongArray),
+        }
+    }
+}
+
+fn is_decimal(s: &str) -> bool {
+    let s = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let mantissa = s.split(['e', 'E']).next().unwrap_or("");
+    !mantissa.is_empty()
+        && mantissa != "."
+        && mantissa.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && mantissa.matches('.').count() <= 1
+}
+
+/// Interprets an unquoted token as a number or boolean where possible, and
+/// as a string otherwise, like vanilla does.
+fn primitive(token: &str) -> nbt::Value {
+    match token {
+        "true" => return nbt::Value::Byte(1),
+        "false" => return nbt::Value::Byte(0),
+        _ => {}
+    }
+    let (body, suffix) = match token.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() && i > 0 => (&token[..i], Some(c)),
+        _ => (token, None),
+    };
+    let value = match suffix.map(|c| c.to_ascii_lowercase()) {
+        Some('b') => body.parse().ok().map(nbt::Value::Byte),
+        Some('s') => body.parse().ok().map(nbt::Value::Short),
+        Some('l') => body.parse().ok().map(nbt::Value::Long),
+        Some('f') if is_decimal(body) => body.parse().ok().map(nbt::Value::Float),
+        Some('d') if is_decimal(body) => body.parse().ok().map(nbt::Value::Double),
+        None if body.contains('.') && is_decimal(body) => body.parse().ok().map(nbt::Value::Double),
+        None => body.parse().ok().map(nbt::Value::Int),
+        _ => None,
+    };
+    value.unwrap_or_else(|| nbt::Value::String(token.to_owned()))
+}
+
+/// Formats an `nbt::Value` as SNBT. The alternate flag (`{:#}`) indents
+/// compounds and lists over multiple lines. Formatting fails if the value
+/// holds an infinite or NaN float.
+pub struct Snbt<'a>(pub &'a nbt::Value);
+
+impl<'a> fmt::Display for Snbt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self.0, if f.alternate() { Some(0) } else { None })
+    }
+}
+
+/// Formats `value` as compact SNBT, or `None` if it holds an infinite or
+/// NaN float.
+pub fn to_snbt(value: &nbt::Value) -> Option<String> {
+    let mut s = String::new();
+    fmt::write(&mut s, format_args!("{}", Snbt(value))).ok()?;
+    Some(s)
+}
+
+/// Writes `value` followed by `suffix`, which only round-trips for finite
+/// floats.
+fn write_float(f: &mut impl fmt::Write, value: f64, debug: &str, suffix: char) -> fmt::Result {
+    if !value.is_finite() {
+        return Err(fmt::Error);
+    }
+    write!(f, "{}{}", debug, suffix)
+}
+
+fn write_string(f: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    let quote = if s.contains('"') && !s.contains('\'') {
+        '\''
+    } else {
+        '"'
+    };
+    f.write_char(quote)?;
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char(quote)
+}
+
+fn write_key(f: &mut impl fmt::Write, key: &str) -> fmt::Result {
+    if !key.is_empty() && key.chars().all(is_unquoted_char) {
+        f.write_str(key)
+    } else {
+        write_string(f, key)
+    }
+}
+
+fn write_separator(f: &mut impl fmt::Write, indent: Option<usize>, first: bool) -> fmt::Result {
+    if !first {
+        f.write_char(',')?;
+    }
+    if let Some(level) = indent {
+        f.write_char('\n')?;
+        for _ in 0..level {
+            f.write_str("    ")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_close(f: &mut impl fmt::Write, indent: Option<usize>, close: char) -> fmt::Result {
+    if let Some(level) = indent {
+        write_separator(f, Some(level), true)?;
+    }
+    f.write_char(close)
+}
+
+fn write_array<T: fmt::Display>(
+    f: &mut impl fmt::Write,
+    prefix: char,
+    values: &[T],
+    suffix: &str,
+) -> fmt::Result {
+    write!(f, "[{};", prefix)?;
+    for (i, v) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write!(f, "{}{}", v, suffix)?;
+    }
+    f.write_char(']')
+}
+
+fn write_value(f: &mut impl fmt::Write, value: &nbt::Value, indent: Option<usize>) -> fmt::Result {
+    let inner = indent.map(|level| level + 1);
+    match value {
+        nbt::Value::Byte(v) => write!(f, "{}b", v),
+        nbt::Value::Short(v) => write!(f, "{}s", v),
+        nbt::Value::Int(v) => write!(f, "{}", v),
+        nbt::Value::Long(v) => write!(f, "{}L", v),
+        nbt::Value::Float(v) => write_float(f, *v as f64, &format!("{:?}", v), 'f'),
+        nbt::Value::Double(v) => write_float(f, *v, &format!("{:?}", v), 'd'),
+        nbt::Value::String(s) => write_string(f, s),
+        nbt::Value::ByteArray(values) => write_array(f, 'B', values, "b"),
+        nbt::Value::IntArray(values) => write_array(f, 'I', values, ""),
+        nbt::Value::LongArray(values) => write_array(f, 'L', values, "L"),
+        nbt::Value::List(values) => {
+            if values.is_empty() {
+                return f.write_str("[]");
+            }
+            f.write_char('[')?;
+            for (i, v) in values.iter().enumerate() {
+                write_separator(f, inner, i == 0)?;
+                write_value(f, v, inner)?;
+            }
+            write_close(f, indent, ']')
+        }
+        nbt::Value::Compound(map) => {
+            if map.is_empty() {
+                return f.write_str("{}");
+            }
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            f.write_char('{')?;
+            for (i, key) in keys.into_iter().enumerate() {
+                write_separator(f, inner, i == 0)?;
+                write_key(f, key)?;
+                f.write_str(if indent.is_some() { ": " } else { ":" })?;
+                write_value(f, &map[key], inner)?;
+            }
+            write_close(f, indent, '}')
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nbt::Value;
+
+    #[test]
+    fn test_parse_primitives() {
+        assert_eq!(parse("1b").unwrap(), Value::Byte(1));
+        assert_eq!(parse("-3s").unwrap(), Value::Short(-3));
+        assert_eq!(parse("42").unwrap(), Value::Int(42));
+        assert_eq!(parse("42L").unwrap(), Value::Long(42));
+        assert_eq!(parse("0.5f").unwrap(), Value::Float(0.5));
+        assert_eq!(parse("8.0").unwrap(), Value::Double(8.0));
+        assert_eq!(parse("1e3d").unwrap(), Value::Double(1000.0));
+        assert_eq!(parse("true").unwrap(), Value::Byte(1));
+        assert_eq!(parse("300b").unwrap(), Value::String("300b".to_owned()));
+        assert_eq!(parse("rain").unwrap(), Value::String("rain".to_owned()));
+        assert_eq!(
+            parse(r#"'say "hi"\\'"#).unwrap(),
+            Value::String(r#"say "hi"\"#.to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_compound() {
+        let value = parse(
+            r#"{piglin_safe:1b, min_y: 0, effects:"minecraft:overworld",
+                heights:[L;1L,2L], biomes:[{name:plains}], "odd key":[]}"#,
+        )
+        .unwrap();
+        let map = match value {
+            Value::Compound(map) => map,
+            _ => panic!("not a compound"),
+        };
+        assert_eq!(map["piglin_safe"], Value::Byte(1));
+        assert_eq!(map["min_y"], Value::Int(0));
+        assert_eq!(
+            map["effects"],
+            Value::String("minecraft:overworld".to_owned())
+        );
+        assert_eq!(map["heights"], Value::LongArray(vec![1, 2]));
+        assert_eq!(map["odd key"], Value::List(vec![]));
+        assert!(matches!(&map["biomes"], Value::List(l) if l.len() == 1));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("{a:1").unwrap_err().position, 4);
+        assert_eq!(parse("[1,2b]").unwrap_err().position, 3);
+        assert!(parse("[I;1,2b]").is_err());
+        assert!(parse("{a:1} x").is_err());
+        assert!(parse("\"open").is_err());
+    }
+
+    #[test]
+    fn test_parse_depth() {
+        // The root and 512 levels below it, as in binary NBT
+        let deepest = format!("{}{}", "[".repeat(513), "]".repeat(513));
+        assert!(parse(&deepest).is_ok());
+        let too_deep = format!("{}{}", "[".repeat(514), "]".repeat(514));
+        let error = parse(&too_deep).unwrap_err();
+        assert_eq!(error.message, "Nested deeper than 512");
+        assert_eq!(error.position, 513);
+        // Far deeper input fails the same way instead of overflowing the stack
+        assert!(parse(&"[{a:".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn test_print_roundtrip() {
+        let text = r#"{a:[B;1b,-2b],b:[I;3],c:"it's",d:[{e:1.5d,f:0.25f}],"g h":7L,i:-1s}"#;
+        let value = parse(text).unwrap();
+        assert_eq!(to_snbt(&value).unwrap(), text);
+
+        let pretty = format!("{:#}", Snbt(&value));
+        assert!(pretty.starts_with("{\n    a: [B;1b,-2b],\n"));
+        assert_eq!(parse(&pretty).unwrap(), value);
+
+        assert_eq!(to_snbt(&Value::Float(f32::INFINITY)), None);
+        let mut map = nbt::Map::new();
+        map.insert("x".to_owned(), Value::Double(f64::NAN));
+        assert_eq!(to_snbt(&Value::List(vec![Value::Compound(map)])), None);
+        assert_eq!(to_snbt(&Value::Double(-0.0)).unwrap(), "-0.0d");
+    }
+}
@@ -1,10 +1,29 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SocketMode {
     Handshaking,
     Status,
     Login,
+    /// Between Login Acknowledged and Acknowledge Finish Configuration, only
+    /// for clients at `CONFIGURATION_PROTOCOL_VERSION` or above.
+    Configuration,
     Play,
 }
 
+/// The first protocol version (1.20.5) whose Configuration packets are
+/// implemented. The older supported client, 1.18, goes from Login straight
+/// to Play.
+pub const CONFIGURATION_PROTOCOL_VERSION: i32 = 766;
+
+/// The protocol versions that can join, with the game versions they are
+/// shown as: 1.18 and 1.20.5. Others are disconnected at Login Start.
+pub const SUPPORTED_PROTOCOL_VERSIONS: [(i32, &str); 2] = [(757, "1.18"), (766, "1.20.5")];
+
+pub fn is_supported_protocol(protocol_version: i32) -> bool {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .any(|&(version, _)| version == protocol_version)
+}
+
 #[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 #[repr(u8)]
 pub enum HandshakingPacket {
@@ -17,6 +36,7 @@ pub enum LoginPacket {
     LoginStart = 0x00,
     EncryptionResponse = 0x01,
     LoginPluginResponse = 0x02,
+    LoginAcknowledged = 0x03,
 }
 
 #[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
@@ -27,6 +47,144 @@ pub enum LoginPacketOut {
     LoginSuccess = 0x02,
 }
 
+#[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum ConfigurationPacket {
+    ClientInformation = 0x00,
+    CookieResponse = 0x01,
+    PluginMessage = 0x02,
+    AcknowledgeFinishConfiguration = 0x03,
+    KeepAlive = 0x04,
+    Pong = 0x05,
+    ResourcePackResponse = 0x06,
+    KnownPacks = 0x07,
+}
+
+#[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum ConfigurationPacketOut {
+    CookieRequest = 0x00,
+    PluginMessage = 0x01,
+    Disconnect = 0x02,
+    FinishConfiguration = 0x03,
+    KeepAlive = 0x04,
+    Ping = 0x05,
+    ResetChat = 0x06,
+    RegistryData = 0x07,
+    RemoveResourcePack = 0x08,
+    AddResourcePack = 0x09,
+    StoreCookie = 0x0a,
+    Transfer = 0x0b,
+    FeatureFlags = 0x0c,
+    UpdateTags = 0x0d,
+    KnownPacks = 0x0e,
+}
+
+/// Serverbound Play packets of protocol 766 (1.20.5). Those of older
+/// clients are translated by `from_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum PlayPacket {
+    TeleportConfirm = 0x00,
+    /// A chat message that starts with `/`, without the slash.
+    ChatCommand = 0x04,
+    ChatMessage = 0x06,
+    ClientSettings = 0x0a,
+    KeepAlive = 0x18,
+    PlayerPosition = 0x1a,
+    PlayerPositionAndRotation = 0x1b,
+    PlayerRotation = 0x1c,
+    PlayerMovement = 0x1d,
+}
+
+impl PlayPacket {
+    /// The packet with ID `id` in `protocol_version`.
+    pub fn from_id(id: u8, protocol_version: i32) -> Option<PlayPacket> {
+        if protocol_version >= CONFIGURATION_PROTOCOL_VERSION {
+            return num::FromPrimitive::from_u8(id);
+        }
+        Some(match num::FromPrimitive::from_u8(id)? {
+            PlayPacket757::TeleportConfirm => PlayPacket::TeleportConfirm,
+            PlayPacket757::ChatMessage => PlayPacket::ChatMessage,
+            PlayPacket757::ClientSettings => PlayPacket::ClientSettings,
+            PlayPacket757::KeepAlive => PlayPacket::KeepAlive,
+            PlayPacket757::PlayerPosition => PlayPacket::PlayerPosition,
+            PlayPacket757::PlayerPositionAndRotation => PlayPacket::PlayerPositionAndRotation,
+            PlayPacket757::PlayerRotation => PlayPacket::PlayerRotation,
+            PlayPacket757::PlayerMovement => PlayPacket::PlayerMovement,
+        })
+    }
+}
+
+/// Clientbound Play packets of protocol 766 (1.20.5). Older clients get
+/// the IDs from `id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum PlayPacketOut {
+    BlockChange = 0x09,
+    WindowItems = 0x13,
+    UnloadChunk = 0x21,
+    GameEvent = 0x22,
+    ChunkDataAndUpdateLight = 0x27,
+    UpdateLight = 0x2a,
+    JoinGame = 0x2b,
+    PlayerPositionAndLook = 0x40,
+    Respawn = 0x47,
+    UpdateViewPosition = 0x54,
+}
+
+impl PlayPacketOut {
+    /// The ID of the packet in `protocol_version`.
+    pub fn id(self, protocol_version: i32) -> u8 {
+        if protocol_version >= CONFIGURATION_PROTOCOL_VERSION {
+            return self as u8;
+        }
+        let packet = match self {
+            PlayPacketOut::BlockChange => PlayPacketOut757::BlockChange,
+            PlayPacketOut::WindowItems => PlayPacketOut757::WindowItems,
+            PlayPacketOut::UnloadChunk => PlayPacketOut757::UnloadChunk,
+            PlayPacketOut::GameEvent => PlayPacketOut757::ChangeGameState,
+            PlayPacketOut::ChunkDataAndUpdateLight => PlayPacketOut757::ChunkDataAndUpdateLight,
+            PlayPacketOut::UpdateLight => PlayPacketOut757::UpdateLight,
+            PlayPacketOut::JoinGame => PlayPacketOut757::JoinGame,
+            PlayPacketOut::PlayerPositionAndLook => PlayPacketOut757::PlayerPositionAndLook,
+            PlayPacketOut::Respawn => PlayPacketOut757::Respawn,
+            PlayPacketOut::UpdateViewPosition => PlayPacketOut757::UpdateViewPosition,
+        };
+        packet as u8
+    }
+}
+
+/// Serverbound Play packets of protocol 757 (1.18).
+#[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum PlayPacket757 {
+    TeleportConfirm = 0x00,
+    ChatMessage = 0x03,
+    ClientSettings = 0x05,
+    KeepAlive = 0x0f,
+    PlayerPosition = 0x11,
+    PlayerPositionAndRotation = 0x12,
+    PlayerRotation = 0x13,
+    PlayerMovement = 0x14,
+}
+
+/// Clientbound Play packets of protocol 757 (1.18).
+#[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum PlayPacketOut757 {
+    BlockChange = 0x0c,
+    WindowItems = 0x14,
+    UnloadChunk = 0x1d,
+    ChangeGameState = 0x1e,
+    ChunkDataAndUpdateLight = 0x22,
+    UpdateLight = 0x25,
+    JoinGame = 0x26,
+    PlayerPositionAndLook = 0x38,
+    Respawn = 0x3d,
+    UpdateViewPosition = 0x49,
+}
+
 #[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 #[repr(i8)]
 pub enum GameMode {
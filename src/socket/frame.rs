@@ -0,0 +1,133 @@
+use std::pin::Pin;
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+use crate::mc_types::{VarInt, VarIntError};
+
+/// Largest frame the protocol allows, the maximum value of a 3 byte VarInt.
+pub const MAX_FRAME_LENGTH: usize = 2097151;
+
+const READ_CHUNK: usize = 8192;
+
+/** Splits a byte stream into length prefixed frames.
+
+The socket is read in large chunks into one buffer and each frame is split
+off it without copying. The packet id and body can then be decoded
+synchronously with `mc_types::slice::McSliceReader`.
+ */
+pub struct FrameReader<R> {
+    reader: R,
+    buf: BytesMut,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(reader: R) -> FrameReader<R> {
+        FrameReader {
+            reader,
+            buf: BytesMut::with_capacity(READ_CHUNK),
+        }
+    }
+
+    /// Splits the next frame off the buffer if it has fully arrived.
+    fn try_split_frame(&mut self) -> io::Result<Option<Bytes>> {
+        let mut prefix = &self.buf[..];
+        let length = match VarInt::read_from(&mut prefix) {
+            Ok(v) => v,
+            Err(VarIntError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if length < 0 || length as usize > MAX_FRAME_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid frame length {}", length),
+            ));
+        }
+        let header = self.buf.len() - prefix.len();
+        let length = length as usize;
+        if prefix.len() < length {
+            self.buf.reserve(header + length - self.buf.len());
+            return Ok(None);
+        }
+        let _ = self.buf.split_to(header);
+        Ok(Some(self.buf.split_to(length).freeze()))
+    }
+
+    /// Reads the next frame, without its length prefix.
+    pub async fn read_frame(&mut self) -> io::Result<Bytes> {
+        loop {
+            if let Some(frame) = self.try_split_frame()? {
+                return Ok(frame);
+            }
+            if self.buf.capacity() == self.buf.len() {
+                self.buf.reserve(READ_CHUNK);
+            }
+            if self.reader.read_buf(&mut self.buf).await? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed mid-frame",
+                ));
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+}
+
+/// Reads a single frame without buffering past its end, for when the
+/// reader is shared with code that reads the stream directly.
+pub async fn read_frame<R: AsyncRead + ?Sized>(reader: &mut Pin<&mut R>) -> io::Result<Bytes> {
+    let length = VarInt::read_from_async(reader).await?;
+    if length < 0 || length as usize > MAX_FRAME_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid frame length {}", length),
+        ));
+    }
+    let mut buf = BytesMut::new();
+    buf.resize(length as usize, 0);
+    reader.read_exact(&mut buf).await?;
+    Ok(buf.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc_types::ext::McWriteExt;
+    use async_std::task;
+
+    #[test]
+    fn test_read_frame() {
+        let mut input = Vec::new();
+        input.write_mc_varint(3).unwrap();
+        input.extend_from_slice(&[0x00, 0x11, 0x22, 0x33]);
+
+        task::block_on(async move {
+            let mut reader = &input[..];
+            let frame = read_frame(&mut Pin::new(&mut reader)).await.unwrap();
+            assert_eq!(&frame[..], &[0x00, 0x11, 0x22]);
+            assert_eq!(reader, &[0x33]);
+        });
+    }
+
+    #[test]
+    fn test_frame_reader() {
+        let mut input = Vec::new();
+        input.write_mc_varint(2).unwrap();
+        input.extend_from_slice(&[0x00, 0x11]);
+        input.write_mc_varint(300).unwrap();
+        input.extend_from_slice(&[0x05; 300]);
+        input.write_mc_varint(5).unwrap();
+        input.extend_from_slice(&[0x01]);
+
+        task::block_on(async move {
+            let mut frames = FrameReader::new(&input[..]);
+            assert_eq!(&frames.read_frame().await.unwrap()[..], &[0x00, 0x11]);
+            assert_eq!(frames.read_frame().await.unwrap().len(), 300);
+            let truncated = frames.read_frame().await.unwrap_err();
+            assert_eq!(truncated.kind(), io::ErrorKind::UnexpectedEof);
+        });
+    }
+}
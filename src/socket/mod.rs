@@ -1,4 +1,5 @@
 pub mod compression;
+pub mod frame;
 pub mod passthrough;
 
 use std::pin::Pin;
@@ -0,0 +1,984 @@
+//! Vanilla worlds in Anvil region files.
+//!
+//! A region file `r.<x>.<z>.mca` holds 32x32 chunks. It starts with a 4 KiB
+//! table of where each chunk is, counted in 4 KiB sectors, followed by 4 KiB
+//! of timestamps. Each chunk is a 4 byte length, a compression byte and the
+//! compressed NBT. Chunks too large for the region live in `c.<x>.<z>.mcc`
+//! next to it, which the high bit of the compression byte points to.
+//!
+//! Only the chunk format of 1.18 and later is understood, and chunks are
+//! saved in the 1.18.1 format.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{File, OpenOptions},
+    hash::Hash,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::ZlibEncoder,
+};
+
+use crate::mc_types::nbt_io::{read_nbt, write_nbt, NbtError, NbtFormat, NbtLimits};
+
+use super::{
+    biomes::Biome,
+    blocks::BlockState,
+    chunk::{
+        bits_for, BitStorage, BlockEntity, ChunkColumn, LightArray, PalettedContainer, SECTION_SIZE,
+    },
+    dimensions::DimensionType,
+    ids::BlockEntityType,
+    registry::{NbtMap, Registry},
+};
+
+pub const SECTOR_SIZE: usize = 4096;
+/// Set in the compression byte of chunks stored in a `.mcc` file.
+const EXTERNAL_FLAG: u8 = 0x80;
+/// The most sectors a chunk can take up in the region file itself.
+const MAX_SECTORS: usize = 255;
+/// The first snapshot with the 1.18 chunk format.
+pub const MIN_DATA_VERSION: i32 = 2844;
+/// The data version of 1.18.1, which new chunks are saved with. Loaded
+/// chunks keep theirs.
+pub const DATA_VERSION: i32 = 2865;
+/// The tags of a saved chunk that `chunk_to_nbt` writes. Others are kept
+/// from the loaded chunk.
+const OWN_TAGS: [&str; 8] = [
+    "xPos",
+    "yPos",
+    "zPos",
+    "Status",
+    "isLightOn",
+    "sections",
+    "block_entities",
+    "Heightmaps",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Compression {
+    Gzip = 1,
+    Zlib = 2,
+    None = 3,
+}
+
+impl Compression {
+    pub fn from_id(id: u8) -> Option<Compression> {
+        match id {
+            1 => Some(Compression::Gzip),
+            2 => Some(Compression::Zlib),
+            3 => Some(Compression::None),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AnvilError {
+    Io(io::Error),
+    Nbt(NbtError),
+    UnsupportedCompression(u8),
+    /// The region file itself is damaged.
+    Corrupt(String),
+    /// The chunk NBT isn't laid out as expected.
+    Format(String),
+}
+
+impl fmt::Display for AnvilError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnvilError::Io(e) => write!(f, "{}", e),
+            AnvilError::Nbt(e) => write!(f, "{}", e),
+            AnvilError::UnsupportedCompression(id) => write!(f, "unsupported compression {}", id),
+            AnvilError::Corrupt(message) => write!(f, "corrupt region file: {}", message),
+            AnvilError::Format(message) => write!(f, "invalid chunk: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for AnvilError {}
+
+impl From<io::Error> for AnvilError {
+    fn from(e: io::Error) -> Self {
+        AnvilError::Io(e)
+    }
+}
+
+impl From<NbtError> for AnvilError {
+    fn from(e: NbtError) -> Self {
+        AnvilError::Nbt(e)
+    }
+}
+
+impl From<AnvilError> for io::Error {
+    fn from(e: AnvilError) -> Self {
+        match e {
+            AnvilError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Index of a chunk in the region tables.
+fn table_index(x: i32, z: i32) -> usize {
+    ((x & 31) + (z & 31) * 32) as usize
+}
+
+pub struct RegionFile {
+    path: PathBuf,
+    file: File,
+    /// Sector offset in the upper 24 bits, sector count in the lower 8.
+    locations: Vec<u32>,
+    timestamps: Vec<u32>,
+    /// Which sectors hold the header or a chunk.
+    used: Vec<bool>,
+}
+
+impl RegionFile {
+    /// Opens a region file, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> Result<RegionFile, AnvilError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut locations = vec![0; 1024];
+        let mut timestamps = vec![0; 1024];
+        // Vanilla leaves a new region empty until the first chunk is saved
+        if file.metadata()?.len() == 0 {
+            file.write_all(&[0; 2 * SECTOR_SIZE])?;
+        } else {
+            file.read_u32_into::<BE>(&mut locations)?;
+            file.read_u32_into::<BE>(&mut timestamps)?;
+        }
+        let mut used = vec![true; 2];
+        for &location in &locations {
+            let (offset, sectors) = ((location >> 8) as usize, (location & 0xff) as usize);
+            if location != 0 && offset >= 2 {
+                if used.len() < offset + sectors {
+                    used.resize(offset + sectors, false);
+                }
+                used[offset..offset + sectors].fill(true);
+            }
+        }
+        Ok(RegionFile {
+            path: path.to_owned(),
+            file,
+            locations,
+            timestamps,
+            used,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Chunk coordinates may be absolute or relative to the region.
+    pub fn has_chunk(&self, x: i32, z: i32) -> bool {
+        self.locations[table_index(x, z)] != 0
+    }
+
+    /// When the chunk was last saved, in seconds since the epoch.
+    pub fn timestamp(&self, x: i32, z: i32) -> u32 {
+        self.timestamps[table_index(x, z)]
+    }
+
+    /// The `.mcc` file of the chunk at absolute coordinates `x`, `z`.
+    pub fn external_path(&self, x: i32, z: i32) -> PathBuf {
+        self.path.with_file_name(format!("c.{}.{}.mcc", x, z))
+    }
+
+    /// Reads the NBT of the chunk at absolute coordinates `x`, `z`.
+    pub fn read_chunk(&mut self, x: i32, z: i32) -> Result<Option<nbt::Value>, AnvilError> {
+        let location = self.locations[table_index(x, z)];
+        if location == 0 {
+            return Ok(None);
+        }
+        let (offset, sectors) = ((location >> 8) as u64, (location & 0xff) as usize);
+        if offset < 2 {
+            return Err(AnvilError::Corrupt(format!(
+                "chunk {}, {} overlaps the header",
+                x, z
+            )));
+        }
+        self.file
+            .seek(SeekFrom::Start(offset * SECTOR_SIZE as u64))?;
+        let length = self.file.read_u32::<BE>()? as usize;
+        if length == 0 || length + 4 > sectors * SECTOR_SIZE {
+            return Err(AnvilError::Corrupt(format!(
+                "chunk {}, {} is {} bytes long in {} sectors",
+                x, z, length, sectors
+            )));
+        }
+        let compression = self.file.read_u8()?;
+        let mut data = vec![0; length - 1];
+        self.file.read_exact(&mut data)?;
+        if compression & EXTERNAL_FLAG != 0 {
+            data = std::fs::read(self.external_path(x, z))?;
+        }
+        let compression = compression & !EXTERNAL_FLAG;
+        let compression = Compression::from_id(compression)
+            .ok_or(AnvilError::UnsupportedCompression(compression))?;
+        decompress_nbt(&data, compression).map(Some)
+    }
+
+    /// Finds `count` free sectors, preferring the first gap that fits.
+    fn allocate(&mut self, count: usize) -> usize {
+        let mut run = 0;
+        for (i, &used) in self.used.iter().enumerate() {
+            run = if used { 0 } else { run + 1 };
+            if run == count {
+                let offset = i + 1 - count;
+                self.used[offset..=i].fill(true);
+                return offset;
+            }
+        }
+        let offset = self.used.len() - run;
+        self.used.resize(offset + count, true);
+        self.used[offset..].fill(true);
+        offset
+    }
+
+    /// Writes the chunk at absolute coordinates `x`, `z` with zlib. The old
+    /// sectors are only freed once the new ones are written, and are reused
+    /// by later chunks.
+    pub fn write_chunk(&mut self, x: i32, z: i32, nbt: &nbt::Value) -> Result<(), AnvilError> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        write_nbt(&mut encoder, "", nbt, NbtFormat::Named)?;
+        let data = encoder.finish()?;
+
+        let mut sectors = Vec::with_capacity(data.len() + 5);
+        let compression = Compression::Zlib as u8;
+        let external = self.external_path(x, z);
+        if data.len() + 5 > MAX_SECTORS * SECTOR_SIZE {
+            std::fs::write(&external, &data)?;
+            sectors.write_u32::<BE>(1)?;
+            sectors.push(compression | EXTERNAL_FLAG);
+        } else {
+            sectors.write_u32::<BE>(data.len() as u32 + 1)?;
+            sectors.push(compression);
+            sectors.extend_from_slice(&data);
+            match std::fs::remove_file(&external) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        let count = sectors.len().div_ceil(SECTOR_SIZE);
+        sectors.resize(count * SECTOR_SIZE, 0);
+
+        let offset = self.allocate(count);
+        self.file
+            .seek(SeekFrom::Start((offset * SECTOR_SIZE) as u64))?;
+        self.file.write_all(&sectors)?;
+
+        let index = table_index(x, z);
+        let old = self.locations[index];
+        self.locations[index] = (offset << 8 | count) as u32;
+        self.timestamps[index] = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        self.file.seek(SeekFrom::Start(index as u64 * 4))?;
+        self.file.write_u32::<BE>(self.locations[index])?;
+        self.file
+            .seek(SeekFrom::Start((SECTOR_SIZE + index * 4) as u64))?;
+        self.file.write_u32::<BE>(self.timestamps[index])?;
+
+        let (old_offset, old_count) = ((old >> 8) as usize, (old & 0xff) as usize);
+        if old != 0 && old_offset >= 2 && old_offset + old_count <= self.used.len() {
+            self.used[old_offset..old_offset + old_count].fill(false);
+        }
+        Ok(())
+    }
+
+    /// Makes sure everything written so far is on disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+pub fn decompress_nbt(data: &[u8], compression: Compression) -> Result<nbt::Value, AnvilError> {
+    let mut reader: Box<dyn Read> = match compression {
+        Compression::Gzip => Box::new(GzDecoder::new(data)),
+        Compression::Zlib => Box::new(ZlibDecoder::new(data)),
+        Compression::None => Box::new(data),
+    };
+    let (_, nbt) = read_nbt(&mut reader, NbtFormat::Named, &NbtLimits::default())?;
+    Ok(nbt)
+}
+
+/// The region files of one dimension, opened as chunks are requested.
+pub struct RegionStorage {
+    dir: PathBuf,
+    regions: HashMap<(i32, i32), RegionFile>,
+}
+
+impl RegionStorage {
+    /// `dir` is the `region` directory of the dimension.
+    pub fn new(dir: impl Into<PathBuf>) -> RegionStorage {
+        RegionStorage {
+            dir: dir.into(),
+            regions: HashMap::new(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The region file holding the chunk at `x`, `z`.
+    pub fn region_path(&self, x: i32, z: i32) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.mca", x >> 5, z >> 5))
+    }
+
+    /// The open region holding the chunk at `x`, `z`. Missing regions are
+    /// only created if `create` is set.
+    fn region(
+        &mut self,
+        x: i32,
+        z: i32,
+        create: bool,
+    ) -> Result<Option<&mut RegionFile>, AnvilError> {
+        let key = (x >> 5, z >> 5);
+        if !self.regions.contains_key(&key) {
+            let path = self.region_path(x, z);
+            if !path.exists() {
+                if !create {
+                    return Ok(None);
+                }
+                std::fs::create_dir_all(&self.dir)?;
+            }
+            self.regions.insert(key, RegionFile::open(&path)?);
+        }
+        Ok(self.regions.get_mut(&key))
+    }
+
+    pub fn read_chunk_nbt(&mut self, x: i32, z: i32) -> Result<Option<nbt::Value>, AnvilError> {
+        match self.region(x, z, false)? {
+            Some(region) => region.read_chunk(x, z),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads a fully generated chunk. Chunks that vanilla hasn't finished
+    /// generating count as missing.
+    pub fn load_chunk(
+        &mut self,
+        x: i32,
+        z: i32,
+        dimension: &DimensionType,
+        biomes: &Registry<Biome>,
+    ) -> Result<Option<ChunkColumn>, AnvilError> {
+        let nbt = match self.read_chunk_nbt(x, z)? {
+            Some(nbt) => nbt,
+            None => return Ok(None),
+        };
+        let status = compound(&nbt, "chunk")?.get("Status");
+        match status {
+            Some(nbt::Value::String(s)) if s == "full" || s == "minecraft:full" => {}
+            _ => return Ok(None),
+        }
+        chunk_from_nbt(&nbt, dimension, biomes).map(Some)
+    }
+
+    pub fn save_chunk(
+        &mut self,
+        column: &ChunkColumn,
+        biomes: &Registry<Biome>,
+    ) -> Result<(), AnvilError> {
+        self.write_chunk_nbt(column.x, column.z, &chunk_to_nbt(column, biomes))
+    }
+
+    /// Writes a chunk already converted with `chunk_to_nbt`.
+    pub fn write_chunk_nbt(&mut self, x: i32, z: i32, nbt: &nbt::Value) -> Result<(), AnvilError> {
+        let region = self.region(x, z, true)?.unwrap();
+        region.write_chunk(x, z, nbt)
+    }
+
+    pub fn sync(&self) -> io::Result<()> {
+        for region in self.regions.values() {
+            region.sync()?;
+        }
+        Ok(())
+    }
+}
+
+fn format_error<T>(message: String) -> Result<T, AnvilError> {
+    Err(AnvilError::Format(message))
+}
+
+fn compound<'a>(value: &'a nbt::Value, what: &str) -> Result<&'a NbtMap, AnvilError> {
+    match value {
+        nbt::Value::Compound(map) => Ok(map),
+        _ => format_error(format!("{} is not a compound", what)),
+    }
+}
+
+fn int(map: &NbtMap, key: &str) -> Result<i32, AnvilError> {
+    match map.get(key) {
+        Some(nbt::Value::Int(i)) => Ok(*i),
+        Some(nbt::Value::Short(i)) => Ok(*i as i32),
+        Some(nbt::Value::Byte(i)) => Ok(*i as i32),
+        _ => format_error(format!("missing integer {}", key)),
+    }
+}
+
+fn list<'a>(map: &'a NbtMap, key: &str) -> &'a [nbt::Value] {
+    match map.get(key) {
+        Some(nbt::Value::List(list)) => list,
+        _ => &[],
+    }
+}
+
+fn string<'a>(map: &'a NbtMap, key: &str) -> Option<&'a str> {
+    match map.get(key) {
+        Some(nbt::Value::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// Fills `container` from a saved palette and its packed indices, and
+/// calls `visit` with each index into the container and into `palette`.
+fn read_paletted(
+    container: &mut PalettedContainer,
+    palette: &[i32],
+    data: Option<&nbt::Value>,
+    min_bits: u8,
+    mut visit: impl FnMut(usize, usize),
+) -> Result<(), AnvilError> {
+    match palette {
+        [] => return format_error("empty palette".to_owned()),
+        [value] => {
+            container.fill(*value);
+            (0..container.kind().size).for_each(|i| visit(i, 0));
+            return Ok(());
+        }
+        _ => {}
+    }
+    let data = match data {
+        Some(nbt::Value::LongArray(data)) => data.iter().map(|&l| l as u64).collect(),
+        _ => return format_error("palette without data".to_owned()),
+    };
+    let bits = bits_for(palette.len()).max(min_bits);
+    let size = container.kind().size;
+    let storage = BitStorage::from_data(bits, size, data)
+        .ok_or_else(|| AnvilError::Format("data length does not match the palette".to_owned()))?;
+    for i in 0..size {
+        let index = storage.get(i) as usize;
+        let id = palette
+            .get(index)
+            .ok_or_else(|| AnvilError::Format("palette index out of range".to_owned()))?;
+        container.set(i, *id);
+        visit(i, index);
+    }
+    Ok(())
+}
+
+/// A saved palette entry such as `{Name: "minecraft:oak_stairs",
+/// Properties: {facing: "east"}}`, and whether the registry knows it
+/// exactly. Blocks the registry doesn't know become air and properties it
+/// doesn't know are left out.
+fn block_state(entry: &nbt::Value) -> Result<(BlockState, bool), AnvilError> {
+    let entry = compound(entry, "block state")?;
+    let name = string(entry, "Name")
+        .ok_or_else(|| AnvilError::Format("block state without a name".to_owned()))?;
+    let mut state = match BlockState::from_name(name) {
+        Ok(state) => state,
+        Err(_) => return Ok((BlockState::AIR, false)),
+    };
+    let mut exact = true;
+    if let Some(nbt::Value::Compound(properties)) = entry.get("Properties") {
+        for (property, value) in properties {
+            match value {
+                nbt::Value::String(value) => match state.with(property, value) {
+                    Ok(with) => state = with,
+                    Err(_) => exact = false,
+                },
+                _ => exact = false,
+            }
+        }
+    }
+    Ok((state, exact))
+}
+
+fn light_array(section: &NbtMap, key: &str) -> Option<LightArray> {
+    match section.get(key) {
+        Some(nbt::Value::ByteArray(bytes)) => {
+            let bytes: Vec<u8> = bytes.iter().map(|&b| b as u8).collect();
+            LightArray::from_bytes(&bytes)
+        }
+        _ => None,
+    }
+}
+
+/// Converts saved chunk NBT into a column of `dimension`. Biomes the
+/// registry doesn't know become the first biome. Blocks it doesn't know
+/// exactly are kept as saved, see `ChunkColumn::keep_block`.
+pub fn chunk_from_nbt(
+    nbt: &nbt::Value,
+    dimension: &DimensionType,
+    biomes: &Registry<Biome>,
+) -> Result<ChunkColumn, AnvilError> {
+    let root = compound(nbt, "chunk")?;
+    let data_version = int(root, "DataVersion")?;
+    if data_version < MIN_DATA_VERSION {
+        return format_error(format!("data version {} is older than 1.18", data_version));
+    }
+    let (x, z) = (int(root, "xPos")?, int(root, "zPos")?);
+    let mut column = ChunkColumn::new(x, z, dimension, 0, biomes.len());
+
+    for section in list(root, "sections") {
+        let section = compound(section, "section")?;
+        let y = int(section, "Y")?;
+        let light_index = y - dimension.min_section() + 1;
+        if light_index < 0 || light_index as usize >= column.light_section_count() {
+            continue;
+        }
+        let light_index = light_index as usize;
+        if let Some(light) = light_array(section, "SkyLight") {
+            column.set_sky_light(light_index, Some(light));
+        }
+        column.set_block_light(light_index, light_array(section, "BlockLight"));
+
+        let index = match light_index.checked_sub(1) {
+            Some(index) if index < column.sections().len() => index,
+            _ => continue,
+        };
+        let target = &mut column.sections_mut()[index];
+        // Blocks to keep as saved, with their index into `entries`
+        let mut kept = Vec::new();
+        let mut entries: &[nbt::Value] = &[];
+        if let Some(nbt::Value::Compound(states)) = section.get("block_states") {
+            entries = list(states, "palette");
+            let loaded = entries
+                .iter()
+                .map(block_state)
+                .collect::<Result<Vec<_>, _>>()?;
+            let palette: Vec<i32> = loaded.iter().map(|(state, _)| state.id()).collect();
+            read_paletted(
+                &mut target.blocks,
+                &palette,
+                states.get("data"),
+                4,
+                |i, entry| {
+                    if !loaded[entry].1 {
+                        kept.push((i, entry));
+                    }
+                },
+            )?;
+            target.recount_blocks();
+        }
+        if let Some(nbt::Value::Compound(saved)) = section.get("biomes") {
+            let palette: Vec<i32> = list(saved, "palette")
+                .iter()
+                .map(|entry| match entry {
+                    nbt::Value::String(name) => name
+                        .parse()
+                        .ok()
+                        .and_then(|name| biomes.id_of(&name))
+                        .unwrap_or(0),
+                    _ => 0,
+                })
+                .collect();
+            read_paletted(
+                &mut target.biomes,
+                &palette,
+                saved.get("data"),
+                0,
+                |_, _| {},
+            )?;
+        }
+        let section_y = column.min_y() + (index * SECTION_SIZE) as i32;
+        for (i, entry) in kept {
+            let (x, y, z) = (i & 15, (i >> 8) as i32, (i >> 4) & 15);
+            column.keep_block(x as u8, section_y + y, z as u8, entries[entry].clone());
+        }
+    }
+
+    for block_entity in list(root, "block_entities") {
+        let block_entity = compound(block_entity, "block entity")?;
+        let type_id = match string(block_entity, "id").map(BlockEntityType::from_name) {
+            Some(Ok(type_id)) => type_id,
+            _ => continue,
+        };
+        let (bx, by, bz) = (
+            int(block_entity, "x")?,
+            int(block_entity, "y")?,
+            int(block_entity, "z")?,
+        );
+        let mut data = block_entity.clone();
+        for key in ["id", "x", "y", "z", "keepPacked"] {
+            data.remove(key);
+        }
+        column.set_block_entity(BlockEntity {
+            x: (bx & 15) as u8,
+            y: by,
+            z: (bz & 15) as u8,
+            type_id: type_id.id(),
+            nbt: nbt::Value::Compound(data),
+        });
+    }
+
+    let other_tags = root
+        .iter()
+        .filter(|(key, _)| !OWN_TAGS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    column.set_other_tags(other_tags);
+
+    // Chunks saved before their light was computed are lit again on load
+    column.set_light_on(matches!(root.get("isLightOn"), Some(nbt::Value::Byte(1))));
+    column.recalculate_heightmaps();
+    Ok(column)
+}
+
+/// Saves `size` values with their own palette, the way `read_paletted`
+/// expects them. `name` turns a value into its palette entry.
+fn write_paletted<T: Copy + Eq + Hash>(
+    size: usize,
+    value: impl Fn(usize) -> T,
+    min_bits: u8,
+    name: impl Fn(T) -> nbt::Value,
+) -> nbt::Value {
+    let mut palette = Vec::new();
+    let mut indices = HashMap::new();
+    let values: Vec<u64> = (0..size)
+        .map(|i| {
+            let value = value(i);
+            *indices.entry(value).or_insert_with(|| {
+                palette.push(value);
+                palette.len() as u64 - 1
+            })
+        })
+        .collect();
+
+    let mut saved = nbt::Map::new();
+    if palette.len() > 1 {
+        let mut storage = BitStorage::new(bits_for(palette.len()).max(min_bits), size);
+        for (i, value) in values.into_iter().enumerate() {
+            storage.set(i, value);
+        }
+        let data = storage.data().iter().map(|&l| l as i64).collect();
+        saved.insert("data".to_owned(), nbt::Value::LongArray(data));
+    }
+    let palette = palette.into_iter().map(name).collect();
+    saved.insert("palette".to_owned(), nbt::Value::List(palette));
+    nbt::Value::Compound(saved)
+}
+
+fn block_state_nbt(state: BlockState) -> nbt::Value {
+    let mut entry = nbt::Map::new();
+    let name = state.block().name.to_string();
+    entry.insert("Name".to_owned(), nbt::Value::String(name));
+    let properties: NbtMap = state
+        .properties()
+        .map(|(k, v)| (k.to_owned(), nbt::Value::String(v.to_owned())))
+        .collect();
+    if !properties.is_empty() {
+        entry.insert("Properties".to_owned(), nbt::Value::Compound(properties));
+    }
+    nbt::Value::Compound(entry)
+}
+
+/// The chunk as vanilla 1.18.1 saves it, with the tags of the loaded chunk
+/// the server doesn't use.
+pub fn chunk_to_nbt(column: &ChunkColumn, biomes: &Registry<Biome>) -> nbt::Value {
+    let min_section = column.min_y() >> 4;
+    let mut sections = Vec::new();
+    for light_index in 0..column.light_section_count() {
+        let y = min_section - 1 + light_index as i32;
+        let mut section = nbt::Map::new();
+        section.insert("Y".to_owned(), nbt::Value::Byte(y as i8));
+        let index = light_index.checked_sub(1);
+        if let Some(blocks) = index.and_then(|i| column.sections().get(i)) {
+            let section_y = column.min_y() + (index.unwrap() * SECTION_SIZE) as i32;
+            let block = |i: usize| {
+                let (x, y, z) = ((i & 15) as u8, (i >> 8) as i32, ((i >> 4) & 15) as u8);
+                match column.kept_block(x, section_y + y, z) {
+                    Some(entry) => Err(entry),
+                    None => Ok(blocks.blocks.get(i)),
+                }
+            };
+            let states = write_paletted(blocks.blocks.kind().size, block, 4, |block| match block {
+                Ok(id) => block_state_nbt(BlockState::from_id(id).unwrap_or_default()),
+                Err(index) => column.kept_entries()[index].clone(),
+            });
+            section.insert("block_states".to_owned(), states);
+            let size = blocks.biomes.kind().size;
+            let saved_biomes = write_paletted(
+                size,
+                |i| blocks.biomes.get(i),
+                0,
+                |id| {
+                    let entry = biomes.get_by_id(id).or_else(|| biomes.get_by_id(0));
+                    nbt::Value::String(entry.map_or_else(String::new, |e| e.name.to_string()))
+                },
+            );
+            section.insert("biomes".to_owned(), saved_biomes);
+        } else if column.sky_light(light_index).is_none()
+            && column.block_light(light_index).is_none()
+        {
+            continue;
+        }
+        for (key, light) in [
+            ("SkyLight", column.sky_light(light_index)),
+            ("BlockLight", column.block_light(light_index)),
+        ] {
+            if let Some(light) = light {
+                let bytes = light.as_bytes().iter().map(|&b| b as i8).collect();
+                section.insert(key.to_owned(), nbt::Value::ByteArray(bytes));
+            }
+        }
+        sections.push(nbt::Value::Compound(section));
+    }
+
+    let block_entities = column
+        .block_entities()
+        .map(|block_entity| {
+            let mut saved = match &block_entity.nbt {
+                nbt::Value::Compound(data) => data.clone(),
+                _ => nbt::Map::new(),
+            };
+            let id = BlockEntityType::from_id(block_entity.type_id)
+                .map_or_else(String::new, |t| t.name().to_string());
+            saved.insert("id".to_owned(), nbt::Value::String(id));
+            let x = column.x * 16 + block_entity.x as i32;
+            let z = column.z * 16 + block_entity.z as i32;
+            saved.insert("x".to_owned(), nbt::Value::Int(x));
+            saved.insert("y".to_owned(), nbt::Value::Int(block_entity.y));
+            saved.insert("z".to_owned(), nbt::Value::Int(z));
+            nbt::Value::Compound(saved)
+        })
+        .collect();
+
+    let mut root = column.other_tags().clone();
+    root.entry("DataVersion".to_owned())
+        .or_insert(nbt::Value::Int(DATA_VERSION));
+    root.insert("xPos".to_owned(), nbt::Value::Int(column.x));
+    root.insert("yPos".to_owned(), nbt::Value::Int(min_section));
+    root.insert("zPos".to_owned(), nbt::Value::Int(column.z));
+    root.insert("Status".to_owned(), nbt::Value::String("full".to_owned()));
+    let light_on = column.is_light_on() as i8;
+    root.insert("isLightOn".to_owned(), nbt::Value::Byte(light_on));
+    root.insert("sections".to_owned(), nbt::Value::List(sections));
+    root.insert(
+        "block_entities".to_owned(),
+        nbt::Value::List(block_entities),
+    );
+    root.insert("Heightmaps".to_owned(), column.heightmaps_nbt());
+    nbt::Value::Compound(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::registry::test_overworld;
+    use flate2::{write::ZlibEncoder, Compression as Level};
+    use std::io::Write;
+
+    fn saved_chunk(x: i32, z: i32) -> nbt::Value {
+        let state = |name: &str, properties: &[(&str, &str)]| {
+            let mut state = nbt::Map::new();
+            state.insert("Name".to_owned(), nbt::Value::String(name.to_owned()));
+            let properties: NbtMap = properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), nbt::Value::String(v.to_string())))
+                .collect();
+            state.insert("Properties".to_owned(), nbt::Value::Compound(properties));
+            nbt::Value::Compound(state)
+        };
+        // Block 0 is snowy grass and every other block is unknown, 4 bits each
+        let mut states = nbt::Map::new();
+        states.insert(
+            "palette".to_owned(),
+            nbt::Value::List(vec![
+                state("minecraft:air", &[]),
+                state("minecraft:grass_block", &[("snowy", "true")]),
+                state("minecraft:diamond_block", &[]),
+            ]),
+        );
+        let mut data = vec![0x2222_2222_2222_2222u64 as i64; 256];
+        data[0] = 0x2222_2222_2222_2221;
+        states.insert("data".to_owned(), nbt::Value::LongArray(data));
+        let mut biomes = nbt::Map::new();
+        biomes.insert(
+            "palette".to_owned(),
+            nbt::Value::List(vec![nbt::Value::String("minecraft:swamp".to_owned())]),
+        );
+        let mut section = nbt::Map::new();
+        section.insert("Y".to_owned(), nbt::Value::Byte(1));
+        section.insert("block_states".to_owned(), nbt::Value::Compound(states));
+        section.insert("biomes".to_owned(), nbt::Value::Compound(biomes));
+        section.insert(
+            "SkyLight".to_owned(),
+            nbt::Value::ByteArray(vec![0x7f; 2048]),
+        );
+
+        let mut chest = nbt::Map::new();
+        chest.insert(
+            "id".to_owned(),
+            nbt::Value::String("minecraft:chest".to_owned()),
+        );
+        chest.insert("x".to_owned(), nbt::Value::Int(x * 16 + 3));
+        chest.insert("y".to_owned(), nbt::Value::Int(20));
+        chest.insert("z".to_owned(), nbt::Value::Int(z * 16 + 4));
+        chest.insert("Items".to_owned(), nbt::Value::List(Vec::new()));
+
+        let mut root = nbt::Map::new();
+        root.insert("DataVersion".to_owned(), nbt::Value::Int(2975));
+        root.insert("xPos".to_owned(), nbt::Value::Int(x));
+        root.insert("zPos".to_owned(), nbt::Value::Int(z));
+        root.insert("Status".to_owned(), nbt::Value::String("full".to_owned()));
+        root.insert("InhabitedTime".to_owned(), nbt::Value::Long(1200));
+        root.insert(
+            "structures".to_owned(),
+            nbt::Value::Compound(nbt::Map::new()),
+        );
+        root.insert(
+            "sections".to_owned(),
+            nbt::Value::List(vec![nbt::Value::Compound(section)]),
+        );
+        root.insert(
+            "block_entities".to_owned(),
+            nbt::Value::List(vec![nbt::Value::Compound(chest)]),
+        );
+        nbt::Value::Compound(root)
+    }
+
+    #[test]
+    fn test_read_region() {
+        let dir = std::env::temp_dir().join(format!("zipper-anvil-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let compress = |nbt: &nbt::Value| {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Level::default());
+            write_nbt(&mut encoder, "", nbt, NbtFormat::Named).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        // Chunk -31, 2 is stored inline in sector 2, chunk -32, 0 in a .mcc
+        let inline = compress(&saved_chunk(-31, 2));
+        let mut region = vec![0u8; 3 * SECTOR_SIZE];
+        let index = table_index(-31, 2) * 4;
+        region[index..index + 4].copy_from_slice(&(2 << 8 | 1u32).to_be_bytes());
+        let index = table_index(-32, 0) * 4;
+        region[index..index + 4].copy_from_slice(&(3 << 8 | 1u32).to_be_bytes());
+        let mut sector = Vec::new();
+        sector
+            .write_all(&(inline.len() as u32 + 1).to_be_bytes())
+            .unwrap();
+        sector.push(Compression::Zlib as u8);
+        sector.extend_from_slice(&inline);
+        region[2 * SECTOR_SIZE..2 * SECTOR_SIZE + sector.len()].copy_from_slice(&sector);
+        region.extend_from_slice(&[0, 0, 0, 1, Compression::Zlib as u8 | EXTERNAL_FLAG]);
+        region.resize(4 * SECTOR_SIZE, 0);
+        std::fs::write(dir.join("r.-1.0.mca"), &region).unwrap();
+        std::fs::write(dir.join("c.-32.0.mcc"), compress(&saved_chunk(-32, 0))).unwrap();
+
+        let (registries, dimension) = test_overworld();
+        let mut storage = RegionStorage::new(&dir);
+        let column = storage
+            .load_chunk(-31, 2, &dimension, &registries.biomes)
+            .unwrap()
+            .unwrap();
+        let external = storage.load_chunk(-32, 0, &dimension, &registries.biomes);
+        let missing = storage.load_chunk(-30, 2, &dimension, &registries.biomes);
+        let other_region = storage.load_chunk(0, 0, &dimension, &registries.biomes);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((column.x, column.z), (-31, 2));
+        let grass = BlockState::from_name("grass_block").unwrap();
+        assert_eq!(
+            column.get_block(0, 16, 0),
+            grass.with("snowy", "true").unwrap()
+        );
+        assert_eq!(column.get_block(1, 16, 0), BlockState::AIR);
+        assert_eq!(column.sections()[1].block_count(), 1);
+        assert_eq!(column.surface_y(0, 0), 17);
+        assert_eq!(column.get_biome(0, 4, 0), Some(2));
+        let chest = column.block_entity(3, 20, 4).unwrap();
+        assert_eq!(
+            chest.type_id,
+            BlockEntityType::from_name("chest").unwrap().id()
+        );
+        assert_eq!(column.sky_light(2).unwrap().get(0, 0, 0), 15);
+        assert!(column.sky_light(1).is_none());
+
+        assert_eq!(external.unwrap().unwrap().x, -32);
+        assert!(missing.unwrap().is_none());
+        assert!(other_region.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_write_region() {
+        let dir = std::env::temp_dir().join(format!("zipper-anvil-write-{}", std::process::id()));
+        let (registries, dimension) = test_overworld();
+        let mut column =
+            chunk_from_nbt(&saved_chunk(5, -1), &dimension, &registries.biomes).unwrap();
+        let stone = BlockState::from_name("stone").unwrap();
+        column.set_block(7, -64, 7, stone);
+
+        let mut storage = RegionStorage::new(&dir);
+        storage.save_chunk(&column, &registries.biomes).unwrap();
+        storage.save_chunk(&column, &registries.biomes).unwrap();
+        let path = storage.region_path(5, -1);
+        let length = std::fs::metadata(&path).unwrap().len();
+        // The sectors freed by the first save are reused by the third
+        storage.save_chunk(&column, &registries.biomes).unwrap();
+        let reused = std::fs::metadata(&path).unwrap().len() == length;
+
+        let mut storage = RegionStorage::new(&dir);
+        let loaded = storage.load_chunk(5, -1, &dimension, &registries.biomes);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(reused);
+        assert_eq!(length % SECTOR_SIZE as u64, 0);
+        assert_eq!(loaded.unwrap().unwrap(), column);
+    }
+
+    #[test]
+    fn test_keep_unknown_blocks() {
+        let (registries, dimension) = test_overworld();
+        let mut column =
+            chunk_from_nbt(&saved_chunk(0, 0), &dimension, &registries.biomes).unwrap();
+        let stone = BlockState::from_name("stone").unwrap();
+        column.set_block(1, 16, 0, stone);
+
+        let saved = chunk_to_nbt(&column, &registries.biomes);
+        let loaded = chunk_from_nbt(&saved, &dimension, &registries.biomes).unwrap();
+        let root = compound(&saved, "chunk").unwrap();
+        let sections = list(root, "sections");
+        let section = sections
+            .iter()
+            .map(|section| compound(section, "section").unwrap())
+            .find(|section| int(section, "Y").unwrap() == 1)
+            .unwrap();
+        let palette = match section.get("block_states") {
+            Some(nbt::Value::Compound(states)) => list(states, "palette").len(),
+            _ => 0,
+        };
+
+        // Grass, stone and the unknown diamond block
+        assert_eq!(palette, 3);
+        assert_eq!(root["DataVersion"], nbt::Value::Int(2975));
+        assert_eq!(root["InhabitedTime"], nbt::Value::Long(1200));
+        assert!(root.contains_key("structures"));
+        assert_eq!(loaded.get_block(1, 16, 0), stone);
+        assert_eq!(loaded.kept_block(1, 16, 0), None);
+        assert_eq!(loaded.get_block(2, 16, 0), BlockState::AIR);
+        let diamond = &loaded.kept_entries()[loaded.kept_block(2, 16, 0).unwrap()];
+        let name = match diamond {
+            nbt::Value::Compound(entry) => string(entry, "Name"),
+            _ => None,
+        };
+        assert_eq!(name, Some("minecraft:diamond_block"));
+        assert_eq!(loaded, column);
+    }
+}
@@ -0,0 +1,440 @@
+//! What blocks do when they're ticked or a neighbour changes.
+//!
+//! Behaviour is registered per block in `BlockBehaviors`, and every state of
+//! the block shares it. `BlockBehaviors::vanilla` has the blocks the server
+//! simulates so far: crops grow, leaves away from logs decay and sand and
+//! gravel fall. Without falling block entities, falling blocks land at once.
+
+use std::{collections::HashMap, sync::Arc};
+
+use rand::Rng;
+
+use super::{
+    blocks::{blocks, BlockState},
+    level::Level,
+    tick::{BlockPos, TickPriority},
+};
+use crate::mc_types::McIdentifier;
+
+/// Every method does nothing by default.
+pub trait BlockBehavior: Send + Sync {
+    /// Whether random ticks of `state` do anything, so the rest are skipped.
+    fn ticks_randomly(&self, _state: BlockState) -> bool {
+        false
+    }
+
+    fn random_tick(&self, _level: &mut Level, _pos: BlockPos, _state: BlockState) {}
+
+    /// A tick the block scheduled ran.
+    fn scheduled_tick(&self, _level: &mut Level, _pos: BlockPos, _state: BlockState) {}
+
+    /// The block was just placed at `pos`, replacing `old`.
+    fn placed(&self, _level: &mut Level, _pos: BlockPos, _state: BlockState, _old: BlockState) {}
+
+    /// The block at `from`, next to `pos`, changed.
+    fn neighbor_changed(
+        &self,
+        _level: &mut Level,
+        _pos: BlockPos,
+        _state: BlockState,
+        _from: BlockPos,
+    ) {
+    }
+}
+
+/// Behaviour by block, for the installed block registry.
+#[derive(Clone, Default)]
+pub struct BlockBehaviors {
+    /// By the first state of the block.
+    by_block: HashMap<i32, Arc<dyn BlockBehavior>>,
+}
+
+impl BlockBehaviors {
+    pub fn new() -> BlockBehaviors {
+        BlockBehaviors::default()
+    }
+
+    /// The vanilla behaviour of every block in the registry that has some.
+    pub fn vanilla() -> BlockBehaviors {
+        let mut behaviors = BlockBehaviors::new();
+        for block in blocks().iter() {
+            if block.name.namespace() != McIdentifier::DEFAULT_NAMESPACE {
+                continue;
+            }
+            let name = block.name.name();
+            let behavior: Arc<dyn BlockBehavior> = match name {
+                "wheat" | "carrots" | "potatoes" => Arc::new(Crop { max_age: 7 }),
+                "beetroots" => Arc::new(Crop { max_age: 3 }),
+                "sand" | "red_sand" | "gravel" => Arc::new(Falling),
+                _ if name.ends_with("_concrete_powder") => Arc::new(Falling),
+                _ if name.ends_with("_leaves") => Arc::new(Leaves),
+                _ => continue,
+            };
+            behaviors.by_block.insert(block.first_state, behavior);
+        }
+        behaviors
+    }
+
+    /// Makes every state of block `name` use `behavior`, replacing what it
+    /// had. Returns false if the block isn't in the registry.
+    pub fn register(&mut self, name: &McIdentifier, behavior: Arc<dyn BlockBehavior>) -> bool {
+        match blocks().get(name) {
+            Some(block) => {
+                self.by_block.insert(block.first_state, behavior);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, state: BlockState) -> Option<&Arc<dyn BlockBehavior>> {
+        self.by_block.get(&state.block().first_state)
+    }
+}
+
+fn is_named(state: BlockState, names: &[&str]) -> bool {
+    let name = &state.block().name;
+    name.namespace() == McIdentifier::DEFAULT_NAMESPACE && names.contains(&name.name())
+}
+
+fn age(state: BlockState) -> u8 {
+    state.get("age").and_then(|a| a.parse().ok()).unwrap_or(0)
+}
+
+/// Wheat, carrots, potatoes and beetroots. They grow in light of 9 or more,
+/// faster on hydrated farmland and slower in crowded rows, and break when
+/// the farmland under them goes.
+pub struct Crop {
+    pub max_age: u8,
+}
+
+impl Crop {
+    /// Vanilla's growth speed, from the farmland under and around the crop.
+    fn growth_speed(&self, level: &mut Level, (x, y, z): BlockPos, state: BlockState) -> f32 {
+        let mut speed = 1.0;
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let below = level.get_block(x + dx, y - 1, z + dz);
+                if !is_named(below, &["farmland"]) {
+                    continue;
+                }
+                let mut bonus = if below.get("moisture") != Some("0") {
+                    3.0
+                } else {
+                    1.0
+                };
+                if dx != 0 || dz != 0 {
+                    bonus /= 4.0;
+                }
+                speed += bonus;
+            }
+        }
+
+        let block = state.block().first_state;
+        let mut same =
+            |dx: i32, dz: i32| level.get_block(x + dx, y, z + dz).block().first_state == block;
+        let row_x = same(-1, 0) || same(1, 0);
+        let row_z = same(0, -1) || same(0, 1);
+        let crowded = if row_x && row_z {
+            true
+        } else {
+            same(-1, -1) || same(1, -1) || same(1, 1) || same(-1, 1)
+        };
+        if crowded {
+            speed /= 2.0;
+        }
+        speed
+    }
+}
+
+impl BlockBehavior for Crop {
+    fn ticks_randomly(&self, state: BlockState) -> bool {
+        age(state) < self.max_age
+    }
+
+    fn random_tick(&self, level: &mut Level, pos: BlockPos, state: BlockState) {
+        let age = age(state);
+        if age >= self.max_age || level.brightness(pos) < 9 {
+            return;
+        }
+        let speed = self.growth_speed(level, pos, state);
+        let chance = (25.0 / speed) as u32 + 1;
+        if level.random().gen_range(0..chance) == 0 {
+            if let Ok(grown) = state.with("age", &(age + 1).to_string()) {
+                level.place_block(pos, grown);
+            }
+        }
+    }
+
+    fn neighbor_changed(&self, level: &mut Level, pos: BlockPos, _: BlockState, from: BlockPos) {
+        let (x, y, z) = pos;
+        if from == (x, y - 1, z) && !is_named(level.get_block(x, y - 1, z), &["farmland"]) {
+            level.place_block(pos, BlockState::AIR);
+        }
+    }
+}
+
+/// The farthest leaves stay from a log, which they decay at.
+const LEAVES_MAX_DISTANCE: u8 = 7;
+
+fn is_log(state: BlockState) -> bool {
+    let name = &state.block().name;
+    name.namespace() == McIdentifier::DEFAULT_NAMESPACE
+        && ["_log", "_wood", "_stem", "_hyphae"]
+            .iter()
+            .any(|s| name.name().ends_with(s))
+}
+
+/// Leaves keep their distance to the nearest log through other leaves up to
+/// date, and decay at random once they're too far unless a player placed
+/// them.
+pub struct Leaves;
+
+impl Leaves {
+    fn distance(state: BlockState) -> u8 {
+        state
+            .get("distance")
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(LEAVES_MAX_DISTANCE)
+    }
+
+    fn distance_at(level: &mut Level, (x, y, z): BlockPos) -> u8 {
+        let neighbors = [
+            (x - 1, y, z),
+            (x + 1, y, z),
+            (x, y - 1, z),
+            (x, y + 1, z),
+            (x, y, z - 1),
+            (x, y, z + 1),
+        ];
+        let mut distance = LEAVES_MAX_DISTANCE;
+        for (x, y, z) in neighbors.iter().copied() {
+            let neighbor = level.get_block(x, y, z);
+            let to_log = if is_log(neighbor) {
+                0
+            } else if Leaves::is_leaves(neighbor) {
+                Leaves::distance(neighbor)
+            } else {
+                continue;
+            };
+            distance = distance.min(to_log + 1);
+        }
+        distance
+    }
+
+    fn is_leaves(state: BlockState) -> bool {
+        state.block().name.name().ends_with("_leaves")
+    }
+}
+
+impl BlockBehavior for Leaves {
+    fn ticks_randomly(&self, state: BlockState) -> bool {
+        state.get("persistent") == Some("false") && Leaves::distance(state) == LEAVES_MAX_DISTANCE
+    }
+
+    fn random_tick(&self, level: &mut Level, pos: BlockPos, state: BlockState) {
+        if self.ticks_randomly(state) {
+            level.place_block(pos, BlockState::AIR);
+        }
+    }
+
+    fn scheduled_tick(&self, level: &mut Level, pos: BlockPos, state: BlockState) {
+        let distance = Leaves::distance_at(level, pos);
+        if let Ok(updated) = state.with("distance", &distance.to_string()) {
+            level.place_block(pos, updated);
+        }
+    }
+
+    fn placed(&self, level: &mut Level, pos: BlockPos, state: BlockState, old: BlockState) {
+        // Not when only the distance changed
+        if old.block().first_state != state.block().first_state {
+            level.schedule_tick(pos, state, 1, TickPriority::Normal);
+        }
+    }
+
+    fn neighbor_changed(&self, level: &mut Level, pos: BlockPos, state: BlockState, _: BlockPos) {
+        if Leaves::distance_at(level, pos) != Leaves::distance(state) {
+            level.schedule_tick(pos, state, 1, TickPriority::Normal);
+        }
+    }
+}
+
+/// Sand, gravel and concrete powder, which fall two ticks after the block
+/// under them goes.
+pub struct Falling;
+
+impl Falling {
+    const DELAY: i64 = 2;
+
+    /// Whether a falling block passes through `state`.
+    fn is_free(state: BlockState) -> bool {
+        state.is_air()
+            || is_named(
+                state,
+                &["water", "lava", "bubble_column", "fire", "soul_fire"],
+            )
+    }
+}
+
+impl BlockBehavior for Falling {
+    fn scheduled_tick(&self, level: &mut Level, (x, y, z): BlockPos, state: BlockState) {
+        let min_y = level.dimension().min_y;
+        let mut landing = y;
+        while landing > min_y && Falling::is_free(level.get_block(x, landing - 1, z)) {
+            landing -= 1;
+        }
+        if landing == y {
+            return;
+        }
+        level.place_block((x, y, z), BlockState::AIR);
+        // Blocks falling out of the world are gone
+        if landing > min_y || !Falling::is_free(level.get_block(x, min_y, z)) {
+            level.place_block((x, landing, z), state);
+        }
+    }
+
+    fn placed(&self, level: &mut Level, pos: BlockPos, state: BlockState, _: BlockState) {
+        level.schedule_tick(pos, state, Falling::DELAY, TickPriority::Normal);
+    }
+
+    fn neighbor_changed(&self, level: &mut Level, pos: BlockPos, state: BlockState, _: BlockPos) {
+        level.schedule_tick(pos, state, Falling::DELAY, TickPriority::Normal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::level::test_level as flat_level;
+
+    /// Dirt that turns coarse when it's randomly ticked.
+    struct Coarsening;
+
+    impl BlockBehavior for Coarsening {
+        fn ticks_randomly(&self, _: BlockState) -> bool {
+            true
+        }
+
+        fn random_tick(&self, level: &mut Level, pos: BlockPos, _: BlockState) {
+            level.place_block(pos, BlockState::from_name("coarse_dirt").unwrap());
+        }
+    }
+
+    /// A superflat level, grass at Y 3, with the chunks around the origin
+    /// loaded so the middle one gets random ticks.
+    fn test_level(behaviors: BlockBehaviors) -> Level {
+        let mut level = flat_level().with_behaviors(Arc::new(behaviors));
+        for x in -1..=1 {
+            for z in -1..=1 {
+                level.chunk(x, z);
+            }
+        }
+        level.set_random_tick_speed(0);
+        level
+    }
+
+    #[test]
+    fn test_behaviors() {
+        let mut behaviors = BlockBehaviors::vanilla();
+        let dirt = McIdentifier::minecraft("dirt").unwrap();
+        assert!(behaviors.register(&dirt, Arc::new(Coarsening)));
+        let mut level = test_level(behaviors);
+
+        // Sand falls onto the grass two ticks after it's placed, and the
+        // gravel on it follows
+        let sand = BlockState::from_name("sand").unwrap();
+        let gravel = BlockState::from_name("gravel").unwrap();
+        level.place_block((5, 14, 5), sand);
+        level.place_block((5, 15, 5), gravel);
+        level.tick();
+        assert_eq!(level.get_block(5, 14, 5), sand);
+        level.tick();
+        assert_eq!(level.get_block(5, 14, 5), BlockState::AIR);
+        assert_eq!(level.get_block(5, 4, 5), sand);
+        assert_eq!(level.get_block(5, 5, 5), gravel);
+        assert!(level.take_block_changes().contains(&(5, 5, 5)));
+
+        // Random ticks only reach chunks surrounded by loaded ones
+        let coarse = BlockState::from_name("coarse_dirt").unwrap();
+        let count = |level: &mut Level, x: i32| {
+            let mut count = 0;
+            for bx in 0..16 {
+                for bz in 0..16 {
+                    for y in 1..3 {
+                        count += (level.get_block(x * 16 + bx, y, bz) == coarse) as usize;
+                    }
+                }
+            }
+            count
+        };
+        level.tick();
+        assert_eq!(count(&mut level, 0), 0);
+        level.set_random_tick_speed(3);
+        for _ in 0..100 {
+            level.tick();
+        }
+        assert!(count(&mut level, 0) > 0);
+        assert_eq!(count(&mut level, 1), 0);
+        assert_eq!(level.time(), 103);
+    }
+
+    #[test]
+    fn test_crops() {
+        let mut level = test_level(BlockBehaviors::vanilla());
+        let farmland = BlockState::from_name("farmland").unwrap();
+        let wheat = BlockState::from_name("wheat").unwrap();
+        level.place_block((5, 3, 5), farmland);
+        level.place_block((5, 4, 5), wheat);
+
+        // Every block of the section is ticked about every fourth game tick
+        level.set_random_tick_speed(1024);
+        for _ in 0..2000 {
+            level.tick();
+            if age(level.get_block(5, 4, 5)) == 7 {
+                break;
+            }
+        }
+        let grown = level.get_block(5, 4, 5);
+        assert_eq!(grown, wheat.with("age", "7").unwrap());
+        assert!(!Crop { max_age: 7 }.ticks_randomly(grown));
+
+        // Without farmland under it the wheat breaks
+        level.place_block((5, 3, 5), BlockState::from_name("dirt").unwrap());
+        assert_eq!(level.get_block(5, 4, 5), BlockState::AIR);
+    }
+
+    #[test]
+    fn test_leaves() {
+        let mut level = test_level(BlockBehaviors::vanilla());
+        let log = BlockState::from_name("oak_log").unwrap();
+        let leaves = BlockState::from_name("oak_leaves").unwrap();
+        let persistent = leaves.with("persistent", "true").unwrap();
+        level.place_block((5, 4, 5), log);
+        level.place_block((6, 4, 5), leaves);
+        level.place_block((7, 4, 5), leaves);
+        level.place_block((10, 4, 10), persistent);
+
+        // Each leaf learns how far the log is a tick after it changes
+        level.tick();
+        level.tick();
+        assert_eq!(level.get_block(6, 4, 5).get("distance"), Some("1"));
+        assert_eq!(level.get_block(7, 4, 5).get("distance"), Some("2"));
+
+        // Without the log the leaves count up until they're too far
+        level.place_block((5, 4, 5), BlockState::AIR);
+        for _ in 0..20 {
+            level.tick();
+        }
+        assert_eq!(level.get_block(6, 4, 5), leaves);
+        assert_eq!(level.get_block(7, 4, 5), leaves);
+
+        // Then decay at random, unless a player placed them
+        level.set_random_tick_speed(1024);
+        for _ in 0..100 {
+            level.tick();
+        }
+        assert_eq!(level.get_block(6, 4, 5), BlockState::AIR);
+        assert_eq!(level.get_block(7, 4, 5), BlockState::AIR);
+        assert_eq!(level.get_block(10, 4, 10), persistent);
+    }
+}
@@ -0,0 +1,460 @@
+use nbt::Value::{Compound, Double, Float, Int};
+use yaml_rust::Yaml;
+
+use super::{
+    registry::{insert, insert_string, nbt_bool, ToNbt},
+    schema::{load_entries, parse_entries, EntryFields, RegistryError, RegistryErrors},
+};
+use crate::mc_types::McIdentifier;
+
+const MAX_COLOR: i64 = 0xffffff;
+
+/// World generation settings found in vanilla biome JSON. They aren't part
+/// of the codec, so they are accepted and dropped.
+const WORLDGEN_FIELDS: &[&str] = &[
+    "carvers",
+    "features",
+    "spawners",
+    "spawn_costs",
+    "creature_spawn_probability",
+    "player_spawnable",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeParticle {
+    pub particle_type: String,
+    pub probability: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoodSound {
+    pub sound: String,
+    pub tick_delay: i32,
+    pub block_search_extent: i32,
+    pub offset: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdditionsSound {
+    pub sound: String,
+    pub tick_chance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeMusic {
+    pub sound: String,
+    pub min_delay: i32,
+    pub max_delay: i32,
+    pub replace_current_music: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeEffects {
+    pub sky_color: i32,
+    pub water_fog_color: i32,
+    pub fog_color: i32,
+    pub water_color: i32,
+    pub foliage_color: Option<i32>,
+    pub grass_color: Option<i32>,
+    /// `none`, `dark_forest` or `swamp`.
+    pub grass_color_modifier: Option<String>,
+    pub particle: Option<BiomeParticle>,
+    pub ambient_sound: Option<String>,
+    pub mood_sound: Option<MoodSound>,
+    pub additions_sound: Option<AdditionsSound>,
+    pub music: Option<BiomeMusic>,
+}
+
+/// An element of the `minecraft:worldgen/biome` registry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    /// `none`, `rain` or `snow`. Read from `has_precipitation` and the
+    /// temperature in newer biome JSON.
+    pub precipitation: String,
+    pub temperature: f32,
+    pub downfall: f32,
+    pub category: String,
+    pub depth: Option<f32>,
+    pub scale: Option<f32>,
+    pub temperature_modifier: Option<String>,
+    pub effects: BiomeEffects,
+}
+
+impl ToNbt for BiomeParticle {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut options = nbt::Map::new();
+        insert_string(&mut options, "type", &self.particle_type);
+        let mut particle = nbt::Map::new();
+        insert(&mut particle, "probability", Float(self.probability));
+        insert(&mut particle, "options", Compound(options));
+        Compound(particle)
+    }
+}
+
+impl ToNbt for MoodSound {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut sound = nbt::Map::new();
+        insert_string(&mut sound, "sound", &self.sound);
+        insert(&mut sound, "tick_delay", Int(self.tick_delay));
+        insert(
+            &mut sound,
+            "block_search_extent",
+            Int(self.block_search_extent),
+        );
+        insert(&mut sound, "offset", Double(self.offset));
+        Compound(sound)
+    }
+}
+
+impl ToNbt for AdditionsSound {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut sound = nbt::Map::new();
+        insert_string(&mut sound, "sound", &self.sound);
+        insert(&mut sound, "tick_chance", Double(self.tick_chance));
+        Compound(sound)
+    }
+}
+
+impl ToNbt for BiomeMusic {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut music = nbt::Map::new();
+        insert_string(&mut music, "sound", &self.sound);
+        insert(&mut music, "min_delay", Int(self.min_delay));
+        insert(&mut music, "max_delay", Int(self.max_delay));
+        insert(
+            &mut music,
+            "replace_current_music",
+            nbt_bool(self.replace_current_music),
+        );
+        Compound(music)
+    }
+}
+
+impl ToNbt for BiomeEffects {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut effects = nbt::Map::new();
+        let e = &mut effects;
+        insert(e, "sky_color", Int(self.sky_color));
+        insert(e, "water_fog_color", Int(self.water_fog_color));
+        insert(e, "fog_color", Int(self.fog_color));
+        insert(e, "water_color", Int(self.water_color));
+        if let Some(color) = self.foliage_color {
+            insert(e, "foliage_color", Int(color));
+        }
+        if let Some(color) = self.grass_color {
+            insert(e, "grass_color", Int(color));
+        }
+        if let Some(modifier) = &self.grass_color_modifier {
+            insert_string(e, "grass_color_modifier", modifier);
+        }
+        if let Some(particle) = &self.particle {
+            insert(e, "particle", particle.to_nbt());
+        }
+        if let Some(sound) = &self.ambient_sound {
+            insert_string(e, "ambient_sound", sound);
+        }
+        if let Some(sound) = &self.mood_sound {
+            insert(e, "mood_sound", sound.to_nbt());
+        }
+        if let Some(sound) = &self.additions_sound {
+            insert(e, "additions_sound", sound.to_nbt());
+        }
+        if let Some(music) = &self.music {
+            insert(e, "music", music.to_nbt());
+        }
+        Compound(effects)
+    }
+}
+
+impl ToNbt for Biome {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut biome = nbt::Map::new();
+        let b = &mut biome;
+        insert_string(b, "precipitation", &self.precipitation);
+        insert(b, "temperature", Float(self.temperature));
+        insert(b, "downfall", Float(self.downfall));
+        insert_string(b, "category", &self.category);
+        if let Some(depth) = self.depth {
+            insert(b, "depth", Float(depth));
+        }
+        if let Some(scale) = self.scale {
+            insert(b, "scale", Float(scale));
+        }
+        if let Some(modifier) = &self.temperature_modifier {
+            insert_string(b, "temperature_modifier", modifier);
+        }
+        insert(b, "effects", self.effects.to_nbt());
+        Compound(biome)
+    }
+
+    /// Since 1.19.4 biomes only say whether it rains, and have no category,
+    /// depth or scale.
+    fn to_registry_data(&self) -> nbt::Value {
+        let mut biome = nbt::Map::new();
+        let b = &mut biome;
+        insert(
+            b,
+            "has_precipitation",
+            nbt_bool(self.precipitation != "none"),
+        );
+        insert(b, "temperature", Float(self.temperature));
+        insert(b, "downfall", Float(self.downfall));
+        if let Some(modifier) = &self.temperature_modifier {
+            insert_string(b, "temperature_modifier", modifier);
+        }
+        insert(b, "effects", self.effects.to_nbt());
+        Compound(biome)
+    }
+}
+
+/// Reads a nested mapping with `read` and checks it for unknown keys.
+fn nested<T>(
+    f: &mut EntryFields,
+    k: &str,
+    read: impl FnOnce(&mut EntryFields) -> Option<T>,
+) -> Option<T> {
+    let mut fields = f.nested(k)?;
+    let value = read(&mut fields);
+    fields.finish();
+    value
+}
+
+fn one_of(f: &mut EntryFields, k: &str, value: Option<String>, allowed: &[&str]) -> Option<String> {
+    let value = value?;
+    if !allowed.contains(&value.as_str()) {
+        f.error(k, &format!("{:?} is not one of {:?}", value, allowed));
+        return None;
+    }
+    Some(value)
+}
+
+fn color(f: &mut EntryFields, k: &str) -> Option<i32> {
+    f.int(k, None, 0, MAX_COLOR).map(|c| c as i32)
+}
+
+fn make_particle(f: &mut EntryFields) -> Option<BiomeParticle> {
+    // Datapacks nest the type in `options`, the YAML files write it directly.
+    let particle_type = if f.has("options") {
+        nested(f, "options", |f| f.identifier("type", None, false))
+    } else {
+        f.identifier("type", None, false)
+    };
+    let probability = f.float("probability", None, 0.0, 1.0);
+    Some(BiomeParticle {
+        particle_type: particle_type?,
+        probability: probability? as f32,
+    })
+}
+
+fn make_mood_sound(f: &mut EntryFields) -> Option<MoodSound> {
+    let sound = f.identifier("sound", None, false);
+    let tick_delay = f.int("tick_delay", Some(6000), 0, i32::MAX as i64);
+    let block_search_extent = f.int("block_search_extent", Some(8), 0, i32::MAX as i64);
+    let offset = f.float("offset", Some(2.0), 0.0, f64::MAX);
+    Some(MoodSound {
+        sound: sound?,
+        tick_delay: tick_delay? as i32,
+        block_search_extent: block_search_extent? as i32,
+        offset: offset?,
+    })
+}
+
+fn make_additions_sound(f: &mut EntryFields) -> Option<AdditionsSound> {
+    let sound = f.identifier("sound", None, false);
+    let tick_chance = f.float("tick_chance", None, 0.0, 1.0);
+    Some(AdditionsSound {
+        sound: sound?,
+        tick_chance: tick_chance?,
+    })
+}
+
+fn make_music(f: &mut EntryFields) -> Option<BiomeMusic> {
+    let sound = f.identifier("sound", None, false);
+    let min_delay = f.int("min_delay", Some(12000), 0, i32::MAX as i64);
+    let max_delay = f.int("max_delay", Some(24000), 0, i32::MAX as i64);
+    let replace_current_music = f.bool("replace_current_music", Some(true));
+    if let (Some(min), Some(max)) = (min_delay, max_delay) {
+        if min > max {
+            f.error("min_delay", "must not exceed max_delay");
+        }
+    }
+    Some(BiomeMusic {
+        sound: sound?,
+        min_delay: min_delay? as i32,
+        max_delay: max_delay? as i32,
+        replace_current_music: replace_current_music?,
+    })
+}
+
+fn make_biome_effects(f: &mut EntryFields) -> Option<BiomeEffects> {
+    let sky_color = color(f, "sky_color");
+    let water_fog_color = color(f, "water_fog_color");
+    let fog_color = color(f, "fog_color");
+    let water_color = color(f, "water_color");
+    let foliage_color = f.optional("foliage_color", color);
+    let grass_color = f.optional("grass_color", color);
+    let grass_color_modifier = f.optional("grass_color_modifier", |f, k| {
+        let value = f.string(k, None);
+        one_of(f, k, value, &["none", "dark_forest", "swamp"])
+    });
+    let particle = f.optional("particle", |f, k| nested(f, k, make_particle));
+    let ambient_sound = f.optional("ambient_sound", |f, k| f.identifier(k, None, false));
+    let mood_sound = f.optional("mood_sound", |f, k| nested(f, k, make_mood_sound));
+    let additions_sound = f.optional("additions_sound", |f, k| nested(f, k, make_additions_sound));
+    let music = f.optional("music", |f, k| nested(f, k, make_music));
+    Some(BiomeEffects {
+        sky_color: sky_color?,
+        water_fog_color: water_fog_color?,
+        fog_color: fog_color?,
+        water_color: water_color?,
+        foliage_color: foliage_color?,
+        grass_color: grass_color?,
+        grass_color_modifier: grass_color_modifier?,
+        particle: particle?,
+        ambient_sound: ambient_sound?,
+        mood_sound: mood_sound?,
+        additions_sound: additions_sound?,
+        music: music?,
+    })
+}
+
+fn make_biome(f: &mut EntryFields) -> Option<Biome> {
+    let temperature = f.float("temperature", None, -10.0, 10.0);
+    // Since 1.19.4 vanilla only says whether it rains or snows, and the
+    // client picks snow where it is cold enough.
+    let has_precipitation = f.optional("has_precipitation", |f, k| f.bool(k, None));
+    let precipitation = match has_precipitation {
+        Some(Some(has)) if !f.has("precipitation") => Some(match (has, temperature) {
+            (false, _) => "none".to_owned(),
+            (true, Some(t)) if t < 0.15 => "snow".to_owned(),
+            (true, _) => "rain".to_owned(),
+        }),
+        _ => {
+            let precipitation = f.string("precipitation", Some("rain"));
+            one_of(f, "precipitation", precipitation, &["none", "rain", "snow"])
+        }
+    };
+    let downfall = f.float("downfall", None, 0.0, 1.0);
+    let category = f.string("category", Some("none"));
+    let depth = f.optional("depth", |f, k| f.float(k, None, -10.0, 10.0));
+    let scale = f.optional("scale", |f, k| f.float(k, None, -10.0, 10.0));
+    let temperature_modifier = f.optional("temperature_modifier", |f, k| {
+        let value = f.string(k, None);
+        one_of(f, k, value, &["none", "frozen"])
+    });
+    let effects = nested(f, "effects", make_biome_effects);
+    if !f.has("effects") {
+        f.error("effects", "missing required field");
+    }
+    for k in WORLDGEN_FIELDS {
+        f.ignore(k);
+    }
+    Some(Biome {
+        precipitation: precipitation?,
+        temperature: temperature? as f32,
+        downfall: downfall? as f32,
+        category: category?,
+        depth: depth?.map(|v| v as f32),
+        scale: scale?.map(|v| v as f32),
+        temperature_modifier: temperature_modifier?,
+        effects: effects?,
+    })
+}
+
+/// The shipped biomes, for `RegistrySource::Embedded`.
+pub const EMBEDDED_BIOMES: &str = include_str!("biomes.yaml");
+
+/// Loads and validates every biome in a YAML file. Biomes are returned in
+/// file order, which is the order their IDs are assigned in, so appending a
+/// biome never changes the IDs of the ones before it.
+pub fn load_biomes(file: &str) -> Result<Vec<(McIdentifier, Biome)>, RegistryErrors> {
+    let entries = load_entries(file)?;
+    biomes_from_entries(file, &entries)
+}
+
+/// Like `load_biomes`, for YAML that is already in memory.
+pub fn parse_biomes(file: &str, text: &str) -> Result<Vec<(McIdentifier, Biome)>, RegistryErrors> {
+    let entries = parse_entries(file, text)?;
+    biomes_from_entries(file, &entries)
+}
+
+pub(super) fn biomes_from_entries(
+    file: &str,
+    entries: &[(String, Yaml)],
+) -> Result<Vec<(McIdentifier, Biome)>, RegistryErrors> {
+    let mut errors = Vec::new();
+    let mut biomes = Vec::new();
+    for (name, doc) in entries {
+        let id = match name.parse::<McIdentifier>() {
+            Ok(id) => id,
+            Err(e) => {
+                errors.push(RegistryError::Field {
+                    file: file.to_owned(),
+                    entry: name.clone(),
+                    field: "".to_owned(),
+                    message: format!("invalid biome name: {}", e),
+                });
+                continue;
+            }
+        };
+        let mut fields = EntryFields::new(file, name, doc, &mut errors);
+        let biome = make_biome(&mut fields);
+        fields.finish();
+        if let Some(biome) = biome {
+            biomes.push((id, biome));
+        }
+    }
+    if errors.is_empty() {
+        Ok(biomes)
+    } else {
+        Err(RegistryErrors(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_biomes() {
+        let biomes = load_biomes("src/world/biomes.yaml").unwrap();
+        assert_eq!(biomes[0].0.to_string(), "minecraft:ocean");
+        assert_eq!(biomes[1].0.to_string(), "minecraft:plains");
+
+        let (_, deltas) = &biomes[3];
+        assert_eq!(deltas.precipitation, "none");
+        assert_eq!(deltas.effects.fog_color, 6840176);
+        assert_eq!(
+            deltas.effects.particle.as_ref().unwrap().particle_type,
+            "minecraft:white_ash"
+        );
+        assert_eq!(deltas.effects.music.as_ref().unwrap().max_delay, 24000);
+        assert!(deltas.effects.grass_color_modifier.is_none());
+
+        let effects = match deltas.to_nbt() {
+            Compound(mut map) => map.remove("effects").unwrap(),
+            _ => panic!(),
+        };
+        assert!(matches!(effects, Compound(e) if e.contains_key("additions_sound")));
+    }
+
+    #[test]
+    fn test_biome_errors() {
+        let errors = parse_biomes(
+            "test.yaml",
+            "zipper:bad:\n  precipitation: hail\n  temperature: 0.5\n  downfall: 0.5\n  \
+             effects:\n    sky_color: 1\n    water_fog_color: 1\n    fog_color: 1\n    \
+             water_color: -1\n    music:\n      sound: minecraft:x\n      volume: 1\n",
+        )
+        .unwrap_err();
+        let messages: Vec<String> = errors.0.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "test.yaml: zipper:bad: precipitation: \"hail\" is not one of [\"none\", \"rain\", \"snow\"]",
+                "test.yaml: zipper:bad.effects: water_color: -1 is not in 0..=16777215",
+                "test.yaml: zipper:bad.effects.music: volume: unknown field",
+            ]
+        );
+    }
+}
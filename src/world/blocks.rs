@@ -0,0 +1,733 @@
+//! Global block state IDs from the vanilla `blocks.json` report.
+//!
+//! The report is written by the vanilla server with
+//! `java -DbundlerMainClass=net.minecraft.data.Main -jar server.jar --reports`.
+//! It lists every block with its properties and the ID of each combination
+//! of property values. Within a block the IDs are consecutive, with the last
+//! property (in name order) changing fastest, so a state's ID can be computed
+//! from the indices of its values.
+//!
+//! The registry is installed once at startup. Without a report the server
+//! uses the excerpt in `blocks.json` next to this file, which holds the 1.18
+//! states from air to gravel. Tests add the blocks in `test_blocks.json`
+//! after it.
+//!
+//! The registry is the 1.18 one. 1.20.5 numbers states differently, so its
+//! clients get IDs from `ClientStateIds`, matched by block name and
+//! properties against a 1.20.5 report.
+
+use std::{collections::HashMap, fmt, io, str::FromStr, sync::OnceLock};
+
+use serde_json::Value;
+
+use super::schema::{RegistryError, RegistryErrors};
+use crate::{mc_types::McIdentifier, packet::CONFIGURATION_PROTOCOL_VERSION};
+
+/// The shipped excerpt of the report, used when none is configured.
+pub const EMBEDDED_BLOCKS: &str = include_str!("blocks.json");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockProperty {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub name: McIdentifier,
+    /// Sorted by name, as in the report.
+    pub properties: Vec<BlockProperty>,
+    pub first_state: i32,
+    pub default_state: i32,
+}
+
+impl Block {
+    pub fn state_count(&self) -> i32 {
+        self.properties
+            .iter()
+            .map(|p| p.values.len() as i32)
+            .product()
+    }
+
+    fn property_index(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|p| p.name == name)
+    }
+
+    /// How far apart the IDs of neighbouring values of a property are.
+    fn stride(&self, index: usize) -> i32 {
+        self.properties[index + 1..]
+            .iter()
+            .map(|p| p.values.len() as i32)
+            .product()
+    }
+
+    /// The index of each property's value in a state of this block.
+    fn value_indices(&self, id: i32) -> impl Iterator<Item = usize> + '_ {
+        let offset = id - self.first_state;
+        (0..self.properties.len()).map(move |i| {
+            let length = self.properties[i].values.len() as i32;
+            ((offset / self.stride(i)) % length) as usize
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BlockRegistry {
+    blocks: Vec<Block>,
+    by_name: HashMap<McIdentifier, usize>,
+    /// The block of every state ID.
+    state_blocks: Vec<usize>,
+}
+
+impl BlockRegistry {
+    /// Reads a `blocks.json` report. `file` is only used in error messages.
+    pub fn from_report(file: &str, text: &str) -> Result<BlockRegistry, RegistryErrors> {
+        let report: serde_json::Map<String, Value> =
+            serde_json::from_str(text).map_err(|e| RegistryError::Syntax {
+                file: file.to_owned(),
+                message: e.to_string(),
+            })?;
+        let mut errors = Vec::new();
+        let mut blocks = Vec::new();
+        for (name, block) in &report {
+            match read_block(name, block) {
+                Ok(block) => blocks.push(block),
+                Err(message) => errors.push(RegistryError::Field {
+                    file: file.to_owned(),
+                    entry: name.clone(),
+                    field: "".to_owned(),
+                    message,
+                }),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(RegistryErrors(errors));
+        }
+        blocks.sort_by_key(|b| b.first_state);
+
+        let mut registry = BlockRegistry::default();
+        for (index, block) in blocks.into_iter().enumerate() {
+            if block.first_state != registry.state_blocks.len() as i32 {
+                errors.push(RegistryError::Field {
+                    file: file.to_owned(),
+                    entry: block.name.to_string(),
+                    field: "states".to_owned(),
+                    message: format!(
+                        "IDs start at {}, expected {}",
+                        block.first_state,
+                        registry.state_blocks.len()
+                    ),
+                });
+            }
+            let count = block.state_count() as usize;
+            registry
+                .state_blocks
+                .extend(std::iter::repeat_n(index, count));
+            registry.by_name.insert(block.name.clone(), index);
+            registry.blocks.push(block);
+        }
+        if errors.is_empty() {
+            Ok(registry)
+        } else {
+            Err(RegistryErrors(errors))
+        }
+    }
+
+    /// Reads the report at `file`.
+    pub fn load(file: &str) -> Result<BlockRegistry, RegistryErrors> {
+        let text = std::fs::read_to_string(file).map_err(|error| RegistryError::Io {
+            file: file.to_owned(),
+            error,
+        })?;
+        BlockRegistry::from_report(file, &text)
+    }
+
+    pub fn embedded() -> BlockRegistry {
+        BlockRegistry::from_report("<embedded blocks.json>", EMBEDDED_BLOCKS).unwrap()
+    }
+
+    pub fn get(&self, name: &McIdentifier) -> Option<&Block> {
+        self.by_name.get(name).map(|&i| &self.blocks[i])
+    }
+
+    /// The block a state ID belongs to.
+    pub fn block_of(&self, id: i32) -> Option<&Block> {
+        if id < 0 {
+            return None;
+        }
+        self.state_blocks.get(id as usize).map(|&i| &self.blocks[i])
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.state_blocks.len()
+    }
+
+    /// Blocks in state ID order.
+    pub fn iter(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter()
+    }
+}
+
+fn read_block(name: &str, block: &Value) -> Result<Block, String> {
+    let name: McIdentifier = name.parse().map_err(|e| format!("invalid name: {}", e))?;
+    let mut properties = Vec::new();
+    if let Some(map) = block.get("properties") {
+        let map = map.as_object().ok_or("properties must be an object")?;
+        for (property, values) in map {
+            let values = values
+                .as_array()
+                .and_then(|v| v.iter().map(|v| v.as_str().map(str::to_owned)).collect())
+                .ok_or_else(|| format!("values of {} must be strings", property))?;
+            properties.push(BlockProperty {
+                name: property.clone(),
+                values,
+            });
+        }
+    }
+    let states = block
+        .get("states")
+        .and_then(Value::as_array)
+        .ok_or("missing states")?;
+    let mut block = Block {
+        name,
+        properties,
+        first_state: i32::MAX,
+        default_state: -1,
+    };
+    if states.len() as i32 != block.state_count() {
+        return Err(format!(
+            "{} states for {} combinations of properties",
+            states.len(),
+            block.state_count()
+        ));
+    }
+
+    let mut ids = Vec::new();
+    for state in states {
+        let id = state
+            .get("id")
+            .and_then(Value::as_i64)
+            .ok_or("state without an id")? as i32;
+        block.first_state = block.first_state.min(id);
+        if state.get("default").and_then(Value::as_bool) == Some(true) {
+            block.default_state = id;
+        }
+        ids.push((id, state));
+    }
+    if block.default_state < 0 {
+        return Err("no default state".to_owned());
+    }
+    // Check that every state has the ID its property values imply
+    for (id, state) in ids {
+        let mut expected = block.first_state;
+        for (i, property) in block.properties.iter().enumerate() {
+            let value = state
+                .get("properties")
+                .and_then(|p| p.get(&property.name))
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("state {} has no {}", id, property.name))?;
+            let index = property
+                .values
+                .iter()
+                .position(|v| v == value)
+                .ok_or_else(|| format!("state {} has unknown {}={}", id, property.name, value))?;
+            expected += index as i32 * block.stride(i);
+        }
+        if id != expected {
+            return Err(format!("state {} should have ID {}", id, expected));
+        }
+    }
+    Ok(block)
+}
+
+/// Blocks renamed since 1.18, by their 1.18 name.
+const RENAMED_BLOCKS: [(&str, &str); 1] = [("minecraft:grass", "minecraft:short_grass")];
+
+/// The state IDs a client uses, for each state of the installed registry.
+#[derive(Debug)]
+pub struct ClientStateIds {
+    /// `None` when they are the installed registry's own.
+    ids: Option<Vec<i32>>,
+    state_count: usize,
+}
+
+impl ClientStateIds {
+    /// The IDs of a client whose states `client` lists. Properties the
+    /// client's block doesn't have are dropped and those it added take
+    /// their default; blocks it doesn't have at all become air.
+    pub fn matching(server: &BlockRegistry, client: &BlockRegistry) -> ClientStateIds {
+        let mut ids = Vec::with_capacity(server.state_count());
+        for block in server.iter() {
+            let name = RENAMED_BLOCKS
+                .iter()
+                .find(|(old, _)| block.name.to_string() == *old)
+                .and_then(|(_, new)| new.parse().ok())
+                .unwrap_or_else(|| block.name.clone());
+            let target = match client.get(&name) {
+                Some(target) => target,
+                None => {
+                    ids.extend((0..block.state_count()).map(|_| 0));
+                    continue;
+                }
+            };
+            for id in block.first_state..block.first_state + block.state_count() {
+                let mut indices: Vec<usize> = target.value_indices(target.default_state).collect();
+                for (property, index) in block.properties.iter().zip(block.value_indices(id)) {
+                    let value = &property.values[index];
+                    let found = target.property_index(&property.name).and_then(|i| {
+                        let v = target.properties[i]
+                            .values
+                            .iter()
+                            .position(|v| v == value)?;
+                        Some((i, v))
+                    });
+                    if let Some((i, v)) = found {
+                        indices[i] = v;
+                    }
+                }
+                let offset: i32 = indices
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &v)| v as i32 * target.stride(i))
+                    .sum();
+                ids.push(target.first_state + offset);
+            }
+        }
+        ClientStateIds {
+            ids: Some(ids),
+            state_count: client.state_count(),
+        }
+    }
+
+    /// The ID of a state of the installed registry.
+    pub fn get(&self, id: i32) -> i32 {
+        match &self.ids {
+            Some(ids) => ids.get(id as usize).copied().unwrap_or(0),
+            None => id,
+        }
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.state_count
+    }
+}
+
+static BLOCKS: OnceLock<BlockRegistry> = OnceLock::new();
+static SERVER_STATE_IDS: OnceLock<ClientStateIds> = OnceLock::new();
+static CONFIGURED_STATE_IDS: OnceLock<ClientStateIds> = OnceLock::new();
+
+/// Makes `ids` the ones 1.20.5 clients get. Fails if some are already
+/// installed.
+pub fn install_configured_state_ids(ids: ClientStateIds) -> Result<(), ClientStateIds> {
+    CONFIGURED_STATE_IDS.set(ids)
+}
+
+/// The state IDs clients of `protocol_version` use, `None` for 1.20.5
+/// without `install_configured_state_ids`.
+pub fn client_state_ids(protocol_version: i32) -> Option<&'static ClientStateIds> {
+    if protocol_version >= CONFIGURATION_PROTOCOL_VERSION {
+        return CONFIGURED_STATE_IDS.get();
+    }
+    Some(SERVER_STATE_IDS.get_or_init(|| ClientStateIds {
+        ids: None,
+        state_count: blocks().state_count(),
+    }))
+}
+
+/// Makes `registry` the one used by `BlockState`. Fails if a registry is
+/// already in use.
+pub fn install(registry: BlockRegistry) -> Result<(), BlockRegistry> {
+    BLOCKS.set(registry)
+}
+
+/// The installed registry, or the embedded excerpt if none was installed.
+pub fn blocks() -> &'static BlockRegistry {
+    BLOCKS.get_or_init(default_registry)
+}
+
+#[cfg(not(test))]
+fn default_registry() -> BlockRegistry {
+    BlockRegistry::embedded()
+}
+
+/// The excerpt followed by the blocks that behaviour tests need, which get
+/// the IDs after gravel instead of their vanilla ones.
+#[cfg(test)]
+fn default_registry() -> BlockRegistry {
+    let mut report: serde_json::Map<String, Value> = serde_json::from_str(EMBEDDED_BLOCKS).unwrap();
+    let extra: serde_json::Map<String, Value> =
+        serde_json::from_str(include_str!("test_blocks.json")).unwrap();
+    report.extend(extra);
+    BlockRegistry::from_report("test_blocks.json", &Value::Object(report).to_string()).unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockStateError {
+    UnknownBlock(String),
+    UnknownProperty {
+        block: String,
+        property: String,
+    },
+    InvalidValue {
+        block: String,
+        property: String,
+        value: String,
+    },
+    Syntax(String),
+}
+
+impl fmt::Display for BlockStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockStateError::UnknownBlock(block) => write!(f, "unknown block {}", block),
+            BlockStateError::UnknownProperty { block, property } => {
+                write!(f, "{} has no property {}", block, property)
+            }
+            BlockStateError::InvalidValue {
+                block,
+                property,
+                value,
+            } => write!(f, "{} can't have {}={}", block, property, value),
+            BlockStateError::Syntax(s) => write!(f, "invalid block state {:?}", s),
+        }
+    }
+}
+
+impl std::error::Error for BlockStateError {}
+
+impl From<BlockStateError> for io::Error {
+    fn from(e: BlockStateError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// A global block state ID from the installed registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockState(i32);
+
+impl BlockState {
+    pub const AIR: BlockState = BlockState(0);
+
+    /// The default state of a block.
+    pub fn from_name(name: &str) -> Result<BlockState, BlockStateError> {
+        let block = name
+            .parse()
+            .ok()
+            .and_then(|name| blocks().get(&name))
+            .ok_or_else(|| BlockStateError::UnknownBlock(name.to_owned()))?;
+        Ok(BlockState(block.default_state))
+    }
+
+    pub fn from_id(id: i32) -> Option<BlockState> {
+        blocks().block_of(id).map(|_| BlockState(id))
+    }
+
+    pub fn id(self) -> i32 {
+        self.0
+    }
+
+    pub fn block(self) -> &'static Block {
+        blocks().block_of(self.0).unwrap()
+    }
+
+    pub fn is_air(self) -> bool {
+        let name = &self.block().name;
+        name.namespace() == McIdentifier::DEFAULT_NAMESPACE
+            && matches!(name.name(), "air" | "cave_air" | "void_air")
+    }
+
+    /// Whether the block holds water or lava, including waterlogged blocks.
+    pub fn is_fluid(self) -> bool {
+        let name = &self.block().name;
+        let vanilla = name.namespace() == McIdentifier::DEFAULT_NAMESPACE;
+        (vanilla && matches!(name.name(), "water" | "lava" | "bubble_column"))
+            || self.get("waterlogged") == Some("true")
+    }
+
+    /// Whether entities collide with the block. Air, plants, torches and
+    /// other decorations, cobwebs, snow layers, fire and fluids don't.
+    pub fn blocks_motion(self) -> bool {
+        if self.is_air() {
+            return false;
+        }
+        let name = &self.block().name;
+        if name.namespace() != McIdentifier::DEFAULT_NAMESPACE {
+            return true;
+        }
+        let name = name.name();
+        let passable = matches!(
+            name,
+            "water"
+                | "lava"
+                | "bubble_column"
+                | "grass"
+                | "fern"
+                | "tall_grass"
+                | "large_fern"
+                | "dead_bush"
+                | "seagrass"
+                | "tall_seagrass"
+                | "kelp"
+                | "kelp_plant"
+                | "dandelion"
+                | "poppy"
+                | "blue_orchid"
+                | "allium"
+                | "azure_bluet"
+                | "oxeye_daisy"
+                | "cornflower"
+                | "lily_of_the_valley"
+                | "wither_rose"
+                | "sunflower"
+                | "lilac"
+                | "rose_bush"
+                | "peony"
+                | "brown_mushroom"
+                | "red_mushroom"
+                | "sugar_cane"
+                | "vine"
+                | "torch"
+                | "wall_torch"
+                | "redstone_wire"
+                | "tripwire"
+                | "lever"
+                | "fire"
+                | "soul_fire"
+                | "cobweb"
+                | "snow"
+                | "wheat"
+                | "carrots"
+                | "potatoes"
+                | "beetroots"
+                | "nether_portal"
+                | "end_portal"
+                | "light"
+                | "structure_void"
+        );
+        let passable_kinds = [
+            "_sapling",
+            "_tulip",
+            "_torch",
+            "rail",
+            "_button",
+            "_pressure_plate",
+            "_carpet",
+            "_banner",
+        ];
+        !passable && !passable_kinds.iter().any(|s| name.ends_with(s))
+    }
+
+    /// This state with one property changed.
+    pub fn with(self, property: &str, value: &str) -> Result<BlockState, BlockStateError> {
+        let block = self.block();
+        let index =
+            block
+                .property_index(property)
+                .ok_or_else(|| BlockStateError::UnknownProperty {
+                    block: block.name.to_string(),
+                    property: property.to_owned(),
+                })?;
+        let values = &block.properties[index].values;
+        let new = values.iter().position(|v| v == value).ok_or_else(|| {
+            BlockStateError::InvalidValue {
+                block: block.name.to_string(),
+                property: property.to_owned(),
+                value: value.to_owned(),
+            }
+        })?;
+        let old = block.value_indices(self.0).nth(index).unwrap();
+        let stride = block.stride(index);
+        Ok(BlockState(self.0 + (new as i32 - old as i32) * stride))
+    }
+
+    pub fn get(self, property: &str) -> Option<&'static str> {
+        let block = self.block();
+        let index = block.property_index(property)?;
+        let value = block.value_indices(self.0).nth(index)?;
+        Some(&block.properties[index].values[value])
+    }
+
+    /// Property names and values, sorted by name.
+    pub fn properties(self) -> impl Iterator<Item = (&'static str, &'static str)> {
+        let block = self.block();
+        block
+            .properties
+            .iter()
+            .zip(block.value_indices(self.0))
+            .map(|(p, i)| (p.name.as_str(), p.values[i].as_str()))
+    }
+}
+
+impl Default for BlockState {
+    fn default() -> Self {
+        BlockState::AIR
+    }
+}
+
+/// Formats as `minecraft:oak_stairs[facing=north,half=bottom,...]`.
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.block().name)?;
+        let mut properties = self.properties().peekable();
+        if properties.peek().is_some() {
+            write!(f, "[")?;
+            for (i, (name, value)) in properties.enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{}={}", name, value)?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the `Display` format. Properties that aren't given keep their
+/// default values.
+impl FromStr for BlockState {
+    type Err = BlockStateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, properties) = match s.split_once('[') {
+            Some((name, rest)) => {
+                let rest = rest
+                    .strip_suffix(']')
+                    .ok_or_else(|| BlockStateError::Syntax(s.to_owned()))?;
+                (name, rest)
+            }
+            None => (s, ""),
+        };
+        let mut state = BlockState::from_name(name)?;
+        for pair in properties.split(',').filter(|p| !p.is_empty()) {
+            let (property, value) = pair
+                .split_once('=')
+                .ok_or_else(|| BlockStateError::Syntax(s.to_owned()))?;
+            state = state.with(property.trim(), value.trim())?;
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAIRS: &str = r#"{
+        "minecraft:air": {"states": [{"id": 0, "default": true}]},
+        "minecraft:oak_stairs": {
+            "properties": {"facing": ["north", "south"], "half": ["top", "bottom"]},
+            "states": [
+                {"id": 1, "properties": {"facing": "north", "half": "top"}},
+                {"id": 2, "properties": {"facing": "north", "half": "bottom"}, "default": true},
+                {"id": 3, "properties": {"facing": "south", "half": "top"}},
+                {"id": 4, "properties": {"facing": "south", "half": "bottom"}}
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn test_report() {
+        let registry = BlockRegistry::from_report("test.json", STAIRS).unwrap();
+        assert_eq!(registry.state_count(), 5);
+        let stairs = registry.block_of(3).unwrap();
+        assert_eq!(stairs.name.to_string(), "minecraft:oak_stairs");
+        assert_eq!(stairs.default_state, 2);
+        assert_eq!(stairs.value_indices(3).collect::<Vec<_>>(), vec![1, 0]);
+
+        let bad = STAIRS.replace("\"id\": 4", "\"id\": 5");
+        let errors = BlockRegistry::from_report("test.json", &bad).unwrap_err();
+        assert_eq!(
+            errors.to_string(),
+            "test.json: minecraft:oak_stairs: : state 5 should have ID 4"
+        );
+    }
+
+    #[test]
+    fn test_client_state_ids() {
+        let server = BlockRegistry::from_report("test.json", STAIRS).unwrap();
+        // Grass and waterlogging came later, and half lost its "top" value
+        let client = BlockRegistry::from_report(
+            "client.json",
+            r#"{
+                "minecraft:air": {"states": [{"id": 0, "default": true}]},
+                "minecraft:short_grass": {"states": [{"id": 1, "default": true}]},
+                "minecraft:oak_stairs": {
+                    "properties": {
+                        "facing": ["north", "south"],
+                        "half": ["bottom"],
+                        "waterlogged": ["true", "false"]
+                    },
+                    "states": [
+                        {"id": 2, "properties":
+                            {"facing": "north", "half": "bottom", "waterlogged": "true"}},
+                        {"id": 3, "properties":
+                            {"facing": "north", "half": "bottom", "waterlogged": "false"},
+                            "default": true},
+                        {"id": 4, "properties":
+                            {"facing": "south", "half": "bottom", "waterlogged": "true"}},
+                        {"id": 5, "properties":
+                            {"facing": "south", "half": "bottom", "waterlogged": "false"}}
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+        let ids = ClientStateIds::matching(&server, &client);
+        assert_eq!(ids.state_count(), 6);
+        let mapped: Vec<i32> = (0..5).map(|id| ids.get(id)).collect();
+        assert_eq!(mapped, vec![0, 3, 3, 5, 5]);
+
+        let lone = r#"{"minecraft:air": {"states": [{"id": 0, "default": true}]},
+            "minecraft:grass": {"states": [{"id": 1, "default": true}]}}"#;
+        let server = BlockRegistry::from_report("test.json", lone).unwrap();
+        assert_eq!(ClientStateIds::matching(&server, &client).get(1), 1);
+
+        assert_eq!(client_state_ids(757).unwrap().get(9), 9);
+    }
+
+    #[test]
+    fn test_block_state() {
+        let stone = BlockState::from_name("minecraft:stone").unwrap();
+        assert_eq!(stone.id(), 1);
+        assert!(BlockState::AIR.is_air() && !stone.is_air());
+
+        let grass = BlockState::from_name("grass_block").unwrap();
+        assert_eq!(grass.id(), 9);
+        let snowy = grass.with("snowy", "true").unwrap();
+        assert_eq!(snowy.id(), 8);
+        assert_eq!(snowy.get("snowy"), Some("true"));
+        assert_eq!(snowy.to_string(), "minecraft:grass_block[snowy=true]");
+        assert_eq!(
+            "minecraft:water[level=3]"
+                .parse::<BlockState>()
+                .unwrap()
+                .id(),
+            37
+        );
+
+        assert_eq!(
+            grass.with("snowy", "maybe"),
+            Err(BlockStateError::InvalidValue {
+                block: "minecraft:grass_block".to_owned(),
+                property: "snowy".to_owned(),
+                value: "maybe".to_owned(),
+            })
+        );
+        assert!(matches!(
+            BlockState::from_name("minecraft:unobtainium"),
+            Err(BlockStateError::UnknownBlock(_))
+        ));
+        let embedded = BlockRegistry::embedded();
+        assert_eq!(embedded.block_of(68).unwrap().name.name(), "gravel");
+        assert_eq!(embedded.block_of(69), None);
+        assert_eq!(
+            BlockState::from_id(69).unwrap().block().name.name(),
+            "oak_log"
+        );
+    }
+}
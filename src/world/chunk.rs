@@ -0,0 +1,1020 @@
+//! Chunk columns and their Chunk Data and Update Light packets (1.18 and
+//! 1.20.5).
+//!
+//! A column is a stack of 16x16x16 sections covering the dimension's
+//! `min_y` and `height`. Each section stores its block states and its 4x4x4
+//! biome cells in paletted containers, which is also how they are sent.
+//! Light arrays cover one extra section below and above the column.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    mc_types::ext::{McReadExt, McWriteExt},
+    packet::CONFIGURATION_PROTOCOL_VERSION,
+};
+
+use super::{
+    blocks::{blocks, BlockState, ClientStateIds},
+    dimensions::DimensionType,
+    registry::NbtMap,
+};
+
+pub const SECTION_SIZE: usize = 16;
+pub const SECTION_BLOCKS: usize = 4096;
+pub const SECTION_BIOMES: usize = 64;
+pub const LIGHT_ARRAY_SIZE: usize = 2048;
+
+/// The number of bits needed to store `count` different values.
+pub fn bits_for(count: usize) -> u8 {
+    (usize::BITS - count.saturating_sub(1).leading_zeros()) as u8
+}
+
+/// Fixed width values packed into longs, without spanning two longs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitStorage {
+    bits: u8,
+    size: usize,
+    data: Vec<u64>,
+}
+
+impl BitStorage {
+    /// `size` zeroes of `bits` bits each.
+    pub fn new(bits: u8, size: usize) -> BitStorage {
+        let longs = match bits {
+            0 => 0,
+            _ => {
+                let per_long = 64 / bits as usize;
+                size.div_ceil(per_long)
+            }
+        };
+        BitStorage {
+            bits,
+            size,
+            data: vec![0; longs],
+        }
+    }
+
+    /// Wraps packed longs, as found in a packet or a saved chunk.
+    pub fn from_data(bits: u8, size: usize, data: Vec<u64>) -> Option<BitStorage> {
+        let storage = BitStorage::new(bits, size);
+        if data.len() != storage.data.len() {
+            return None;
+        }
+        Some(BitStorage { data, ..storage })
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn data(&self) -> &[u64] {
+        &self.data
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let per_long = 64 / self.bits as usize;
+        (index / per_long, (index % per_long) * self.bits as usize)
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        assert!(index < self.size);
+        if self.bits == 0 {
+            return 0;
+        }
+        let (long, shift) = self.locate(index);
+        (self.data[long] >> shift) & self.mask()
+    }
+
+    pub fn set(&mut self, index: usize, value: u64) {
+        assert!(index < self.size);
+        if self.bits == 0 {
+            return;
+        }
+        let (long, shift) = self.locate(index);
+        let mask = self.mask();
+        self.data[long] = (self.data[long] & !(mask << shift)) | ((value & mask) << shift);
+    }
+
+    pub fn write_to(&self, buf: &mut impl Write) -> io::Result<usize> {
+        let mut count = buf.write_mc_varint(self.data.len() as i32)?;
+        for &long in &self.data {
+            count += buf.write_mc_long(long as i64)?;
+        }
+        Ok(count)
+    }
+}
+
+/// How many entries a paletted container holds and how wide they get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteKind {
+    pub size: usize,
+    pub min_bits: u8,
+    pub max_bits: u8,
+    /// Bits per entry once the palette is dropped and global IDs are stored.
+    pub direct_bits: u8,
+}
+
+impl PaletteKind {
+    pub fn blocks(state_count: usize) -> PaletteKind {
+        PaletteKind {
+            size: SECTION_BLOCKS,
+            min_bits: 4,
+            max_bits: 8,
+            direct_bits: bits_for(state_count),
+        }
+    }
+
+    pub fn biomes(biome_count: usize) -> PaletteKind {
+        PaletteKind {
+            size: SECTION_BIOMES,
+            min_bits: 1,
+            max_bits: 3,
+            direct_bits: bits_for(biome_count),
+        }
+    }
+
+    /// `None` means that the palette is too long and IDs are stored directly.
+    fn bits_for_palette(&self, length: usize) -> Option<u8> {
+        match length {
+            0 | 1 => Some(0),
+            _ => Some(bits_for(length).max(self.min_bits)).filter(|&b| b <= self.max_bits),
+        }
+    }
+}
+
+/// Global IDs stored as indices into a palette of the IDs in use, or
+/// directly once there are too many different ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PalettedContainer {
+    kind: PaletteKind,
+    /// Empty when IDs are stored directly.
+    palette: Vec<i32>,
+    storage: BitStorage,
+}
+
+impl PalettedContainer {
+    /// A container holding only `value`.
+    pub fn new(kind: PaletteKind, value: i32) -> PalettedContainer {
+        PalettedContainer {
+            kind,
+            palette: vec![value],
+            storage: BitStorage::new(0, kind.size),
+        }
+    }
+
+    pub fn kind(&self) -> PaletteKind {
+        self.kind
+    }
+
+    fn is_direct(&self) -> bool {
+        self.palette.is_empty()
+    }
+
+    /// The palette, or `None` if IDs are stored directly.
+    pub fn palette(&self) -> Option<&[i32]> {
+        Some(&self.palette[..]).filter(|p| !p.is_empty())
+    }
+
+    pub fn storage(&self) -> &BitStorage {
+        &self.storage
+    }
+
+    pub fn get(&self, index: usize) -> i32 {
+        let value = self.storage.get(index);
+        if self.is_direct() {
+            value as i32
+        } else {
+            self.palette[value as usize]
+        }
+    }
+
+    /// Sets an entry and returns the previous one.
+    pub fn set(&mut self, index: usize, value: i32) -> i32 {
+        let old = self.get(index);
+        if old == value {
+            return old;
+        }
+        if self.is_direct() {
+            self.storage.set(index, value as u64);
+            return old;
+        }
+        let palette_index = match self.palette.iter().position(|&v| v == value) {
+            Some(i) => i,
+            None => {
+                self.palette.push(value);
+                if self.palette.len() > 1 << self.storage.bits() {
+                    self.grow();
+                    return self.set(index, value);
+                }
+                self.palette.len() - 1
+            }
+        };
+        self.storage.set(index, palette_index as u64);
+        old
+    }
+
+    /// Re-packs the entries after the palette outgrew the current width.
+    fn grow(&mut self) {
+        let bits = self.kind.bits_for_palette(self.palette.len());
+        let mut storage = BitStorage::new(bits.unwrap_or(self.kind.direct_bits), self.kind.size);
+        for i in 0..self.kind.size {
+            match bits {
+                Some(_) => storage.set(i, self.storage.get(i)),
+                None => storage.set(i, self.get(i) as u64),
+            }
+        }
+        if bits.is_none() {
+            self.palette.clear();
+        }
+        self.storage = storage;
+    }
+
+    /// Replaces every entry with `value`.
+    pub fn fill(&mut self, value: i32) {
+        *self = PalettedContainer::new(self.kind, value);
+    }
+
+    /// The number of entries for which `f` holds.
+    pub fn count(&self, mut f: impl FnMut(i32) -> bool) -> usize {
+        if self.storage.bits() == 0 && !self.is_direct() {
+            return if f(self.palette[0]) {
+                self.kind.size
+            } else {
+                0
+            };
+        }
+        (0..self.kind.size).filter(|&i| f(self.get(i))).count()
+    }
+
+    pub fn write_to(&self, buf: &mut impl Write) -> io::Result<usize> {
+        self.write_mapped(buf, |value| value, self.kind.direct_bits)
+    }
+
+    /// Writes the container for a client that numbers the values with
+    /// `map`, `direct_bits` wide when they are stored directly.
+    pub fn write_mapped(
+        &self,
+        buf: &mut impl Write,
+        map: impl Fn(i32) -> i32,
+        direct_bits: u8,
+    ) -> io::Result<usize> {
+        if self.is_direct() {
+            let mut storage = BitStorage::new(direct_bits, self.kind.size);
+            for i in 0..self.kind.size {
+                storage.set(i, map(self.get(i)) as u64);
+            }
+            let mut count = buf.write_mc_ubyte(direct_bits)?;
+            count += storage.write_to(buf)?;
+            return Ok(count);
+        }
+        let mut count = buf.write_mc_ubyte(self.storage.bits())?;
+        if self.storage.bits() == 0 {
+            count += buf.write_mc_varint(map(self.palette[0]))?;
+        } else {
+            count += buf.write_mc_varint(self.palette.len() as i32)?;
+            for &value in &self.palette {
+                count += buf.write_mc_varint(map(value))?;
+            }
+        }
+        count += self.storage.write_to(buf)?;
+        Ok(count)
+    }
+
+    pub fn read_from(reader: &mut impl Read, kind: PaletteKind) -> io::Result<PalettedContainer> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+        let bits = reader.read_mc_ubyte()?;
+        let palette = match bits {
+            0 => vec![reader.read_mc_varint()?],
+            _ if bits <= kind.max_bits => {
+                let length = reader.read_mc_varint()?;
+                if length < 1 || length > 1 << bits {
+                    return Err(invalid("palette length does not match its bits"));
+                }
+                (0..length)
+                    .map(|_| reader.read_mc_varint().map_err(io::Error::from))
+                    .collect::<io::Result<_>>()?
+            }
+            _ => Vec::new(),
+        };
+        let longs = reader.read_mc_varint()?;
+        let mut data = Vec::with_capacity(longs.clamp(0, 4096) as usize);
+        for _ in 0..longs {
+            data.push(reader.read_mc_long()? as u64);
+        }
+        let storage = BitStorage::from_data(bits, kind.size, data)
+            .ok_or_else(|| invalid("data length does not match its bits"))?;
+        Ok(PalettedContainer {
+            kind,
+            palette,
+            storage,
+        })
+    }
+}
+
+/// Light levels of one section, two per byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightArray(Box<[u8; LIGHT_ARRAY_SIZE]>);
+
+impl LightArray {
+    pub fn filled(level: u8) -> LightArray {
+        LightArray(Box::new([level << 4 | level; LIGHT_ARRAY_SIZE]))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<LightArray> {
+        let mut array = LightArray::filled(0);
+        if bytes.len() != LIGHT_ARRAY_SIZE {
+            return None;
+        }
+        array.0.copy_from_slice(bytes);
+        Some(array)
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        let index = block_index(x, y, z);
+        (self.0[index / 2] >> ((index % 2) * 4)) & 0xf
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, level: u8) {
+        let index = block_index(x, y, z);
+        let shift = (index % 2) * 4;
+        let byte = &mut self.0[index / 2];
+        *byte = (*byte & !(0xf << shift)) | ((level & 0xf) << shift);
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+}
+
+fn block_index(x: usize, y: usize, z: usize) -> usize {
+    (y << 8) | (z << 4) | x
+}
+
+fn biome_index(x: usize, y: usize, z: usize) -> usize {
+    (y << 4) | (z << 2) | x
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSection {
+    /// Non-air blocks, which the client uses to skip empty sections.
+    block_count: u16,
+    pub blocks: PalettedContainer,
+    pub biomes: PalettedContainer,
+}
+
+impl ChunkSection {
+    pub fn new(block_kind: PaletteKind, biome_kind: PaletteKind, biome: i32) -> ChunkSection {
+        ChunkSection {
+            block_count: 0,
+            blocks: PalettedContainer::new(block_kind, BlockState::AIR.id()),
+            biomes: PalettedContainer::new(biome_kind, biome),
+        }
+    }
+
+    pub fn block_count(&self) -> u16 {
+        self.block_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+
+    /// Coordinates are relative to the section.
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> BlockState {
+        let id = self.blocks.get(block_index(x, y, z));
+        BlockState::from_id(id).unwrap_or_default()
+    }
+
+    /// Sets a block and returns the previous one.
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) -> BlockState {
+        let old = self.blocks.set(block_index(x, y, z), state.id());
+        let old = BlockState::from_id(old).unwrap_or_default();
+        match (old.is_air(), state.is_air()) {
+            (true, false) => self.block_count += 1,
+            (false, true) => self.block_count -= 1,
+            _ => {}
+        }
+        old
+    }
+
+    pub fn fill_blocks(&mut self, state: BlockState) {
+        self.blocks.fill(state.id());
+        self.block_count = if state.is_air() {
+            0
+        } else {
+            SECTION_BLOCKS as u16
+        };
+    }
+
+    /// Counts the non-air blocks again, after `blocks` was changed directly.
+    pub fn recount_blocks(&mut self) {
+        let count = self
+            .blocks
+            .count(|id| !BlockState::from_id(id).unwrap_or_default().is_air());
+        self.block_count = count as u16;
+    }
+
+    /// Coordinates are in 4 block biome cells relative to the section.
+    pub fn get_biome(&self, x: usize, y: usize, z: usize) -> i32 {
+        self.biomes.get(biome_index(x, y, z))
+    }
+
+    pub fn set_biome(&mut self, x: usize, y: usize, z: usize, biome: i32) {
+        self.biomes.set(biome_index(x, y, z), biome);
+    }
+
+    /// Writes the section with the block state IDs of `ids`.
+    pub fn write_to(&self, buf: &mut impl Write, ids: &ClientStateIds) -> io::Result<usize> {
+        let mut count = buf.write_mc_short(self.block_count as i16)?;
+        let direct_bits = bits_for(ids.state_count());
+        count += self
+            .blocks
+            .write_mapped(buf, |id| ids.get(id), direct_bits)?;
+        count += self.biomes.write_to(buf)?;
+        Ok(count)
+    }
+}
+
+/// The heightmaps a chunk keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeightmapKind {
+    /// The highest non-air block.
+    WorldSurface,
+    /// The highest block that entities collide with or that holds a fluid.
+    MotionBlocking,
+}
+
+impl HeightmapKind {
+    pub const ALL: [HeightmapKind; 2] =
+        [HeightmapKind::WorldSurface, HeightmapKind::MotionBlocking];
+
+    /// The key in Chunk Data and in saved chunks.
+    pub fn name(self) -> &'static str {
+        match self {
+            HeightmapKind::WorldSurface => "WORLD_SURFACE",
+            HeightmapKind::MotionBlocking => "MOTION_BLOCKING",
+        }
+    }
+
+    /// Whether the heightmap stops at `state`.
+    pub fn is_opaque(self, state: BlockState) -> bool {
+        match self {
+            HeightmapKind::WorldSurface => !state.is_air(),
+            HeightmapKind::MotionBlocking => state.blocks_motion() || state.is_fluid(),
+        }
+    }
+}
+
+/// The height of the highest block the heightmap stops at plus one in each
+/// column, relative to `min_y`. Zero means there is no such block. Values
+/// take as many bits as the dimension's `height` needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heightmap {
+    storage: BitStorage,
+}
+
+impl Heightmap {
+    pub fn new(height: i32) -> Heightmap {
+        Heightmap {
+            storage: BitStorage::new(bits_for(height as usize + 1), 256),
+        }
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> i32 {
+        self.storage.get(z * 16 + x) as i32
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, height: i32) {
+        self.storage.set(z * 16 + x, height as u64)
+    }
+
+    pub fn to_nbt(&self) -> nbt::Value {
+        nbt::Value::LongArray(self.storage.data().iter().map(|&l| l as i64).collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockEntity {
+    /// Coordinates within the chunk.
+    pub x: u8,
+    pub y: i32,
+    pub z: u8,
+    /// The ID in the `minecraft:block_entity_type` registry.
+    pub type_id: i32,
+    pub nbt: nbt::Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkColumn {
+    pub x: i32,
+    pub z: i32,
+    min_y: i32,
+    sections: Vec<ChunkSection>,
+    /// Indexed like `HeightmapKind::ALL`.
+    heightmaps: [Heightmap; 2],
+    block_entities: BTreeMap<(i32, u8, u8), BlockEntity>,
+    /// Saved palette entries the registry couldn't load exactly, indexed
+    /// by `kept_blocks`, which is keyed like `block_entities`.
+    kept_entries: Vec<nbt::Value>,
+    kept_blocks: BTreeMap<(i32, u8, u8), usize>,
+    /// Tags of the saved chunk the server doesn't use, saved back as they
+    /// were.
+    other_tags: NbtMap,
+    /// One more section below and above the column.
+    sky_light: Vec<Option<LightArray>>,
+    block_light: Vec<Option<LightArray>>,
+    /// Whether the light engine has lit the column.
+    light_on: bool,
+}
+
+impl ChunkColumn {
+    /// An empty column with every biome cell set to `biome`. `biome_count`
+    /// is the size of the biome registry sent to the client.
+    pub fn new(
+        x: i32,
+        z: i32,
+        dimension: &DimensionType,
+        biome: i32,
+        biome_count: usize,
+    ) -> ChunkColumn {
+        let block_kind = PaletteKind::blocks(blocks().state_count());
+        let biome_kind = PaletteKind::biomes(biome_count);
+        let count = dimension.section_count();
+        ChunkColumn {
+            x,
+            z,
+            min_y: dimension.min_y,
+            sections: vec![ChunkSection::new(block_kind, biome_kind, biome); count],
+            heightmaps: [
+                Heightmap::new(dimension.height),
+                Heightmap::new(dimension.height),
+            ],
+            block_entities: BTreeMap::new(),
+            kept_entries: Vec::new(),
+            kept_blocks: BTreeMap::new(),
+            other_tags: NbtMap::new(),
+            sky_light: vec![None; count + 2],
+            block_light: vec![None; count + 2],
+            light_on: false,
+        }
+    }
+
+    pub fn min_y(&self) -> i32 {
+        self.min_y
+    }
+
+    pub fn height(&self) -> i32 {
+        self.sections.len() as i32 * SECTION_SIZE as i32
+    }
+
+    pub fn sections(&self) -> &[ChunkSection] {
+        &self.sections
+    }
+
+    pub fn sections_mut(&mut self) -> &mut [ChunkSection] {
+        &mut self.sections
+    }
+
+    /// The section holding block `y`.
+    pub fn section_index(&self, y: i32) -> Option<usize> {
+        let index = (y - self.min_y).div_euclid(SECTION_SIZE as i32);
+        if index >= 0 && (index as usize) < self.sections.len() {
+            Some(index as usize)
+        } else {
+            None
+        }
+    }
+
+    /// `x` and `z` are relative to the chunk, `y` is the block's Y.
+    pub fn get_block(&self, x: usize, y: i32, z: usize) -> BlockState {
+        match self.section_index(y) {
+            Some(i) => {
+                let section_y = (y - self.min_y) as usize % SECTION_SIZE;
+                self.sections[i].get_block(x, section_y, z)
+            }
+            None => BlockState::AIR,
+        }
+    }
+
+    /// Sets a block and returns the previous one. Blocks outside the column
+    /// are ignored.
+    pub fn set_block(&mut self, x: usize, y: i32, z: usize, state: BlockState) -> BlockState {
+        let i = match self.section_index(y) {
+            Some(i) => i,
+            None => return BlockState::AIR,
+        };
+        self.kept_blocks.remove(&(y, z as u8, x as u8));
+        let section_y = (y - self.min_y) as usize % SECTION_SIZE;
+        let old = self.sections[i].set_block(x, section_y, z, state);
+
+        let height = y - self.min_y + 1;
+        for (i, kind) in HeightmapKind::ALL.iter().copied().enumerate() {
+            let top = self.heightmaps[i].get(x, z);
+            if kind.is_opaque(state) {
+                if height > top {
+                    self.heightmaps[i].set(x, z, height);
+                }
+            } else if height == top {
+                let below = self.find_height(kind, x, y, z);
+                self.heightmaps[i].set(x, z, below);
+            }
+        }
+        old
+    }
+
+    /// The heightmap value of the highest block below `y` that `kind`
+    /// stops at.
+    fn find_height(&self, kind: HeightmapKind, x: usize, y: i32, z: usize) -> i32 {
+        (self.min_y..y)
+            .rev()
+            .find(|&y| kind.is_opaque(self.get_block(x, y, z)))
+            .map_or(0, |y| y - self.min_y + 1)
+    }
+
+    /// The Y of the lowest block above the highest block `kind` stops at.
+    pub fn height_at(&self, kind: HeightmapKind, x: usize, z: usize) -> i32 {
+        let i = HeightmapKind::ALL.iter().position(|&k| k == kind).unwrap();
+        self.min_y + self.heightmaps[i].get(x, z)
+    }
+
+    /// The Y of the lowest air block above the highest non-air block.
+    pub fn surface_y(&self, x: usize, z: usize) -> i32 {
+        self.height_at(HeightmapKind::WorldSurface, x, z)
+    }
+
+    /// Recomputes the heightmaps after sections were changed directly.
+    pub fn recalculate_heightmaps(&mut self) {
+        let max_y = self.min_y + self.height();
+        for x in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                for (i, kind) in HeightmapKind::ALL.iter().copied().enumerate() {
+                    let height = self.find_height(kind, x, max_y, z);
+                    self.heightmaps[i].set(x, z, height);
+                }
+            }
+        }
+    }
+
+    /// Biome cells are 4 blocks wide; `y` is the cell of block Y `4 * y`.
+    pub fn get_biome(&self, x: usize, y: i32, z: usize) -> Option<i32> {
+        let i = self.section_index(y * 4)?;
+        let cell_y = ((y * 4 - self.min_y) as usize % SECTION_SIZE) / 4;
+        Some(self.sections[i].get_biome(x, cell_y, z))
+    }
+
+    pub fn set_biome(&mut self, x: usize, y: i32, z: usize, biome: i32) {
+        if let Some(i) = self.section_index(y * 4) {
+            let cell_y = ((y * 4 - self.min_y) as usize % SECTION_SIZE) / 4;
+            self.sections[i].set_biome(x, cell_y, z, biome);
+        }
+    }
+
+    pub fn fill_biome(&mut self, biome: i32) {
+        for section in &mut self.sections {
+            section.biomes.fill(biome);
+        }
+    }
+
+    pub fn block_entity(&self, x: u8, y: i32, z: u8) -> Option<&BlockEntity> {
+        self.block_entities.get(&(y, z, x))
+    }
+
+    pub fn set_block_entity(&mut self, block_entity: BlockEntity) {
+        let key = (block_entity.y, block_entity.z, block_entity.x);
+        self.block_entities.insert(key, block_entity);
+    }
+
+    pub fn remove_block_entity(&mut self, x: u8, y: i32, z: u8) -> Option<BlockEntity> {
+        self.block_entities.remove(&(y, z, x))
+    }
+
+    pub fn block_entities(&self) -> impl Iterator<Item = &BlockEntity> {
+        self.block_entities.values()
+    }
+
+    /// The index into `kept_entries` of the saved palette entry of a block
+    /// that was loaded as something else, until the block is set.
+    pub fn kept_block(&self, x: u8, y: i32, z: u8) -> Option<usize> {
+        self.kept_blocks.get(&(y, z, x)).copied()
+    }
+
+    pub fn kept_entries(&self) -> &[nbt::Value] {
+        &self.kept_entries
+    }
+
+    /// Keeps the saved palette entry of a block the registry couldn't load
+    /// exactly, so that it's saved unchanged.
+    pub fn keep_block(&mut self, x: u8, y: i32, z: u8, entry: nbt::Value) {
+        let index = match self.kept_entries.iter().position(|e| *e == entry) {
+            Some(index) => index,
+            None => {
+                self.kept_entries.push(entry);
+                self.kept_entries.len() - 1
+            }
+        };
+        self.kept_blocks.insert((y, z, x), index);
+    }
+
+    pub fn has_kept_blocks(&self) -> bool {
+        !self.kept_blocks.is_empty()
+    }
+
+    pub fn other_tags(&self) -> &NbtMap {
+        &self.other_tags
+    }
+
+    pub fn set_other_tags(&mut self, tags: NbtMap) {
+        self.other_tags = tags;
+    }
+
+    /// Sky light of light section `index`, where 0 is the section below the
+    /// column. `None` means no light.
+    pub fn sky_light(&self, index: usize) -> Option<&LightArray> {
+        self.sky_light.get(index)?.as_ref()
+    }
+
+    pub fn set_sky_light(&mut self, index: usize, light: Option<LightArray>) {
+        self.sky_light[index] = light;
+    }
+
+    /// The sky light of light section `index`, created dark if missing.
+    pub fn sky_light_mut(&mut self, index: usize) -> &mut LightArray {
+        self.sky_light[index].get_or_insert_with(|| LightArray::filled(0))
+    }
+
+    pub fn block_light(&self, index: usize) -> Option<&LightArray> {
+        self.block_light.get(index)?.as_ref()
+    }
+
+    pub fn set_block_light(&mut self, index: usize, light: Option<LightArray>) {
+        self.block_light[index] = light;
+    }
+
+    pub fn block_light_mut(&mut self, index: usize) -> &mut LightArray {
+        self.block_light[index].get_or_insert_with(|| LightArray::filled(0))
+    }
+
+    pub fn light_section_count(&self) -> usize {
+        self.sections.len() + 2
+    }
+
+    pub fn is_light_on(&self) -> bool {
+        self.light_on
+    }
+
+    pub fn set_light_on(&mut self, light_on: bool) {
+        self.light_on = light_on;
+    }
+
+    pub fn heightmaps_nbt(&self) -> nbt::Value {
+        let mut heightmaps = nbt::Map::new();
+        for (kind, heightmap) in HeightmapKind::ALL.iter().zip(&self.heightmaps) {
+            heightmaps.insert(kind.name().to_owned(), heightmap.to_nbt());
+        }
+        nbt::Value::Compound(heightmaps)
+    }
+
+    /// The body of Chunk Data and Update Light, with the block state IDs of
+    /// `ids`. Since protocol 766 its NBT has no root name.
+    pub fn write_chunk_data(
+        &self,
+        buf: &mut impl Write,
+        protocol_version: i32,
+        ids: &ClientStateIds,
+    ) -> io::Result<usize> {
+        let configured = protocol_version >= CONFIGURATION_PROTOCOL_VERSION;
+        let write_nbt = |buf: &mut _, nbt: &nbt::Value| {
+            if configured {
+                McWriteExt::write_mc_network_nbt(buf, nbt)
+            } else {
+                McWriteExt::write_mc_nbt(buf, nbt)
+            }
+        };
+        let mut count = buf.write_mc_int(self.x)?;
+        count += buf.write_mc_int(self.z)?;
+        count += write_nbt(buf, &self.heightmaps_nbt())?;
+
+        let mut data = Vec::new();
+        for section in &self.sections {
+            section.write_to(&mut data, ids)?;
+        }
+        count += buf.write_mc_varint(data.len() as i32)?;
+        buf.write_all(&data)?;
+        count += data.len();
+
+        count += buf.write_mc_varint(self.block_entities.len() as i32)?;
+        for block_entity in self.block_entities.values() {
+            count += buf.write_mc_ubyte((block_entity.x & 15) << 4 | (block_entity.z & 15))?;
+            count += buf.write_mc_short(block_entity.y as i16)?;
+            count += buf.write_mc_varint(block_entity.type_id)?;
+            count += write_nbt(buf, &block_entity.nbt)?;
+        }
+
+        count += self.write_light(buf, protocol_version)?;
+        Ok(count)
+    }
+
+    /// The body of Update Light.
+    pub fn write_update_light(
+        &self,
+        buf: &mut impl Write,
+        protocol_version: i32,
+    ) -> io::Result<usize> {
+        let mut count = buf.write_mc_varint(self.x)?;
+        count += buf.write_mc_varint(self.z)?;
+        count += self.write_light(buf, protocol_version)?;
+        Ok(count)
+    }
+
+    /// Light masks and arrays, shared by both packets. Sections without an
+    /// array are sent as empty. Before protocol 766 they start with the
+    /// "trust edges" flag.
+    fn write_light(&self, buf: &mut impl Write, protocol_version: i32) -> io::Result<usize> {
+        let mut count = 0;
+        if protocol_version < CONFIGURATION_PROTOCOL_VERSION {
+            count += buf.write_mc_bool(true)?; // trust edges
+        }
+        let masks = |arrays: &[Option<LightArray>]| {
+            let mut present = vec![0u64; arrays.len().div_ceil(64)];
+            let mut empty = present.clone();
+            for (i, array) in arrays.iter().enumerate() {
+                match array {
+                    Some(a) if !a.is_zero() => present[i / 64] |= 1 << (i % 64),
+                    _ => empty[i / 64] |= 1 << (i % 64),
+                }
+            }
+            (present, empty)
+        };
+        let (sky, empty_sky) = masks(&self.sky_light);
+        let (block, empty_block) = masks(&self.block_light);
+        for mask in [&sky, &block, &empty_sky, &empty_block] {
+            count += write_bit_set(buf, mask)?;
+        }
+        for arrays in [&self.sky_light, &self.block_light] {
+            let arrays: Vec<&LightArray> =
+                arrays.iter().flatten().filter(|a| !a.is_zero()).collect();
+            count += buf.write_mc_varint(arrays.len() as i32)?;
+            for array in arrays {
+                count += buf.write_mc_varint(LIGHT_ARRAY_SIZE as i32)?;
+                buf.write_all(array.as_bytes())?;
+                count += LIGHT_ARRAY_SIZE;
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn write_bit_set(buf: &mut impl Write, longs: &[u64]) -> io::Result<usize> {
+    let mut count = buf.write_mc_varint(longs.len() as i32)?;
+    for &long in longs {
+        count += buf.write_mc_long(long as i64)?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mc_types::slice::McSliceReader,
+        world::blocks::{client_state_ids, BlockRegistry},
+    };
+
+    #[test]
+    fn test_bit_storage() {
+        assert_eq!(bits_for(1), 0);
+        assert_eq!(bits_for(2), 1);
+        assert_eq!(bits_for(257), 9);
+        let mut storage = BitStorage::new(5, 64);
+        // 12 values per long, so 64 values need 6 longs
+        assert_eq!(storage.data().len(), 6);
+        storage.set(11, 31);
+        storage.set(12, 7);
+        assert_eq!(storage.data()[0], 31 << 55);
+        assert_eq!(storage.data()[1], 7);
+        assert_eq!(storage.get(11), 31);
+    }
+
+    #[test]
+    fn test_palette_grows_to_direct() {
+        let mut container = PalettedContainer::new(PaletteKind::biomes(100), 0);
+        for i in 0..SECTION_BIOMES {
+            assert_eq!(container.set(i, i as i32), 0);
+        }
+        assert!(container.palette().is_none());
+        assert_eq!(container.storage().bits(), 7);
+        assert_eq!(container.get(63), 63);
+
+        let mut buf = Vec::new();
+        container.write_to(&mut buf).unwrap();
+        let read = PalettedContainer::read_from(&mut McSliceReader::new(&buf), container.kind());
+        assert_eq!(read.unwrap(), container);
+    }
+
+    #[test]
+    fn test_column() {
+        let overworld = crate::world::dimensions::DEFAULT_DIMENSION_TYPE.clone();
+        let stone = BlockState::from_name("stone").unwrap();
+        let mut column = ChunkColumn::new(1, -2, &overworld, 0, 4);
+        assert_eq!(column.sections().len(), 24);
+        column.set_block(3, -64, 4, stone);
+        column.set_block(3, 70, 4, stone);
+        assert_eq!(column.get_block(3, 70, 4), stone);
+        assert_eq!(column.sections()[8].block_count(), 1);
+        assert_eq!(column.surface_y(3, 4), 71);
+        column.set_block(3, 70, 4, BlockState::AIR);
+        assert_eq!(column.surface_y(3, 4), -63);
+        let sapling = BlockState::from_name("oak_sapling").unwrap();
+        column.set_block(3, 10, 4, sapling);
+        assert_eq!(column.surface_y(3, 4), 11);
+        assert_eq!(column.height_at(HeightmapKind::MotionBlocking, 3, 4), -63);
+        column.set_block(3, 10, 4, BlockState::AIR);
+        assert_eq!(column.surface_y(3, 4), -63);
+        assert_eq!(column.sections()[8].block_count(), 0);
+
+        column.set_biome(0, -16, 0, 2);
+        assert_eq!(column.get_biome(0, -16, 0), Some(2));
+        assert_eq!(column.get_biome(0, 96, 0), None);
+
+        let ids = client_state_ids(757).unwrap();
+        let mut buf = Vec::new();
+        let count = column.write_chunk_data(&mut buf, 757, ids).unwrap();
+        assert_eq!(count, buf.len());
+        // No root name on the heightmaps, and no "trust edges"
+        let mut configured = Vec::new();
+        let count = column.write_chunk_data(&mut configured, 766, ids).unwrap();
+        assert_eq!(count, configured.len());
+        assert_eq!(configured.len(), buf.len() - 3);
+        let mut reader = McSliceReader::new(&buf);
+        assert_eq!(reader.read_mc_int().unwrap(), 1);
+        assert_eq!(reader.read_mc_int().unwrap(), -2);
+        reader.read_mc_nbt().unwrap();
+        let size = reader.read_mc_varint().unwrap();
+        let mut sections = McSliceReader::new(reader.read_mc_bytes(size as usize).unwrap());
+        assert_eq!(sections.read_mc_short().unwrap(), 1);
+        let blocks = PalettedContainer::read_from(&mut sections, PaletteKind::blocks(69)).unwrap();
+        assert_eq!(blocks.get(block_index(3, 0, 4)), stone.id());
+    }
+
+    #[test]
+    fn test_client_state_ids() {
+        let client = BlockRegistry::from_report(
+            "client.json",
+            r#"{"minecraft:air": {"states": [{"id": 0, "default": true}]},
+                "minecraft:dirt": {"states": [{"id": 1, "default": true}]},
+                "minecraft:stone": {"states": [{"id": 2, "default": true}]}}"#,
+        )
+        .unwrap();
+        let ids = ClientStateIds::matching(blocks(), &client);
+        let stone = BlockState::from_name("stone").unwrap();
+        let mut section = ChunkSection::new(
+            PaletteKind::blocks(blocks().state_count()),
+            PaletteKind::biomes(4),
+            0,
+        );
+        section.set_block(0, 0, 0, stone);
+        let mut buf = Vec::new();
+        section.write_to(&mut buf, &ids).unwrap();
+        let mut reader = McSliceReader::new(&buf);
+        assert_eq!(reader.read_mc_short().unwrap(), 1);
+        let blocks = PalettedContainer::read_from(&mut reader, PaletteKind::blocks(3)).unwrap();
+        assert_eq!(blocks.palette(), Some(&[0, 2][..]));
+
+        // Stored directly, entries take the client's width
+        let mut direct = PalettedContainer::new(PaletteKind::biomes(100), 0);
+        for i in 0..SECTION_BIOMES {
+            direct.set(i, i as i32);
+        }
+        let mut buf = Vec::new();
+        direct.write_mapped(&mut buf, |v| v % 3, 2).unwrap();
+        let narrow = PaletteKind {
+            max_bits: 1,
+            direct_bits: 2,
+            ..PaletteKind::biomes(3)
+        };
+        let read = PalettedContainer::read_from(&mut McSliceReader::new(&buf), narrow).unwrap();
+        assert_eq!(read.storage().bits(), 2);
+        assert_eq!(read.get(5), 2);
+        assert_eq!(read.get(63), 0);
+    }
+}
@@ -0,0 +1,483 @@
+//! Registry entries from vanilla datapacks.
+//!
+//! A datapack is a directory or a zip file laid out like the ones the vanilla
+//! server loads: `data/<namespace>/dimension_type/<path>.json` defines the
+//! dimension type `<namespace>:<path>`, and
+//! `data/<namespace>/worldgen/biome/<path>.json` the biome of that name. The
+//! JSON is checked by the same code as the YAML registry files.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+};
+
+use yaml_rust::{yaml::Hash, Yaml};
+
+use super::schema::{RegistryError, RegistryErrors};
+
+/// The registries a datapack can add to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackRegistry {
+    DimensionType,
+    Biome,
+}
+
+impl PackRegistry {
+    const ALL: [PackRegistry; 2] = [PackRegistry::DimensionType, PackRegistry::Biome];
+
+    /// The directory under `data/<namespace>/` holding this registry.
+    pub fn directory(self) -> &'static str {
+        match self {
+            PackRegistry::DimensionType => "dimension_type",
+            PackRegistry::Biome => "worldgen/biome",
+        }
+    }
+}
+
+/// One JSON file of a datapack, converted to YAML.
+#[derive(Debug, Clone)]
+pub struct PackEntry {
+    pub registry: PackRegistry,
+    /// The entry name, `<namespace>:<path>`.
+    pub name: String,
+    /// The pack and the file within it, for error messages.
+    pub file: String,
+    pub doc: Yaml,
+}
+
+/// Reads every registry entry in a datapack directory or zip file, sorted by
+/// file name. Files outside the known registries are skipped.
+pub fn load_datapack(pack: &Path) -> Result<Vec<PackEntry>, RegistryErrors> {
+    let io_error = |error: io::Error| RegistryError::Io {
+        file: pack.display().to_string(),
+        error,
+    };
+    let mut files = Vec::new();
+    if pack.is_dir() {
+        read_directory(pack, "", &mut files).map_err(io_error)?;
+    } else {
+        read_zip(pack, &mut files).map_err(io_error)?;
+    }
+    files.sort();
+
+    let mut errors = Vec::new();
+    let mut entries = Vec::new();
+    for (path, text) in files {
+        let (registry, name) = match entry_name(&path) {
+            Some(v) => v,
+            None => continue,
+        };
+        let file = format!("{}/{}", pack.display(), path);
+        match serde_json::from_str(&text) {
+            Ok(json) => entries.push(PackEntry {
+                registry,
+                name,
+                file,
+                doc: json_to_yaml(json),
+            }),
+            Err(e) => errors.push(RegistryError::Syntax {
+                file,
+                message: e.to_string(),
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(entries)
+    } else {
+        Err(RegistryErrors(errors))
+    }
+}
+
+/// The datapacks in a directory, in the order they are applied. A missing
+/// directory has no packs.
+pub fn find_datapacks(dir: &Path) -> io::Result<Vec<std::path::PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut packs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_zip = path.extension() == Some("zip".as_ref());
+        if path.is_dir() || is_zip {
+            packs.push(path);
+        }
+    }
+    packs.sort();
+    Ok(packs)
+}
+
+fn read_directory(dir: &Path, prefix: &str, files: &mut Vec<(String, String)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            read_directory(&entry.path(), &format!("{}/", path), files)?;
+        } else if entry_name(&path).is_some() {
+            files.push((path, fs::read_to_string(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+fn read_zip(file: &Path, files: &mut Vec<(String, String)>) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(File::open(file)?)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() || entry_name(entry.name()).is_none() {
+            continue;
+        }
+        let path = entry.name().to_owned();
+        let mut text = String::new();
+        entry.read_to_string(&mut text)?;
+        files.push((path, text));
+    }
+    Ok(())
+}
+
+/// Maps `data/<namespace>/<registry>/<path>.json` to the registry and
+/// `<namespace>:<path>`.
+fn entry_name(path: &str) -> Option<(PackRegistry, String)> {
+    let rest = path.strip_prefix("data/")?.strip_suffix(".json")?;
+    let (namespace, rest) = rest.split_once('/')?;
+    for registry in PackRegistry::ALL.iter() {
+        let path = match rest.strip_prefix(registry.directory()) {
+            Some(path) => path,
+            None => continue,
+        };
+        if let Some(path) = path.strip_prefix('/') {
+            return Some((*registry, format!("{}:{}", namespace, path)));
+        }
+    }
+    None
+}
+
+fn json_to_yaml(json: serde_json::Value) -> Yaml {
+    use serde_json::Value;
+    match json {
+        Value::Null => Yaml::Null,
+        Value::Bool(b) => Yaml::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        Value::String(s) => Yaml::String(s),
+        Value::Array(array) => Yaml::Array(array.into_iter().map(json_to_yaml).collect()),
+        Value::Object(object) => {
+            let mut hash = Hash::new();
+            for (k, v) in object {
+                hash.insert(Yaml::String(k), json_to_yaml(v));
+            }
+            Yaml::Hash(hash)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mc_types::McIdentifier,
+        world::{
+            biomes::biomes_from_entries,
+            dimensions::{dimension_types_from_entries, IntProvider},
+            registry::{RegistrySet, RegistrySource, ToNbt},
+        },
+    };
+    use std::io::Write;
+
+    const NETHER: &str = r##"{
+        "piglin_safe": true, "natural": false, "ambient_light": 0.1,
+        "fixed_time": 18000, "infiniburn": "#minecraft:infiniburn_nether",
+        "respawn_anchor_works": true, "has_skylight": false, "bed_works": false,
+        "effects": "minecraft:the_nether", "has_raids": false, "min_y": 0,
+        "height": 256, "logical_height": 128, "coordinate_scale": 8.0,
+        "ultrawarm": true, "has_ceiling": true
+    }"##;
+
+    /// `data/minecraft/dimension_type/overworld.json` from 1.20.5.
+    const VANILLA_OVERWORLD: &str = r##"{
+  "ambient_light": 0.0,
+  "bed_works": true,
+  "coordinate_scale": 1.0,
+  "effects": "minecraft:overworld",
+  "has_ceiling": false,
+  "has_raids": true,
+  "has_skylight": true,
+  "height": 384,
+  "infiniburn": "#minecraft:infiniburn_overworld",
+  "logical_height": 384,
+  "min_y": -64,
+  "monster_spawn_block_light_limit": 0,
+  "monster_spawn_light_level": {
+    "type": "minecraft:uniform",
+    "max_inclusive": 7,
+    "min_inclusive": 0
+  },
+  "natural": true,
+  "piglin_safe": false,
+  "respawn_anchor_works": false,
+  "ultrawarm": false
+}"##;
+
+    const BIOME: &str = r#"{
+        "precipitation": "none", "temperature": 2.0, "downfall": 0.0,
+        "category": "nether",
+        "effects": {
+            "sky_color": 7254527, "water_fog_color": 329011, "fog_color": 3344392,
+            "water_color": 4159204,
+            "particle": {"probability": 0.1, "options": {"type": "minecraft:ash"}}
+        },
+        "spawners": {}, "spawn_costs": {}, "carvers": {}, "features": []
+    }"#;
+
+    /// `data/minecraft/worldgen/biome/basalt_deltas.json` from the 1.20.5
+    /// server.
+    const VANILLA_BIOME: &str = r#"{
+  "carvers": {
+    "air": [
+      "minecraft:nether_cave"
+    ]
+  },
+  "downfall": 0.0,
+  "effects": {
+    "additions_sound": {
+      "sound": "minecraft:ambient.basalt_deltas.additions",
+      "tick_chance": 0.0111
+    },
+    "ambient_sound": "minecraft:ambient.basalt_deltas.loop",
+    "fog_color": 6840176,
+    "mood_sound": {
+      "block_search_extent": 8,
+      "offset": 2.0,
+      "sound": "minecraft:ambient.basalt_deltas.mood",
+      "tick_delay": 6000
+    },
+    "music": {
+      "max_delay": 24000,
+      "min_delay": 12000,
+      "replace_current_music": false,
+      "sound": "minecraft:music.nether.basalt_deltas"
+    },
+    "particle": {
+      "options": {
+        "type": "minecraft:white_ash"
+      },
+      "probability": 0.118093334
+    },
+    "sky_color": 7254527,
+    "water_color": 4159204,
+    "water_fog_color": 329011
+  },
+  "features": [
+    [],
+    [],
+    [
+      "minecraft:delta",
+      "minecraft:small_basalt_columns",
+      "minecraft:large_basalt_columns"
+    ],
+    [],
+    [
+      "minecraft:basalt_blobs",
+      "minecraft:blackstone_blobs"
+    ],
+    [],
+    [],
+    [
+      "minecraft:spring_delta",
+      "minecraft:patch_fire",
+      "minecraft:patch_soul_fire",
+      "minecraft:glowstone_extra",
+      "minecraft:glowstone",
+      "minecraft:brown_mushroom_nether",
+      "minecraft:red_mushroom_nether",
+      "minecraft:ore_magma",
+      "minecraft:spring_closed_double",
+      "minecraft:ore_gold_deltas",
+      "minecraft:ore_quartz_deltas",
+      "minecraft:ore_ancient_debris_large",
+      "minecraft:ore_debris_small"
+    ],
+    [],
+    [
+      "minecraft:spring_lava"
+    ]
+  ],
+  "has_precipitation": false,
+  "spawn_costs": {},
+  "spawners": {
+    "ambient": [],
+    "axolotls": [],
+    "creature": [],
+    "misc": [],
+    "monster": [
+      {
+        "type": "minecraft:ghast",
+        "maxCount": 1,
+        "minCount": 1,
+        "weight": 40
+      },
+      {
+        "type": "minecraft:magma_cube",
+        "maxCount": 5,
+        "minCount": 2,
+        "weight": 100
+      }
+    ],
+    "underground_water_creature": [],
+    "water_ambient": [],
+    "water_creature": []
+  },
+  "temperature": 2.0
+}"#;
+
+    #[test]
+    fn test_packs_override_defaults() {
+        let dir = std::env::temp_dir().join(format!("zipper-datapack-{}", std::process::id()));
+        let pack = dir.join("a_pack");
+        fs::create_dir_all(pack.join("data/minecraft/dimension_type")).unwrap();
+        fs::create_dir_all(pack.join("data/zipper/worldgen/biome")).unwrap();
+        fs::write(
+            pack.join("data/minecraft/dimension_type/the_nether.json"),
+            NETHER,
+        )
+        .unwrap();
+        fs::write(pack.join("data/zipper/worldgen/biome/ashlands.json"), BIOME).unwrap();
+
+        let zip_path = dir.join("b_pack.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("data/zipper/worldgen/biome/ashlands.json", options)
+            .unwrap();
+        zip.write_all(BIOME.replace("0.1", "0.25").as_bytes())
+            .unwrap();
+        zip.finish().unwrap();
+
+        let mut sources = vec![
+            RegistrySource::DimensionTypes("src/world/dimensions.yaml".into()),
+            RegistrySource::Biomes("src/world/biomes.yaml".into()),
+        ];
+        let packs = find_datapacks(&dir).unwrap();
+        sources.extend(packs.into_iter().map(RegistrySource::Datapack));
+        let registries = RegistrySet::load(&sources).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let nether = McIdentifier::minecraft("the_nether").unwrap();
+        let nether = registries.dimension_types.get(&nether).unwrap();
+        assert_eq!(nether.id, 1);
+        assert_eq!(nether.element.logical_height, 128);
+
+        let ashlands: McIdentifier = "zipper:ashlands".parse().unwrap();
+        let ashlands = registries.biomes.get(&ashlands).unwrap();
+        let particle = ashlands.element.effects.particle.as_ref().unwrap();
+        assert_eq!(particle.particle_type, "minecraft:ash");
+        assert_eq!(particle.probability, 0.25);
+    }
+
+    #[test]
+    fn test_vanilla_biome() {
+        let json = serde_json::from_str(VANILLA_BIOME).unwrap();
+        let entries = vec![("minecraft:basalt_deltas".to_owned(), json_to_yaml(json))];
+        let biomes = biomes_from_entries("basalt_deltas.json", &entries).unwrap();
+        let deltas = &biomes[0].1;
+        assert_eq!(deltas.precipitation, "none");
+        assert_eq!(deltas.category, "none");
+        assert_eq!(deltas.effects.fog_color, 6840176);
+        assert_eq!(deltas.effects.music.as_ref().unwrap().min_delay, 12000);
+
+        let snowy = VANILLA_BIOME
+            .replace(
+                "\"has_precipitation\": false",
+                "\"has_precipitation\": true",
+            )
+            .replace("\"temperature\": 2.0", "\"temperature\": 0.0");
+        let json = serde_json::from_str(&snowy).unwrap();
+        let entries = vec![("zipper:snowy_deltas".to_owned(), json_to_yaml(json))];
+        let biomes = biomes_from_entries("snowy_deltas.json", &entries).unwrap();
+        assert_eq!(biomes[0].1.precipitation, "snow");
+    }
+
+    #[test]
+    fn test_vanilla_dimension_type() {
+        let uniform = IntProvider::Uniform {
+            min_inclusive: 0,
+            max_inclusive: 7,
+        };
+        let json = serde_json::from_str(VANILLA_OVERWORLD).unwrap();
+        let entries = vec![("minecraft:overworld".to_owned(), json_to_yaml(json))];
+        let dimensions = dimension_types_from_entries("overworld.json", &entries).unwrap();
+        let overworld = &dimensions[0].1;
+        assert_eq!(overworld.min_y, -64);
+        assert_eq!(overworld.monster_spawn_light_level, Some(uniform));
+        assert_eq!(overworld.monster_spawn_block_light_limit, Some(0));
+        let data = match overworld.to_registry_data() {
+            nbt::Value::Compound(map) => map,
+            _ => panic!(),
+        };
+        assert_eq!(data["monster_spawn_light_level"], uniform.to_nbt());
+        assert_eq!(
+            data["infiniburn"],
+            nbt::Value::String("#minecraft:infiniburn_overworld".to_owned())
+        );
+
+        // 1.19 to 1.20.4 nest the bounds, and constants can be plain
+        let nested = VANILLA_OVERWORLD.replace(
+            r#""max_inclusive": 7,
+    "min_inclusive": 0"#,
+            r#""value": {"max_inclusive": 7, "min_inclusive": 0}"#,
+        );
+        let constant = VANILLA_OVERWORLD.replace(
+            r#"{
+    "type": "minecraft:uniform",
+    "max_inclusive": 7,
+    "min_inclusive": 0
+  }"#,
+            "7",
+        );
+        let bad = VANILLA_OVERWORLD.replace("\"max_inclusive\": 7", "\"max_inclusive\": 16");
+        let load = |text: &str| {
+            let json = serde_json::from_str(text).unwrap();
+            let entries = vec![("zipper:overworld".to_owned(), json_to_yaml(json))];
+            dimension_types_from_entries("overworld.json", &entries)
+                .map(|d| d[0].1.monster_spawn_light_level)
+        };
+        assert_eq!(load(&nested).unwrap(), Some(uniform));
+        assert_eq!(load(&constant).unwrap(), Some(IntProvider::Constant(7)));
+        assert_eq!(
+            load(&bad).unwrap_err().to_string(),
+            "overworld.json: zipper:overworld.monster_spawn_light_level: max_inclusive: \
+             16 is not in 0..=15"
+        );
+    }
+
+    #[test]
+    fn test_entry_name() {
+        assert_eq!(
+            entry_name("data/minecraft/dimension_type/overworld.json"),
+            Some((
+                PackRegistry::DimensionType,
+                "minecraft:overworld".to_owned()
+            ))
+        );
+        assert_eq!(
+            entry_name("data/zipper/worldgen/biome/caves/deep.json"),
+            Some((PackRegistry::Biome, "zipper:caves/deep".to_owned()))
+        );
+        assert_eq!(entry_name("data/zipper/worldgen/biome_source/a.json"), None);
+        assert_eq!(entry_name("data/zipper/tags/blocks/a.json"), None);
+        assert_eq!(entry_name("pack.mcmeta"), None);
+    }
+
+    #[test]
+    fn test_json_to_yaml() {
+        let json = serde_json::from_str(r#"{"a": 1, "b": 0.5, "c": [true, "x"]}"#).unwrap();
+        let yaml = json_to_yaml(json);
+        assert_eq!(yaml["a"].as_i64(), Some(1));
+        assert_eq!(yaml["b"].as_f64(), Some(0.5));
+        assert_eq!(yaml["c"][0].as_bool(), Some(true));
+        assert_eq!(yaml["c"][1].as_str(), Some("x"));
+    }
+}
@@ -1,197 +1,411 @@
 use lazy_static::lazy_static;
 use nbt::Value::*;
-use yaml_rust::{Yaml, YamlLoader};
+use yaml_rust::Yaml;
 
-const NBT_ITRUE: nbt::Value = Byte(1i8);
-const NBT_IFALSE: nbt::Value = Byte(0i8);
+use super::{
+    registry::{insert, insert_string, nbt_bool, ToNbt},
+    schema::{load_entries, parse_entries, EntryFields, RegistryError, RegistryErrors},
+};
+use crate::mc_types::McIdentifier;
 
-type NbtMap = nbt::Map<std::string::String, nbt::Value>;
+/// The build limits the client accepts for `min_y` and `height`.
+const MIN_BUILD_Y: i64 = -2032;
+const MAX_BUILD_Y: i64 = 2031;
+const MAX_HEIGHT: i64 = 4064;
 
-fn nbt_bool(b: bool) -> nbt::Value {
-    if b {
-        NBT_ITRUE
-    } else {
-        NBT_IFALSE
-    }
+/// An element of the `minecraft:dimension_type` registry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionType {
+    pub piglin_safe: bool,
+    pub natural: bool,
+    pub ambient_light: f32,
+    /// Locks the time of day, as in the nether and the end.
+    pub fixed_time: Option<i64>,
+    pub infiniburn: std::string::String,
+    pub respawn_anchor_works: bool,
+    pub has_skylight: bool,
+    pub bed_works: bool,
+    pub effects: std::string::String,
+    pub has_raids: bool,
+    pub min_y: i32,
+    pub height: i32,
+    pub logical_height: i32,
+    pub coordinate_scale: f64,
+    pub ultrawarm: bool,
+    pub has_ceiling: bool,
+    pub monster_spawn_light_level: Option<IntProvider>,
+    pub monster_spawn_block_light_limit: Option<i32>,
 }
 
-fn load_yaml_from_file<'a>(filename: &str) -> Vec<Yaml> {
-    let fstr = std::fs::read_to_string(filename).unwrap();
-    let yaml = YamlLoader::load_from_str(&fstr).unwrap();
-    let doc = &yaml[0];
-    println!("{:?}", doc);
-    yaml
+/// A number vanilla picks when it needs one, like the light level monsters
+/// spawn at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntProvider {
+    Constant(i32),
+    Uniform {
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
 }
 
-fn insert(map: &mut NbtMap, k: &str, v: nbt::Value) {
-    map.insert(k.to_owned(), v);
+impl ToNbt for IntProvider {
+    /// Constants as a plain int, others as 1.20.5 writes them.
+    fn to_nbt(&self) -> nbt::Value {
+        match *self {
+            IntProvider::Constant(value) => Int(value),
+            IntProvider::Uniform {
+                min_inclusive,
+                max_inclusive,
+            } => {
+                let mut map = nbt::Map::new();
+                insert_string(&mut map, "type", "minecraft:uniform");
+                insert(&mut map, "min_inclusive", Int(min_inclusive));
+                insert(&mut map, "max_inclusive", Int(max_inclusive));
+                Compound(map)
+            }
+        }
+    }
 }
 
-fn ynbt_bool(map: &mut NbtMap, doc: &Yaml, k: &str) {
-    println!("{:?}", doc);
-    println!("{} {:?}", k, doc[k]);
-    insert(map, k, nbt_bool(doc[k].as_bool().unwrap()));
-}
+impl DimensionType {
+    /// The highest block Y coordinate.
+    pub fn max_y(&self) -> i32 {
+        self.min_y + self.height - 1
+    }
 
-fn ynbt_float(map: &mut NbtMap, doc: &Yaml, k: &str) {
-    println!("{:?}", doc);
-    println!("{} {:?}", k, doc[k]);
-    insert(map, k, Float(doc[k].as_f64().unwrap() as f32));
-}
+    pub fn contains_y(&self, y: i32) -> bool {
+        y >= self.min_y && y <= self.max_y()
+    }
 
-fn ynbt_int(map: &mut NbtMap, doc: &Yaml, k: &str) {
-    println!("{:?}", doc);
-    println!("{} {:?}", k, doc[k]);
-    insert(map, k, Int(doc[k].as_i64().unwrap() as i32));
-}
+    /// The number of 16 block tall sections in a chunk column.
+    pub fn section_count(&self) -> usize {
+        (self.height / 16) as usize
+    }
 
-fn ynbt_string(map: &mut NbtMap, doc: &Yaml, k: &str) {
-    println!("{:?}", doc);
-    println!("{} {:?}", k, doc[k]);
-    insert(map, k, String(doc[k].as_str().unwrap().to_owned()));
+    /// The section Y coordinate of the lowest section.
+    pub fn min_section(&self) -> i32 {
+        self.min_y >> 4
+    }
 }
 
-fn registry_entry_generic(name: &str, id: i32, element: nbt::Value) -> nbt::Value {
-    let mut default = nbt::Map::new();
-    default.insert("name".to_owned(), String(name.to_owned()));
-    default.insert("id".to_owned(), Int(id));
-    default.insert("element".to_owned(), element);
-    nbt::Value::Compound(default)
-}
+impl ToNbt for DimensionType {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut default = nbt::Map::new();
+        let d = &mut default;
+        insert(d, "piglin_safe", nbt_bool(self.piglin_safe));
+        insert(d, "natural", nbt_bool(self.natural));
+        insert(d, "ambient_light", Float(self.ambient_light));
+        if let Some(fixed_time) = self.fixed_time {
+            insert(d, "fixed_time", Long(fixed_time));
+        }
+        insert_string(d, "infiniburn", &self.infiniburn);
+        insert(
+            d,
+            "respawn_anchor_works",
+            nbt_bool(self.respawn_anchor_works),
+        );
+        insert(d, "has_skylight", nbt_bool(self.has_skylight));
+        insert(d, "bed_works", nbt_bool(self.bed_works));
+        insert_string(d, "effects", &self.effects);
+        insert(d, "has_raids", nbt_bool(self.has_raids));
+        insert(d, "min_y", Int(self.min_y));
+        insert(d, "height", Int(self.height));
+        insert(d, "logical_height", Int(self.logical_height));
+        insert(d, "coordinate_scale", Double(self.coordinate_scale));
+        insert(d, "ultrawarm", nbt_bool(self.ultrawarm));
+        insert(d, "has_ceiling", nbt_bool(self.has_ceiling));
+        if let Some(level) = &self.monster_spawn_light_level {
+            insert(d, "monster_spawn_light_level", level.to_nbt());
+        }
+        if let Some(limit) = self.monster_spawn_block_light_limit {
+            insert(d, "monster_spawn_block_light_limit", Int(limit));
+        }
+        nbt::Value::Compound(default)
+    }
 
-fn make_dimension_type(doc: &Yaml) -> nbt::Value {
-    let mut default = nbt::Map::new();
-    let d = &mut default;
-    ynbt_bool(d, &doc, "piglin_safe");
-    ynbt_bool(d, &doc, "natural");
-    ynbt_float(d, &doc, "ambient_light");
-    ynbt_string(d, &doc, "infiniburn");
-    ynbt_bool(d, &doc, "respawn_anchor_works");
-    ynbt_bool(d, &doc, "bed_works");
-    ynbt_string(d, &doc, "effects");
-    ynbt_bool(d, &doc, "has_raids");
-    ynbt_int(d, &doc, "min_y");
-    ynbt_int(d, &doc, "height");
-    ynbt_int(d, &doc, "logical_height");
-    ynbt_float(d, &doc, "coordinate_scale");
-    ynbt_bool(d, &doc, "ultrawarm");
-    ynbt_bool(d, &doc, "has_ceiling");
-    nbt::Value::Compound(default)
+    /// Since 1.18.2 `infiniburn` names a tag, and the monster spawn fields
+    /// are required.
+    fn to_registry_data(&self) -> nbt::Value {
+        let mut data = match self.to_nbt() {
+            Compound(map) => map,
+            _ => unreachable!(),
+        };
+        if !self.infiniburn.starts_with('#') {
+            insert_string(&mut data, "infiniburn", &format!("#{}", self.infiniburn));
+        }
+        let level = self
+            .monster_spawn_light_level
+            .unwrap_or(IntProvider::Constant(0));
+        insert(&mut data, "monster_spawn_light_level", level.to_nbt());
+        let limit = self.monster_spawn_block_light_limit.unwrap_or(0);
+        insert(&mut data, "monster_spawn_block_light_limit", Int(limit));
+        Compound(data)
+    }
 }
 
-fn default_dimension_type() -> nbt::Value {
-    let mut default = nbt::Map::new();
-    default.insert("piglin_safe".to_owned(), NBT_ITRUE);
-    default.insert("natural".to_owned(), NBT_ITRUE);
-    default.insert("ambient_light".to_owned(), Float(1.0f32));
-    default.insert(
-        "infiniburn".to_owned(),
-        String("minecraft:infiniburn".to_owned()),
-    );
-    default.insert("respawn_anchor_works".to_owned(), NBT_IFALSE);
-    default.insert("has_skylight".to_owned(), NBT_ITRUE);
-    default.insert("bed_works".to_owned(), NBT_ITRUE);
-    default.insert(
-        "effects".to_owned(),
-        String("minecraft:overworld".to_owned()),
-    );
-    default.insert("has_raids".to_owned(), NBT_ITRUE);
-    default.insert("min_y".to_owned(), Int(-2032i32));
-    default.insert("height".to_owned(), Int(2032i32));
-    default.insert("logical_height".to_owned(), Int(256i32));
-    default.insert("coordinate_scale".to_owned(), Float(20.0f32));
-    default.insert("ultrawarm".to_owned(), NBT_IFALSE);
-    default.insert("has_ceiling".to_owned(), NBT_IFALSE);
-    nbt::Value::Compound(default)
+/// An int provider in `min..=max`: a plain integer or a mapping with a
+/// `type`. Before 1.20.5 the bounds of `minecraft:uniform` were nested under
+/// `value`.
+fn int_provider(f: &mut EntryFields, k: &str, min: i64, max: i64) -> Option<IntProvider> {
+    if !f.is_mapping(k) {
+        return f
+            .int(k, None, min, max)
+            .map(|v| IntProvider::Constant(v as i32));
+    }
+    let mut provider = f.nested(k)?;
+    let kind = provider.identifier("type", None, false)?;
+    let kind = kind.parse::<McIdentifier>().ok()?;
+    let read = if kind == McIdentifier::minecraft("constant").unwrap() {
+        provider
+            .int("value", None, min, max)
+            .map(|v| IntProvider::Constant(v as i32))
+    } else if kind == McIdentifier::minecraft("uniform").unwrap() {
+        if provider.is_mapping("value") {
+            let mut bounds = provider.nested("value")?;
+            let read = uniform_bounds(&mut bounds, min, max);
+            bounds.finish();
+            read
+        } else {
+            uniform_bounds(&mut provider, min, max)
+        }
+    } else {
+        provider.error("type", "expected minecraft:constant or minecraft:uniform");
+        None
+    };
+    provider.finish();
+    read
 }
 
-fn default_dimension_entry() -> nbt::Value {
-    registry_entry_generic("minecraft:overworld", 0, default_dimension_type())
+fn uniform_bounds(f: &mut EntryFields, min: i64, max: i64) -> Option<IntProvider> {
+    let min_inclusive = f.int("min_inclusive", None, min, max);
+    let max_inclusive = f.int("max_inclusive", None, min, max);
+    let (min_inclusive, max_inclusive) = (min_inclusive?, max_inclusive?);
+    if min_inclusive > max_inclusive {
+        f.error("max_inclusive", "must not be below min_inclusive");
+        return None;
+    }
+    Some(IntProvider::Uniform {
+        min_inclusive: min_inclusive as i32,
+        max_inclusive: max_inclusive as i32,
+    })
 }
 
-fn default_dimension_registry() -> nbt::Value {
-    let mut default = nbt::Map::new();
-    default.insert(
-        "type".to_owned(),
-        String("minecraft:dimension_type".to_owned()),
-    );
-
-    let dimensions = std::fs::read_to_string("src/world/dimensions.yaml").unwrap();
-    let dimensions = YamlLoader::load_from_str(&dimensions).unwrap();
-    let dimensions = &dimensions[0];
-
-    let mut count = 0;
-
-    let mut dimension_list = Vec::new();
-    let mut dimension_map: NbtMap = nbt::Map::new();
-    for (k, dim) in dimensions.as_hash().unwrap() {
-        println!("{:?}", dim);
-        let dim_nbt = make_dimension_type(&dim);
-        let dim_regent = registry_entry_generic(k.as_str().unwrap(), count, dim_nbt);
-        dimension_list.push(dim_regent.clone());
-        dimension_map.insert(k.as_str().unwrap().to_owned(), dim_regent);
-        count += 1;
-    }
-    // default.insert("value".to_owned(), List(dimension_list));
-    default.insert("value".to_owned(), Compound(dimension_map));
-
-    // let mut dimension_map = nbt::Map::new();
-    // dimension_map.insert("minecraft:overworld".to_owned(), default_dimension_entry());
-    // default.insert("value".to_owned(), Compound(dimension_map));
-    nbt::Value::Compound(default)
+fn make_dimension_type(f: &mut EntryFields) -> Option<DimensionType> {
+    let piglin_safe = f.bool("piglin_safe", Some(false));
+    let natural = f.bool("natural", Some(true));
+    let ambient_light = f.float("ambient_light", Some(0.0), 0.0, 1.0);
+    let fixed_time = f.optional("fixed_time", |f, k| f.int(k, None, 0, i64::MAX));
+    let infiniburn = f.identifier("infiniburn", Some("minecraft:infiniburn_overworld"), true);
+    let respawn_anchor_works = f.bool("respawn_anchor_works", Some(false));
+    let has_skylight = f.bool("has_skylight", Some(true));
+    let bed_works = f.bool("bed_works", Some(true));
+    let effects = f.identifier("effects", Some("minecraft:overworld"), false);
+    let has_raids = f.bool("has_raids", Some(true));
+    let min_y = f.int("min_y", None, MIN_BUILD_Y, MAX_BUILD_Y);
+    let height = f.int("height", None, 16, MAX_HEIGHT);
+    let logical_height = f.int("logical_height", None, 0, MAX_HEIGHT);
+    let coordinate_scale = f.float("coordinate_scale", Some(1.0), 1e-5, 3e7);
+    let ultrawarm = f.bool("ultrawarm", Some(false));
+    let has_ceiling = f.bool("has_ceiling", Some(false));
+    let monster_spawn_light_level = f.optional("monster_spawn_light_level", |f, k| {
+        int_provider(f, k, 0, 15)
+    });
+    let monster_spawn_block_light_limit = f.optional("monster_spawn_block_light_limit", |f, k| {
+        f.int(k, None, 0, 15)
+    });
+
+    if let Some(min_y) = min_y {
+        if min_y % 16 != 0 {
+            f.error("min_y", "must be a multiple of 16");
+        }
+    }
+    if let Some(height) = height {
+        if height % 16 != 0 {
+            f.error("height", "must be a multiple of 16");
+        }
+        if let Some(min_y) = min_y {
+            if min_y + height > MAX_BUILD_Y + 1 {
+                f.error("height", "min_y + height exceeds the build limit");
+            }
+        }
+        if let Some(logical_height) = logical_height {
+            if logical_height > height {
+                f.error("logical_height", "must not exceed height");
+            }
+        }
+    }
+
+    Some(DimensionType {
+        piglin_safe: piglin_safe?,
+        natural: natural?,
+        ambient_light: ambient_light? as f32,
+        fixed_time: fixed_time?,
+        infiniburn: infiniburn?,
+        respawn_anchor_works: respawn_anchor_works?,
+        has_skylight: has_skylight?,
+        bed_works: bed_works?,
+        effects: effects?,
+        has_raids: has_raids?,
+        min_y: min_y? as i32,
+        height: height? as i32,
+        logical_height: logical_height? as i32,
+        coordinate_scale: coordinate_scale?,
+        ultrawarm: ultrawarm?,
+        has_ceiling: has_ceiling?,
+        monster_spawn_light_level: monster_spawn_light_level?,
+        monster_spawn_block_light_limit: monster_spawn_block_light_limit?.map(|v| v as i32),
+    })
 }
 
-fn default_biome_properties() -> nbt::Value {
-    let mut default = nbt::Map::new();
-    default.insert("precipitation".to_owned(), String("rain".to_owned()));
-    default.insert("depth".to_owned(), Float(0.0f32));
-    default.insert("temperature".to_owned(), Float(1.0f32));
-    default.insert("scale".to_owned(), Float(1.0f32));
-    default.insert("downfall".to_owned(), Float(0.5f32));
-    default.insert("category".to_owned(), String("plains".to_owned()));
-
-    let mut effects = nbt::Map::new();
-    const colors: &[&str] = &["sky_color", "water_fog_color", "fog_color", "water_color"];
-    for &color in colors {
-        effects.insert(color.to_owned(), Int(8364534i32));
-    }
-    let effects = nbt::Value::Compound(effects);
-    default.insert("effects".to_owned(), effects);
-    nbt::Value::Compound(default)
+/// Loads and validates every dimension type in a YAML file, in file order.
+/// All problems in the file are reported together.
+pub fn load_dimension_types(
+    file: &str,
+) -> Result<Vec<(McIdentifier, DimensionType)>, RegistryErrors> {
+    let entries = load_entries(file)?;
+    dimension_types_from_entries(file, &entries)
 }
 
-fn default_biome_entry() -> nbt::Value {
-    registry_entry_generic("minecraft:plains", 0, default_biome_properties())
+/// Like `load_dimension_types`, for YAML that is already in memory.
+pub fn parse_dimension_types(
+    file: &str,
+    text: &str,
+) -> Result<Vec<(McIdentifier, DimensionType)>, RegistryErrors> {
+    let entries = parse_entries(file, text)?;
+    dimension_types_from_entries(file, &entries)
 }
 
-fn default_biome_registry() -> nbt::Value {
-    let mut default = nbt::Map::new();
-    default.insert(
-        "type".to_owned(),
-        String("minecraft:worldgen/biome".to_owned()),
-    );
-    // let biome_list = Vec::new();
-    // default.insert("value".to_owned(), List(biome_list));
-    let mut biome_map = nbt::Map::new();
-    biome_map.insert("minecraft:default".to_owned(), default_biome_entry());
-    default.insert("value".to_owned(), Compound(biome_map));
-    nbt::Value::Compound(default)
+pub(super) fn dimension_types_from_entries(
+    file: &str,
+    entries: &[(std::string::String, Yaml)],
+) -> Result<Vec<(McIdentifier, DimensionType)>, RegistryErrors> {
+    let mut errors = Vec::new();
+    let mut dimension_types = Vec::new();
+    for (name, doc) in entries {
+        let id = match name.parse::<McIdentifier>() {
+            Ok(id) => id,
+            Err(e) => {
+                errors.push(RegistryError::Field {
+                    file: file.to_owned(),
+                    entry: name.clone(),
+                    field: "".to_owned(),
+                    message: format!("invalid dimension name: {}", e),
+                });
+                continue;
+            }
+        };
+        let mut fields = EntryFields::new(file, name, doc, &mut errors);
+        let dimension_type = make_dimension_type(&mut fields);
+        fields.finish();
+        if let Some(dimension_type) = dimension_type {
+            dimension_types.push((id, dimension_type));
+        }
+    }
+    if errors.is_empty() {
+        Ok(dimension_types)
+    } else {
+        Err(RegistryErrors(errors))
+    }
 }
 
-fn default_dimension_codec() -> nbt::Value {
-    let mut default = nbt::Map::new();
-    default.insert(
-        "minecraft:dimension_type".to_owned(),
-        default_dimension_registry(),
-    );
-    default.insert(
-        "minecraft:worldgen/biome".to_owned(),
-        default_biome_registry(),
-    );
-    nbt::Value::Compound(default)
+/// Vanilla's 1.18 overworld, for code that needs some dimension type.
+fn default_dimension_type() -> DimensionType {
+    DimensionType {
+        piglin_safe: false,
+        natural: true,
+        ambient_light: 0.0,
+        fixed_time: None,
+        infiniburn: "minecraft:infiniburn_overworld".to_owned(),
+        respawn_anchor_works: false,
+        has_skylight: true,
+        bed_works: true,
+        effects: "minecraft:overworld".to_owned(),
+        has_raids: true,
+        min_y: -64,
+        height: 384,
+        logical_height: 384,
+        coordinate_scale: 1.0,
+        ultrawarm: false,
+        has_ceiling: false,
+        monster_spawn_light_level: None,
+        monster_spawn_block_light_limit: None,
+    }
 }
 
+/// The shipped dimension types, for `RegistrySource::Embedded`.
+pub const EMBEDDED_DIMENSION_TYPES: &str = include_str!("dimensions.yaml");
+
 lazy_static! {
-    pub static ref DEFAULT_DIMENSION_TYPE: nbt::Value = default_dimension_type();
-    pub static ref DEFAULT_DIMENSION_CODEC: nbt::Value = default_dimension_codec();
+    pub static ref DEFAULT_DIMENSION_TYPE: DimensionType = default_dimension_type();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc_types::snbt;
+    use crate::world::registry::{RegistrySet, RegistrySource};
+
+    #[test]
+    fn test_load_shipped_dimensions() {
+        let dimensions = load_dimension_types("src/world/dimensions.yaml").unwrap();
+        let (name, nether) = &dimensions[1];
+        assert_eq!(name.to_string(), "minecraft:the_nether");
+        assert_eq!(nether.fixed_time, Some(18000));
+        assert!(!nether.has_skylight);
+        assert_eq!(nether.coordinate_scale, 8.0);
+        assert_eq!(nether.max_y(), 255);
+        assert_eq!(nether.section_count(), 16);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let dimensions = parse_dimension_types(
+            "test.yaml",
+            "zipper:void:\n  min_y: -64\n  height: 64\n  logical_height: 64\n",
+        )
+        .unwrap();
+        let expected = snbt::parse(
+            r#"{piglin_safe:0b,natural:1b,ambient_light:0.0f,
+                infiniburn:"minecraft:infiniburn_overworld",respawn_anchor_works:0b,
+                has_skylight:1b,bed_works:1b,effects:"minecraft:overworld",has_raids:1b,
+                min_y:-64,height:64,logical_height:64,coordinate_scale:1.0d,
+                ultrawarm:0b,has_ceiling:0b}"#,
+        )
+        .unwrap();
+        let void = &dimensions[0].1;
+        assert_eq!(void.to_nbt(), expected);
+        assert_eq!(void.min_section(), -4);
+        assert!(void.contains_y(-1) && !void.contains_y(0));
+    }
+
+    #[test]
+    fn test_errors_name_file_dimension_and_field() {
+        let errors = parse_dimension_types(
+            "test.yaml",
+            "zipper:bad:\n  min_y: 8\n  height: tall\n  logical_height: 64\n  bed_wroks: true\n",
+        )
+        .unwrap_err();
+        let messages: Vec<std::string::String> = errors.0.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "test.yaml: zipper:bad: height: expected an integer, found String(\"tall\")",
+                "test.yaml: zipper:bad: min_y: must be a multiple of 16",
+                "test.yaml: zipper:bad: bed_wroks: unknown field",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_codec() {
+        let registries = RegistrySet::load(&[RegistrySource::Embedded]).unwrap();
+        let codec = match registries.to_codec() {
+            Compound(map) => map,
+            _ => panic!(),
+        };
+        assert!(codec.contains_key("minecraft:dimension_type"));
+        assert!(codec.contains_key("minecraft:worldgen/biome"));
+        let overworld = McIdentifier::minecraft("overworld").unwrap();
+        assert_eq!(registries.dimension_types.id_of(&overworld), Some(0));
+    }
 }
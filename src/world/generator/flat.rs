@@ -0,0 +1,352 @@
+//! Superflat worlds, described with vanilla's flat preset strings:
+//!
+//! ```text
+//! minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains;village
+//! ```
+//!
+//! The layers are listed from the bottom up, each optionally prefixed with
+//! its thickness. The biome and the structure list are optional, and
+//! structures may carry options such as `village(size=1 distance=32)`.
+
+use std::{fmt, io, str::FromStr};
+
+use super::ChunkGenerator;
+use crate::{
+    mc_types::McIdentifier,
+    world::{
+        biomes::Biome,
+        blocks::{BlockState, BlockStateError},
+        chunk::{ChunkColumn, SECTION_SIZE},
+        dimensions::DimensionType,
+        registry::Registry,
+    },
+};
+
+/// The preset of vanilla's "Classic Flat" world.
+pub const CLASSIC_FLAT_PRESET: &str =
+    "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains;village";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlatPresetError {
+    NoLayers,
+    InvalidLayer(String),
+    Block(BlockStateError),
+    InvalidBiome(String),
+    InvalidStructure(String),
+    UnknownBiome(McIdentifier),
+    TooHigh { height: i32, max: i32 },
+}
+
+impl fmt::Display for FlatPresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlatPresetError::NoLayers => write!(f, "the preset has no layers"),
+            FlatPresetError::InvalidLayer(s) => write!(f, "invalid layer {:?}", s),
+            FlatPresetError::Block(e) => write!(f, "{}", e),
+            FlatPresetError::InvalidBiome(s) => write!(f, "invalid biome {:?}", s),
+            FlatPresetError::InvalidStructure(s) => write!(f, "invalid structure {:?}", s),
+            FlatPresetError::UnknownBiome(biome) => write!(f, "unknown biome {}", biome),
+            FlatPresetError::TooHigh { height, max } => write!(
+                f,
+                "the layers are {} blocks high but the world only {}",
+                height, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FlatPresetError {}
+
+impl From<BlockStateError> for FlatPresetError {
+    fn from(e: BlockStateError) -> Self {
+        FlatPresetError::Block(e)
+    }
+}
+
+impl From<FlatPresetError> for io::Error {
+    fn from(e: FlatPresetError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatLayer {
+    pub block: BlockState,
+    pub thickness: u32,
+}
+
+/// A structure that may generate, with its options as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatStructure {
+    pub name: String,
+    pub options: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatSettings {
+    /// From the bottom up.
+    pub layers: Vec<FlatLayer>,
+    pub biome: McIdentifier,
+    pub structures: Vec<FlatStructure>,
+}
+
+impl FlatSettings {
+    pub fn height(&self) -> i32 {
+        self.layers.iter().map(|l| l.thickness as i32).sum()
+    }
+
+    pub fn has_structure(&self, name: &str) -> bool {
+        self.structures.iter().any(|s| s.name == name)
+    }
+}
+
+impl Default for FlatSettings {
+    fn default() -> Self {
+        CLASSIC_FLAT_PRESET.parse().unwrap()
+    }
+}
+
+/// Splits on `separator`, except inside brackets and parentheses.
+fn split_top_level(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_layer(s: &str) -> Result<FlatLayer, FlatPresetError> {
+    let s = s.trim();
+    let (thickness, block) = match s.split_once('*') {
+        Some((thickness, block)) if !thickness.contains('[') => {
+            let thickness = thickness
+                .trim()
+                .parse()
+                .map_err(|_| FlatPresetError::InvalidLayer(s.to_owned()))?;
+            (thickness, block.trim())
+        }
+        _ => (1, s),
+    };
+    if thickness == 0 || block.is_empty() {
+        return Err(FlatPresetError::InvalidLayer(s.to_owned()));
+    }
+    Ok(FlatLayer {
+        block: block.parse()?,
+        thickness,
+    })
+}
+
+fn parse_structure(s: &str) -> Result<FlatStructure, FlatPresetError> {
+    let invalid = || FlatPresetError::InvalidStructure(s.to_owned());
+    let s = s.trim();
+    let (name, options) = match s.split_once('(') {
+        Some((name, rest)) => (name, rest.strip_suffix(')').ok_or_else(invalid)?),
+        None => (s, ""),
+    };
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    let options = options
+        .split_whitespace()
+        .map(|option| {
+            let (key, value) = option.split_once('=').ok_or_else(invalid)?;
+            Ok((key.to_owned(), value.to_owned()))
+        })
+        .collect::<Result<_, FlatPresetError>>()?;
+    Ok(FlatStructure {
+        name: name.to_owned(),
+        options,
+    })
+}
+
+impl FromStr for FlatSettings {
+    type Err = FlatPresetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = split_top_level(s.trim(), ';');
+        if parts[0].trim().is_empty() {
+            return Err(FlatPresetError::NoLayers);
+        }
+        let layers = split_top_level(parts[0], ',')
+            .into_iter()
+            .map(parse_layer)
+            .collect::<Result<_, _>>()?;
+        let biome = match parts.get(1).map(|b| b.trim()) {
+            Some(biome) if !biome.is_empty() => biome
+                .parse()
+                .map_err(|_| FlatPresetError::InvalidBiome(biome.to_owned()))?,
+            _ => McIdentifier::minecraft("plains").unwrap(),
+        };
+        let structures = match parts.get(2).map(|s| s.trim()) {
+            Some(structures) if !structures.is_empty() => split_top_level(structures, ',')
+                .into_iter()
+                .map(parse_structure)
+                .collect::<Result<_, _>>()?,
+            _ => Vec::new(),
+        };
+        if parts.len() > 3 {
+            return Err(FlatPresetError::InvalidStructure(parts[3..].join(";")));
+        }
+        Ok(FlatSettings {
+            layers,
+            biome,
+            structures,
+        })
+    }
+}
+
+impl fmt::Display for FlatSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, layer) in self.layers.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if layer.thickness > 1 {
+                write!(f, "{}*", layer.thickness)?;
+            }
+            let block = layer.block.block();
+            if layer.block.id() == block.default_state {
+                write!(f, "{}", block.name)?;
+            } else {
+                write!(f, "{}", layer.block)?;
+            }
+        }
+        write!(f, ";{}", self.biome)?;
+        for (i, structure) in self.structures.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { ";" } else { "," }, structure.name)?;
+            if !structure.options.is_empty() {
+                let options: Vec<String> = structure
+                    .options
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect();
+                write!(f, "({})", options.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Generates the same column everywhere. Structures are only recorded in
+/// the settings, none are placed yet.
+#[derive(Debug, Clone)]
+pub struct FlatGenerator {
+    settings: FlatSettings,
+    template: ChunkColumn,
+}
+
+impl FlatGenerator {
+    pub fn new(
+        settings: FlatSettings,
+        dimension: &DimensionType,
+        biomes: &Registry<Biome>,
+    ) -> Result<FlatGenerator, FlatPresetError> {
+        let biome = biomes
+            .id_of(&settings.biome)
+            .ok_or_else(|| FlatPresetError::UnknownBiome(settings.biome.clone()))?;
+        if settings.height() > dimension.height {
+            return Err(FlatPresetError::TooHigh {
+                height: settings.height(),
+                max: dimension.height,
+            });
+        }
+
+        let mut template = ChunkColumn::new(0, 0, dimension, biome, biomes.len());
+        let mut y = dimension.min_y;
+        for layer in &settings.layers {
+            for _ in 0..layer.thickness {
+                for x in 0..SECTION_SIZE {
+                    for z in 0..SECTION_SIZE {
+                        template.set_block(x, y, z, layer.block);
+                    }
+                }
+                y += 1;
+            }
+        }
+        Ok(FlatGenerator { settings, template })
+    }
+
+    pub fn settings(&self) -> &FlatSettings {
+        &self.settings
+    }
+}
+
+impl ChunkGenerator for FlatGenerator {
+    fn generate(&self, x: i32, z: i32) -> ChunkColumn {
+        let mut column = self.template.clone();
+        column.x = x;
+        column.z = z;
+        column
+    }
+
+    fn is_flat(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::registry::test_overworld;
+
+    #[test]
+    fn test_parse_preset() {
+        let settings: FlatSettings =
+            "minecraft:bedrock,2*dirt,grass_block[snowy=true];minecraft:swamp;village(size=1 distance=32),mineshaft"
+                .parse()
+                .unwrap();
+        assert_eq!(settings.layers.len(), 3);
+        assert_eq!(settings.layers[1].thickness, 2);
+        assert_eq!(settings.height(), 4);
+        assert_eq!(settings.biome.name(), "swamp");
+        assert!(settings.has_structure("mineshaft"));
+        assert_eq!(
+            settings.to_string(),
+            "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block[snowy=true];\
+             minecraft:swamp;village(size=1 distance=32),mineshaft"
+        );
+        assert_eq!(FlatSettings::default().to_string(), CLASSIC_FLAT_PRESET);
+
+        assert_eq!("".parse::<FlatSettings>(), Err(FlatPresetError::NoLayers));
+        assert_eq!(
+            "0*dirt".parse::<FlatSettings>(),
+            Err(FlatPresetError::InvalidLayer("0*dirt".to_owned()))
+        );
+        assert!(matches!(
+            "2*diamond_block".parse::<FlatSettings>(),
+            Err(FlatPresetError::Block(_))
+        ));
+    }
+
+    #[test]
+    fn test_generate() {
+        let (registries, dimension) = test_overworld();
+        let generator =
+            FlatGenerator::new(FlatSettings::default(), &dimension, &registries.biomes).unwrap();
+        let column = generator.generate(3, -7);
+        assert_eq!((column.x, column.z), (3, -7));
+        assert_eq!(column.surface_y(5, 5), dimension.min_y + 4);
+        assert_eq!(
+            column.get_block(5, dimension.min_y + 3, 5),
+            BlockState::from_name("grass_block").unwrap()
+        );
+        assert_eq!(column.get_biome(0, dimension.min_y / 4, 0), Some(1));
+
+        let settings = "1000*stone".parse().unwrap();
+        assert!(matches!(
+            FlatGenerator::new(settings, &dimension, &registries.biomes),
+            Err(FlatPresetError::TooHigh { .. })
+        ));
+    }
+}
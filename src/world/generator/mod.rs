@@ -0,0 +1,67 @@
+//! World generators, which fill chunk columns that aren't loaded from disk.
+
+pub mod flat;
+pub mod noise;
+pub mod terrain;
+
+use sha2::{Digest, Sha256};
+
+use super::chunk::ChunkColumn;
+
+pub trait ChunkGenerator: Send + Sync {
+    /// Generates the column at chunk coordinates `x` and `z`.
+    fn generate(&self, x: i32, z: i32) -> ChunkColumn;
+
+    /// Whether Join Game tells clients the world is superflat, which moves
+    /// the horizon down to the bottom of the world.
+    fn is_flat(&self) -> bool {
+        false
+    }
+}
+
+/// The world seed for a configured seed. As in vanilla, numbers are used as
+/// they are and any other text is hashed like Java's `String.hashCode`.
+pub fn parse_seed(seed: &str) -> i64 {
+    match seed.trim().parse() {
+        Ok(seed) => seed,
+        Err(_) => seed
+            .encode_utf16()
+            .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32))
+            as i64,
+    }
+}
+
+/// The seed sent to clients, which they only use for biome blending: the
+/// first 8 bytes of the SHA-256 of the seed, both little endian as vanilla
+/// does it.
+pub fn hashed_seed(seed: i64) -> i64 {
+    let digest = Sha256::digest(&seed.to_le_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    i64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeds() {
+        assert_eq!(parse_seed("-12345"), -12345);
+        // Expected values from Java's String.hashCode
+        assert_eq!(parse_seed("hello"), 99162322);
+        assert_eq!(parse_seed("Hello World"), -862545276);
+        assert_eq!(parse_seed("Glacier"), 1772835215);
+        assert_eq!(parse_seed("zipper \u{2744} \u{1f332}"), 749107222);
+    }
+
+    #[test]
+    fn test_hashed_seed() {
+        // Expected values from Java's MessageDigest, as vanilla hashes them
+        assert_eq!(hashed_seed(0), 8794265229978523055);
+        assert_eq!(hashed_seed(1), -6467378160175308932);
+        assert_eq!(hashed_seed(-1), 6759447113877070610);
+        assert_eq!(hashed_seed(12345), 293737985876514017);
+        assert_eq!(hashed_seed(i64::MIN), 6374347445474471398);
+    }
+}
@@ -0,0 +1,185 @@
+//! Seeded random numbers and Perlin noise. Everything here is computed from
+//! the seed alone, so a seed gives the same values on every run and platform.
+
+/// The SplitMix64 generator, used to derive everything else from a seed.
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: i64) -> SplitMix64 {
+        SplitMix64 { state: seed as u64 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    /// A float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in `[0, bound)`.
+    pub fn next_below(&mut self, bound: u32) -> u32 {
+        (((self.next_u64() >> 32) * bound as u64) >> 32) as u32
+    }
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A random value for one block position.
+pub fn hash_position(seed: i64, x: i32, y: i32, z: i32) -> u64 {
+    let mut h = mix(seed as u64 ^ 0x2545_f491_4f6c_dd1d);
+    for v in [x, y, z] {
+        h = mix(h ^ v as u32 as u64);
+    }
+    h
+}
+
+/// Improved Perlin noise with a shuffled permutation table.
+#[derive(Debug, Clone)]
+pub struct PerlinNoise {
+    permutation: [u8; 256],
+    offset: [f64; 3],
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    match hash & 15 {
+        0 | 12 => x + y,
+        1 | 14 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 | 13 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+impl PerlinNoise {
+    pub fn new(rng: &mut SplitMix64) -> PerlinNoise {
+        let offset = [
+            rng.next_f64() * 256.0,
+            rng.next_f64() * 256.0,
+            rng.next_f64() * 256.0,
+        ];
+        let mut permutation = [0; 256];
+        for (i, p) in permutation.iter_mut().enumerate() {
+            *p = i as u8;
+        }
+        for i in (1..256).rev() {
+            let j = rng.next_below(i as u32 + 1) as usize;
+            permutation.swap(i, j);
+        }
+        PerlinNoise {
+            permutation,
+            offset,
+        }
+    }
+
+    fn p(&self, i: i32) -> u8 {
+        self.permutation[(i & 255) as usize]
+    }
+
+    /// Roughly in `[-1, 1]`.
+    pub fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (x, y, z) = (x + self.offset[0], y + self.offset[1], z + self.offset[2]);
+        let (fx, fy, fz) = (x.floor(), y.floor(), z.floor());
+        let (xi, yi, zi) = (fx as i32, fy as i32, fz as i32);
+        let (x, y, z) = (x - fx, y - fy, z - fz);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let a = self.p(xi) as i32 + yi;
+        let (aa, ab) = (self.p(a) as i32 + zi, self.p(a + 1) as i32 + zi);
+        let b = self.p(xi + 1) as i32 + yi;
+        let (ba, bb) = (self.p(b) as i32 + zi, self.p(b + 1) as i32 + zi);
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(self.p(aa), x, y, z),
+                    grad(self.p(ba), x - 1.0, y, z),
+                ),
+                lerp(
+                    u,
+                    grad(self.p(ab), x, y - 1.0, z),
+                    grad(self.p(bb), x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(self.p(aa + 1), x, y, z - 1.0),
+                    grad(self.p(ba + 1), x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(self.p(ab + 1), x, y - 1.0, z - 1.0),
+                    grad(self.p(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+}
+
+/// Several octaves of Perlin noise, each at double the frequency and half
+/// the amplitude of the previous one.
+#[derive(Debug, Clone)]
+pub struct OctaveNoise {
+    octaves: Vec<PerlinNoise>,
+    /// The frequency of the first octave, in cycles per block.
+    frequency: f64,
+}
+
+impl OctaveNoise {
+    /// `salt` tells apart the noises made from the same seed.
+    pub fn new(seed: i64, salt: u64, octaves: usize, frequency: f64) -> OctaveNoise {
+        let mut rng = SplitMix64::new(mix(seed as u64 ^ mix(salt)) as i64);
+        OctaveNoise {
+            octaves: (0..octaves).map(|_| PerlinNoise::new(&mut rng)).collect(),
+            frequency,
+        }
+    }
+
+    /// Roughly in `[-1, 1]`.
+    pub fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut max = 0.0;
+        let mut frequency = self.frequency;
+        for octave in &self.octaves {
+            total += octave.sample(x * frequency, y * frequency, z * frequency) * amplitude;
+            max += amplitude;
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+        total / max
+    }
+
+    pub fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        self.sample(x, 0.0, z)
+    }
+}
@@ -0,0 +1,397 @@
+//! Terrain from seeded noise: rolling land and oceans, caves, and surface
+//! rules that pick the top blocks.
+//!
+//! Biomes are placed by climate. Every biome of vanilla's overworld biome
+//! source that is in the registry is a candidate, and each column gets the
+//! one whose `temperature`, `downfall` and `depth` are closest to the sampled
+//! temperature, humidity and continentalness.
+
+use std::{fmt, io};
+
+use super::{
+    noise::{hash_position, OctaveNoise},
+    ChunkGenerator,
+};
+use crate::{
+    mc_types::McIdentifier,
+    world::{
+        biomes::Biome,
+        blocks::{BlockState, BlockStateError},
+        chunk::{ChunkColumn, SECTION_SIZE},
+        dimensions::DimensionType,
+        registry::Registry,
+    },
+};
+
+/// The biomes of vanilla's overworld biome source, up to 1.20.5. Biomes
+/// stopped having a category in 1.19.4, so it can't tell them apart.
+const OVERWORLD_BIOMES: [&str; 53] = [
+    "badlands",
+    "bamboo_jungle",
+    "beach",
+    "birch_forest",
+    "cherry_grove",
+    "cold_ocean",
+    "dark_forest",
+    "deep_cold_ocean",
+    "deep_dark",
+    "deep_frozen_ocean",
+    "deep_lukewarm_ocean",
+    "deep_ocean",
+    "desert",
+    "dripstone_caves",
+    "eroded_badlands",
+    "flower_forest",
+    "forest",
+    "frozen_ocean",
+    "frozen_peaks",
+    "frozen_river",
+    "grove",
+    "ice_spikes",
+    "jagged_peaks",
+    "jungle",
+    "lukewarm_ocean",
+    "lush_caves",
+    "mangrove_swamp",
+    "meadow",
+    "mushroom_fields",
+    "ocean",
+    "old_growth_birch_forest",
+    "old_growth_pine_taiga",
+    "old_growth_spruce_taiga",
+    "plains",
+    "river",
+    "savanna",
+    "savanna_plateau",
+    "snowy_beach",
+    "snowy_plains",
+    "snowy_slopes",
+    "snowy_taiga",
+    "sparse_jungle",
+    "stony_peaks",
+    "stony_shore",
+    "sunflower_plains",
+    "swamp",
+    "taiga",
+    "warm_ocean",
+    "windswept_forest",
+    "windswept_gravelly_hills",
+    "windswept_hills",
+    "windswept_savanna",
+    "wooded_badlands",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerrainError {
+    NoBiomes,
+    Block(BlockStateError),
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TerrainError::NoBiomes => write!(f, "the registry has no overworld biomes"),
+            TerrainError::Block(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TerrainError {}
+
+impl From<BlockStateError> for TerrainError {
+    fn from(e: BlockStateError) -> Self {
+        TerrainError::Block(e)
+    }
+}
+
+impl From<TerrainError> for io::Error {
+    fn from(e: TerrainError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// The blocks terrain is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfaceRules {
+    pub top: BlockState,
+    pub under: BlockState,
+    /// How far `under` reaches below the top block.
+    pub under_depth: i32,
+    /// The top of the sea floor.
+    pub underwater: BlockState,
+    /// The top and under blocks within a block of sea level.
+    pub beach: BlockState,
+    pub stone: BlockState,
+    pub bedrock: BlockState,
+    pub fluid: BlockState,
+}
+
+impl SurfaceRules {
+    pub fn vanilla() -> Result<SurfaceRules, BlockStateError> {
+        Ok(SurfaceRules {
+            top: BlockState::from_name("grass_block")?,
+            under: BlockState::from_name("dirt")?,
+            under_depth: 3,
+            underwater: BlockState::from_name("gravel")?,
+            beach: BlockState::from_name("sand")?,
+            stone: BlockState::from_name("stone")?,
+            bedrock: BlockState::from_name("bedrock")?,
+            fluid: BlockState::from_name("water")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoiseSettings {
+    pub sea_level: i32,
+    pub surface: SurfaceRules,
+}
+
+impl NoiseSettings {
+    pub fn vanilla() -> Result<NoiseSettings, BlockStateError> {
+        Ok(NoiseSettings {
+            sea_level: 63,
+            surface: SurfaceRules::vanilla()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BiomeClimate {
+    id: i32,
+    temperature: f64,
+    downfall: f64,
+    depth: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NoiseGenerator {
+    seed: i64,
+    settings: NoiseSettings,
+    dimension: DimensionType,
+    biome_count: usize,
+    biomes: Vec<BiomeClimate>,
+    continents: OctaveNoise,
+    hills: OctaveNoise,
+    detail: OctaveNoise,
+    temperature: OctaveNoise,
+    humidity: OctaveNoise,
+    tunnels: [OctaveNoise; 2],
+    caverns: OctaveNoise,
+}
+
+impl NoiseGenerator {
+    pub fn new(
+        seed: i64,
+        settings: NoiseSettings,
+        dimension: &DimensionType,
+        biomes: &Registry<Biome>,
+    ) -> Result<NoiseGenerator, TerrainError> {
+        let candidates: Vec<BiomeClimate> = biomes
+            .iter()
+            .filter(|entry| {
+                let name = &entry.name;
+                name.namespace() == McIdentifier::DEFAULT_NAMESPACE
+                    && OVERWORLD_BIOMES.contains(&name.name())
+            })
+            .map(|entry| BiomeClimate {
+                id: entry.id,
+                temperature: entry.element.temperature as f64,
+                downfall: entry.element.downfall as f64,
+                depth: entry.element.depth.unwrap_or(0.1) as f64,
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Err(TerrainError::NoBiomes);
+        }
+        Ok(NoiseGenerator {
+            seed,
+            settings,
+            dimension: dimension.clone(),
+            biome_count: biomes.len(),
+            biomes: candidates,
+            continents: OctaveNoise::new(seed, 1, 4, 1.0 / 512.0),
+            hills: OctaveNoise::new(seed, 2, 4, 1.0 / 128.0),
+            detail: OctaveNoise::new(seed, 3, 3, 1.0 / 24.0),
+            temperature: OctaveNoise::new(seed, 4, 3, 1.0 / 768.0),
+            humidity: OctaveNoise::new(seed, 5, 3, 1.0 / 768.0),
+            tunnels: [
+                OctaveNoise::new(seed, 6, 2, 1.0 / 64.0),
+                OctaveNoise::new(seed, 7, 2, 1.0 / 64.0),
+            ],
+            caverns: OctaveNoise::new(seed, 8, 3, 1.0 / 96.0),
+        })
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    pub fn settings(&self) -> &NoiseSettings {
+        &self.settings
+    }
+
+    /// How far inland a column is, below zero for oceans.
+    fn continentalness(&self, x: f64, z: f64) -> f64 {
+        self.continents.sample_2d(x, z) * 2.0 + 0.1
+    }
+
+    /// The biome at block column `x`, `z`.
+    pub fn biome_at(&self, x: i32, z: i32) -> i32 {
+        let (x, z) = (x as f64, z as f64);
+        let temperature = 0.8 + self.temperature.sample_2d(x, z) * 1.5;
+        let downfall = 0.5 + self.humidity.sample_2d(x, z) * 1.2;
+        let continentalness = self.continentalness(x, z);
+        let distance = |b: &BiomeClimate| {
+            (temperature - b.temperature).powi(2)
+                + (downfall - b.downfall).powi(2)
+                + (continentalness - b.depth).powi(2) * 4.0
+        };
+        self.biomes
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap()
+            .id
+    }
+
+    /// The Y of the top block of column `x`, `z`.
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let (x, z) = (x as f64, z as f64);
+        let continentalness = self.continentalness(x, z);
+        let hills = self.hills.sample_2d(x, z) * (6.0 + 20.0 * continentalness.max(0.0));
+        let detail = self.detail.sample_2d(x, z) * 2.0;
+        let height = self.settings.sea_level as f64 + 1.0 + continentalness * 24.0 + hills + detail;
+        let min_y = self.dimension.min_y + 8;
+        let max_y = self.dimension.max_y() - 8;
+        (height.round() as i32).clamp(min_y, max_y.max(min_y))
+    }
+
+    fn is_bedrock(&self, x: i32, y: i32, z: i32) -> bool {
+        let depth = y - self.dimension.min_y;
+        depth == 0 || (depth < 5 && (hash_position(self.seed, x, y, z) % 5) as i32 >= depth)
+    }
+
+    /// Whether a cave runs through `x`, `y`, `z` in a column topped at `top`.
+    fn is_cave(&self, x: i32, y: i32, z: i32, top: i32) -> bool {
+        if y <= self.dimension.min_y + 4 || y > top {
+            return false;
+        }
+        // Keep the sea floor sealed so oceans don't drain into caves
+        if top <= self.settings.sea_level && y > top - 8 {
+            return false;
+        }
+        let (x, y, z) = (x as f64, y as f64, z as f64);
+        let tunnel = self.tunnels[0].sample(x, y * 1.5, z).abs() < 0.04
+            && self.tunnels[1].sample(x, y * 1.5, z).abs() < 0.06;
+        tunnel || (y < (top - 12) as f64 && self.caverns.sample(x, y * 2.0, z) > 0.32)
+    }
+
+    fn surface_block(&self, y: i32, top: i32) -> BlockState {
+        let rules = &self.settings.surface;
+        let sea_level = self.settings.sea_level;
+        if y <= top - 1 - rules.under_depth {
+            rules.stone
+        } else if top < sea_level - 1 {
+            if y == top {
+                rules.underwater
+            } else {
+                rules.under
+            }
+        } else if top <= sea_level + 1 {
+            rules.beach
+        } else if y == top {
+            rules.top
+        } else {
+            rules.under
+        }
+    }
+}
+
+impl ChunkGenerator for NoiseGenerator {
+    fn generate(&self, x: i32, z: i32) -> ChunkColumn {
+        let (base_x, base_z) = (x * SECTION_SIZE as i32, z * SECTION_SIZE as i32);
+        let first_biome = self.biomes[0].id;
+        let mut column = ChunkColumn::new(x, z, &self.dimension, first_biome, self.biome_count);
+
+        let (min_cell, max_cell) = (self.dimension.min_y / 4, self.dimension.max_y() / 4);
+        for cell_x in 0..4 {
+            for cell_z in 0..4 {
+                let biome = self.biome_at(
+                    base_x + cell_x as i32 * 4 + 2,
+                    base_z + cell_z as i32 * 4 + 2,
+                );
+                for cell_y in min_cell..max_cell {
+                    column.set_biome(cell_x, cell_y, cell_z, biome);
+                }
+            }
+        }
+
+        let rules = self.settings.surface;
+        for bx in 0..SECTION_SIZE {
+            for bz in 0..SECTION_SIZE {
+                let (wx, wz) = (base_x + bx as i32, base_z + bz as i32);
+                let top = self.surface_height(wx, wz);
+                for y in self.dimension.min_y..=top.max(self.settings.sea_level) {
+                    let state = if y > top {
+                        rules.fluid
+                    } else if self.is_bedrock(wx, y, wz) {
+                        rules.bedrock
+                    } else if self.is_cave(wx, y, wz, top) {
+                        continue;
+                    } else {
+                        self.surface_block(y, top)
+                    };
+                    column.set_block(bx, y, bz, state);
+                }
+            }
+        }
+        column
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::registry::test_overworld;
+
+    #[test]
+    fn test_same_seed_same_world() {
+        let (registries, dimension) = test_overworld();
+        let settings = NoiseSettings::vanilla().unwrap();
+        let generator = |seed| NoiseGenerator::new(seed, settings, &dimension, &registries.biomes);
+
+        let (a, b, c) = (
+            generator(42).unwrap(),
+            generator(42).unwrap(),
+            generator(43).unwrap(),
+        );
+        assert_eq!(a.generate(5, -3), b.generate(5, -3));
+        assert_ne!(a.generate(5, -3), c.generate(5, -3));
+
+        // basalt_deltas is a nether biome
+        let basalt_deltas = McIdentifier::minecraft("basalt_deltas").unwrap();
+        let basalt_deltas = registries.biomes.id_of(&basalt_deltas).unwrap();
+        assert!(a.biomes.iter().all(|b| b.id != basalt_deltas));
+
+        // Biomes from 1.19.4 and later have no category
+        let mut biomes = registries.biomes.clone();
+        for entry in registries.biomes.iter() {
+            let mut biome = entry.element.clone();
+            biome.category = "none".to_owned();
+            biomes.register(entry.name.clone(), biome);
+        }
+        let uncategorized = NoiseGenerator::new(42, settings, &dimension, &biomes).unwrap();
+        let ids: Vec<i32> = uncategorized.biomes.iter().map(|b| b.id).collect();
+        let ids_before: Vec<i32> = a.biomes.iter().map(|b| b.id).collect();
+        assert_eq!(ids, ids_before);
+        assert_eq!(ids.len(), 3);
+
+        let column = a.generate(0, 0);
+        let top = a.surface_height(0, 0);
+        assert_eq!(
+            column.get_block(0, dimension.min_y, 0),
+            settings.surface.bedrock
+        );
+        assert_eq!(column.surface_y(0, 0), top.max(settings.sea_level) + 1);
+    }
+}
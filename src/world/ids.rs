@@ -0,0 +1,337 @@
+//! Numeric IDs of the static registries from the vanilla `registries.json`
+//! report: items, entity types, particles, sound events, menus, potion
+//! effects and block entity types.
+//!
+//! Like the block report, the registries are installed once at startup, and
+//! the server needs the report to start. The excerpt in `registries.json`
+//! next to this file is only for code that runs without one, like tests. It
+//! has the 1.18 menus, effects and block entity types but only a few items
+//! and entity types.
+
+use std::{collections::HashMap, fmt, io, str::FromStr, sync::OnceLock};
+
+use serde_json::Value;
+
+use super::schema::{RegistryError, RegistryErrors};
+use crate::mc_types::McIdentifier;
+
+/// The shipped excerpt of the report, used when none is installed.
+pub const EMBEDDED_REGISTRIES: &str = include_str!("registries.json");
+
+/// Names and protocol IDs of one registry in the report.
+#[derive(Debug, Clone)]
+pub struct IdRegistry {
+    registry_type: McIdentifier,
+    ids: HashMap<McIdentifier, i32>,
+    names: HashMap<i32, McIdentifier>,
+    default: Option<McIdentifier>,
+}
+
+impl IdRegistry {
+    fn from_report(
+        file: &str,
+        registry_type: &str,
+        report: &Value,
+    ) -> Result<IdRegistry, RegistryError> {
+        let error = |message: String| RegistryError::Field {
+            file: file.to_owned(),
+            entry: registry_type.to_owned(),
+            field: "".to_owned(),
+            message,
+        };
+        let registry = report
+            .get(registry_type)
+            .ok_or_else(|| error("missing from the report".to_owned()))?;
+        let entries = registry
+            .get("entries")
+            .and_then(Value::as_object)
+            .ok_or_else(|| error("missing entries".to_owned()))?;
+
+        let mut ids = HashMap::new();
+        let mut names = HashMap::new();
+        for (name, entry) in entries {
+            let parsed: McIdentifier = name
+                .parse()
+                .map_err(|e| error(format!("invalid name {}: {}", name, e)))?;
+            let id = entry
+                .get("protocol_id")
+                .and_then(Value::as_i64)
+                .ok_or_else(|| error(format!("{} has no protocol_id", name)))?
+                as i32;
+            if let Some(other) = names.insert(id, parsed.clone()) {
+                return Err(error(format!("{} and {} share ID {}", other, name, id)));
+            }
+            ids.insert(parsed, id);
+        }
+        let default = match registry.get("default").and_then(Value::as_str) {
+            Some(name) => {
+                let name: McIdentifier = name
+                    .parse()
+                    .ok()
+                    .filter(|name| ids.contains_key(name))
+                    .ok_or_else(|| error(format!("default {} is not an entry", name)))?;
+                Some(name)
+            }
+            None => None,
+        };
+        Ok(IdRegistry {
+            registry_type: registry_type.parse().unwrap(),
+            ids,
+            names,
+            default,
+        })
+    }
+
+    pub fn registry_type(&self) -> &McIdentifier {
+        &self.registry_type
+    }
+
+    pub fn id_of(&self, name: &McIdentifier) -> Option<i32> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name_of(&self, id: i32) -> Option<&McIdentifier> {
+        self.names.get(&id)
+    }
+
+    /// The entry that unknown names fall back to in vanilla, such as
+    /// `minecraft:air` for items.
+    pub fn default(&self) -> Option<&McIdentifier> {
+        self.default.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StaticRegistries {
+    pub items: IdRegistry,
+    pub entity_types: IdRegistry,
+    pub particle_types: IdRegistry,
+    pub sound_events: IdRegistry,
+    pub menus: IdRegistry,
+    pub mob_effects: IdRegistry,
+    pub block_entity_types: IdRegistry,
+}
+
+impl StaticRegistries {
+    /// Reads a `registries.json` report. `file` is only used in error
+    /// messages.
+    pub fn from_report(file: &str, text: &str) -> Result<StaticRegistries, RegistryErrors> {
+        let report: Value = serde_json::from_str(text).map_err(|e| RegistryError::Syntax {
+            file: file.to_owned(),
+            message: e.to_string(),
+        })?;
+        let mut errors = Vec::new();
+        let mut read = |registry_type: &str| {
+            IdRegistry::from_report(file, registry_type, &report)
+                .map_err(|e| errors.push(e))
+                .ok()
+        };
+        let items = read("minecraft:item");
+        let entity_types = read("minecraft:entity_type");
+        let particle_types = read("minecraft:particle_type");
+        let sound_events = read("minecraft:sound_event");
+        let menus = read("minecraft:menu");
+        let mob_effects = read("minecraft:mob_effect");
+        let block_entity_types = read("minecraft:block_entity_type");
+        match (
+            items,
+            entity_types,
+            particle_types,
+            sound_events,
+            menus,
+            mob_effects,
+            block_entity_types,
+        ) {
+            (
+                Some(items),
+                Some(entity_types),
+                Some(particle_types),
+                Some(sound_events),
+                Some(menus),
+                Some(mob_effects),
+                Some(block_entity_types),
+            ) => Ok(StaticRegistries {
+                items,
+                entity_types,
+                particle_types,
+                sound_events,
+                menus,
+                mob_effects,
+                block_entity_types,
+            }),
+            _ => Err(RegistryErrors(errors)),
+        }
+    }
+
+    /// Reads the report at `file`.
+    pub fn load(file: &str) -> Result<StaticRegistries, RegistryErrors> {
+        let text = std::fs::read_to_string(file).map_err(|error| RegistryError::Io {
+            file: file.to_owned(),
+            error,
+        })?;
+        StaticRegistries::from_report(file, &text)
+    }
+
+    pub fn embedded() -> StaticRegistries {
+        StaticRegistries::from_report("<embedded registries.json>", EMBEDDED_REGISTRIES).unwrap()
+    }
+}
+
+static REGISTRIES: OnceLock<StaticRegistries> = OnceLock::new();
+static CONFIGURED_REGISTRIES: OnceLock<StaticRegistries> = OnceLock::new();
+
+/// Makes `registries` the ones used by the typed IDs. Fails if registries
+/// are already in use.
+pub fn install(registries: StaticRegistries) -> Result<(), Box<StaticRegistries>> {
+    REGISTRIES.set(registries).map_err(Box::new)
+}
+
+/// The installed registries, or the embedded excerpt if none were installed.
+pub fn static_registries() -> &'static StaticRegistries {
+    REGISTRIES.get_or_init(StaticRegistries::embedded)
+}
+
+/// Makes `registries` the ones 1.20.5 clients number items and the like
+/// with. Fails if some are already installed.
+pub fn install_configured(registries: StaticRegistries) -> Result<(), Box<StaticRegistries>> {
+    CONFIGURED_REGISTRIES.set(registries).map_err(Box::new)
+}
+
+/// The registries of 1.20.5 clients, `None` without `install_configured`.
+pub fn configured_static_registries() -> Option<&'static StaticRegistries> {
+    CONFIGURED_REGISTRIES.get()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownIdError {
+    pub registry_type: McIdentifier,
+    pub name: String,
+}
+
+impl fmt::Display for UnknownIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not in {}", self.name, self.registry_type)
+    }
+}
+
+impl std::error::Error for UnknownIdError {}
+
+impl From<UnknownIdError> for io::Error {
+    fn from(e: UnknownIdError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Defines a newtype over the protocol ID of one of the static registries.
+macro_rules! static_id {
+    ($(#[$meta:meta])* $name:ident, $field:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(i32);
+
+        impl $name {
+            pub fn from_name(name: &str) -> Result<$name, UnknownIdError> {
+                let registry = &static_registries().$field;
+                name.parse()
+                    .ok()
+                    .and_then(|name| registry.id_of(&name))
+                    .map($name)
+                    .ok_or_else(|| UnknownIdError {
+                        registry_type: registry.registry_type().clone(),
+                        name: name.to_owned(),
+                    })
+            }
+
+            pub fn from_id(id: i32) -> Option<$name> {
+                static_registries().$field.name_of(id).map(|_| $name(id))
+            }
+
+            pub fn id(self) -> i32 {
+                self.0
+            }
+
+            pub fn name(self) -> &'static McIdentifier {
+                static_registries().$field.name_of(self.0).unwrap()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.name())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = UnknownIdError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $name::from_name(s)
+            }
+        }
+    };
+}
+
+static_id!(Item, items);
+static_id!(EntityType, entity_types);
+static_id!(ParticleType, particle_types);
+static_id!(SoundEvent, sound_events);
+static_id!(
+    /// A container window type, as sent in Open Screen.
+    Menu,
+    menus
+);
+static_id!(
+    /// A potion effect.
+    MobEffect,
+    mob_effects
+);
+static_id!(
+    /// The kind of a block entity, as sent in Chunk Data.
+    BlockEntityType,
+    block_entity_types
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded() {
+        let stone = Item::from_name("minecraft:stone").unwrap();
+        assert_eq!(stone.id(), 1);
+        assert_eq!(stone.to_string(), "minecraft:stone");
+        assert_eq!(
+            static_registries().items.default().unwrap().to_string(),
+            "minecraft:air"
+        );
+        assert_eq!("speed".parse::<MobEffect>().unwrap().id(), 1);
+        assert_eq!(Menu::from_id(11).unwrap().name().name(), "crafting");
+        assert_eq!(BlockEntityType::from_name("chest").unwrap().id(), 1);
+        assert_eq!(
+            EntityType::from_name("minecraft:dragon")
+                .unwrap_err()
+                .to_string(),
+            "minecraft:dragon is not in minecraft:entity_type"
+        );
+    }
+
+    #[test]
+    fn test_report_errors() {
+        let report = r#"{"minecraft:item": {"default": "minecraft:air", "entries": {
+            "minecraft:stone": {"protocol_id": 1}, "minecraft:dirt": {"protocol_id": 1}}}}"#;
+        let errors = StaticRegistries::from_report("test.json", report).unwrap_err();
+        assert_eq!(errors.0.len(), 7);
+        assert_eq!(
+            errors.0[1].to_string(),
+            "test.json: minecraft:entity_type: : missing from the report"
+        );
+    }
+}
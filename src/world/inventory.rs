@@ -0,0 +1,128 @@
+use std::io::{self, Read, Write};
+
+use crate::mc_types::{
+    ext::{McReadExt, McWriteExt},
+    nbt_io::{read_nbt, write_nbt, NbtFormat, NbtLimits},
+};
+
+use super::ids::{IdRegistry, Item, UnknownIdError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    pub item: Item,
+    pub count: i8,
+    /// Damage, enchantments, display name and the like.
+    pub nbt: Option<nbt::Value>,
+}
+
+impl ItemStack {
+    pub fn new(item: Item, count: i8) -> ItemStack {
+        ItemStack {
+            item,
+            count,
+            nbt: None,
+        }
+    }
+
+    /// A stack of the item with this identifier.
+    pub fn from_name(name: &str, count: i8) -> Result<ItemStack, UnknownIdError> {
+        Ok(ItemStack::new(Item::from_name(name)?, count))
+    }
+}
+
+/// Writes an inventory slot in the 1.18 format.
+pub fn write_slot(buf: &mut impl Write, slot: Option<&ItemStack>) -> io::Result<usize> {
+    let stack = match slot {
+        Some(stack) => stack,
+        None => return buf.write_mc_bool(false),
+    };
+    let mut count = buf.write_mc_bool(true)?;
+    count += buf.write_mc_varint(stack.item.id())?;
+    count += buf.write_mc_byte(stack.count)?;
+    count += match &stack.nbt {
+        Some(nbt) => write_nbt(buf, "", nbt, NbtFormat::Named)?,
+        None => buf.write_mc_byte(0)?, // TAG_End
+    };
+    Ok(count)
+}
+
+/// Writes an inventory slot in the 1.20.5 format, whose item IDs come from
+/// `items`. Items it lacks are sent as empty slots. Item NBT is left out,
+/// as 1.20.5 replaced it with data components.
+pub fn write_slot_configured(
+    buf: &mut impl Write,
+    slot: Option<&ItemStack>,
+    items: &IdRegistry,
+) -> io::Result<usize> {
+    let (stack, id) = match slot.and_then(|s| Some((s, items.id_of(s.item.name())?))) {
+        Some((stack, id)) if stack.count > 0 => (stack, id),
+        _ => return buf.write_mc_varint(0),
+    };
+    let mut count = buf.write_mc_varint(stack.count as i32)?;
+    count += buf.write_mc_varint(id)?;
+    count += buf.write_mc_varint(0)?; // components added
+    count += buf.write_mc_varint(0)?; // components removed
+    Ok(count)
+}
+
+/// Reads an inventory slot in the 1.18 format.
+pub fn read_slot(reader: &mut impl Read) -> io::Result<Option<ItemStack>> {
+    if !reader.read_mc_bool()? {
+        return Ok(None);
+    }
+    let id = reader.read_mc_varint()?;
+    let item = Item::from_id(id).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown item ID {}", id),
+        )
+    })?;
+    let count = reader.read_mc_byte()?;
+    let nbt = match reader.read_mc_byte()? {
+        0 => None,
+        tag => {
+            let tag = [tag as u8];
+            let mut reader = (&tag[..]).chain(reader);
+            let (_, nbt) = read_nbt(&mut reader, NbtFormat::Named, &NbtLimits::default())
+                .map_err(io::Error::from)?;
+            Some(nbt)
+        }
+    };
+    Ok(Some(ItemStack { item, count, nbt }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mc_types::slice::McSliceReader, world::ids::static_registries};
+
+    #[test]
+    fn test_slot_round_trip() {
+        let mut stack = ItemStack::from_name("minecraft:stone", 64).unwrap();
+        let mut tag = nbt::Map::new();
+        tag.insert("Damage".to_owned(), nbt::Value::Int(3));
+        stack.nbt = Some(nbt::Value::Compound(tag));
+
+        for slot in [None, Some(ItemStack::new(stack.item, 1)), Some(stack)] {
+            let mut buf = Vec::new();
+            let count = write_slot(&mut buf, slot.as_ref()).unwrap();
+            assert_eq!(count, buf.len());
+            let mut reader = McSliceReader::new(&buf);
+            assert_eq!(read_slot(&mut reader).unwrap(), slot);
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_configured_slot() {
+        let items = &static_registries().items;
+        let stone = ItemStack::from_name("minecraft:stone", 64).unwrap();
+        let mut buf = Vec::new();
+        write_slot_configured(&mut buf, Some(&stone), items).unwrap();
+        assert_eq!(buf, [64, stone.item.id() as u8, 0, 0]);
+
+        buf.clear();
+        write_slot_configured(&mut buf, None, items).unwrap();
+        assert_eq!(buf, [0]);
+    }
+}
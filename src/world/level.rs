@@ -0,0 +1,729 @@
+//! A loaded world: its dimension, the chunks in memory and where missing
+//! chunks come from.
+//!
+//! Each game tick runs the scheduled block ticks that are due, then random
+//! ticks in every chunk whose neighbours are all loaded. Ticks that come due
+//! in a chunk that isn't loaded wait for it. Blocks changed
+//! through `place_block` tell their six neighbours, whose updates are
+//! queued and run in order, so long chains don't recurse.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::sync::broadcast;
+
+use super::{
+    anvil::{chunk_to_nbt, AnvilError, RegionStorage},
+    behavior::BlockBehaviors,
+    blocks::BlockState,
+    chunk::{ChunkColumn, HeightmapKind, SECTION_SIZE},
+    dimensions::DimensionType,
+    generator::ChunkGenerator,
+    light::{ChunkMap, LightEngine, LightKind},
+    player::DataKept,
+    registry::RegistrySet,
+    tick::{BlockPos, ScheduledTick, TickPriority, TickScheduler},
+};
+use crate::mc_types::McIdentifier;
+
+pub struct Level {
+    name: McIdentifier,
+    dimension_type: McIdentifier,
+    dimension: DimensionType,
+    hashed_seed: i64,
+    registries: Arc<RegistrySet>,
+    generator: Arc<dyn ChunkGenerator>,
+    /// Shared with the saves in progress, which write without the level.
+    storage: Option<Arc<Mutex<RegionStorage>>>,
+    chunks: ChunkMap,
+    /// Chunks whose light changed since `take_light_changes`.
+    light_changes: HashSet<(i32, i32)>,
+    /// Chunks changed since they were last saved.
+    dirty: HashSet<(i32, i32)>,
+    /// Chunks that failed to load, which are generated again but never
+    /// saved over what's on disk.
+    unsaved: HashSet<(i32, i32)>,
+    /// Blocks changed since `take_block_changes`.
+    block_changes: HashSet<BlockPos>,
+    behaviors: Arc<BlockBehaviors>,
+    /// Game ticks since the level was created.
+    time: i64,
+    ticks: TickScheduler,
+    /// Due ticks in chunks that aren't loaded, put back when they are.
+    waiting_ticks: HashMap<(i32, i32), Vec<ScheduledTick>>,
+    random_tick_speed: u32,
+    /// What players keep when they move into the level.
+    keep: DataKept,
+    random: StdRng,
+    /// Positions to update and the neighbour that changed.
+    neighbor_updates: VecDeque<(BlockPos, BlockPos)>,
+    updating_neighbors: bool,
+}
+
+/// The most scheduled ticks run in one game tick, as in vanilla.
+pub const MAX_SCHEDULED_TICKS: usize = 65536;
+/// Vanilla's default randomTickSpeed.
+pub const DEFAULT_RANDOM_TICK_SPEED: u32 = 3;
+/// The order vanilla updates neighbours in: west, east, down, up, north,
+/// south.
+const NEIGHBORS: [BlockPos; 6] = [
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, -1, 0),
+    (0, 1, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+];
+
+impl Level {
+    /// A world of `dimension_type`, which must be in `registries`.
+    pub fn new(
+        name: McIdentifier,
+        dimension_type: McIdentifier,
+        registries: Arc<RegistrySet>,
+        hashed_seed: i64,
+        generator: Arc<dyn ChunkGenerator>,
+    ) -> Option<Level> {
+        let dimension = registries
+            .dimension_types
+            .get(&dimension_type)?
+            .element
+            .clone();
+        Some(Level {
+            name,
+            dimension_type,
+            dimension,
+            hashed_seed,
+            registries,
+            generator,
+            storage: None,
+            chunks: HashMap::new(),
+            light_changes: HashSet::new(),
+            dirty: HashSet::new(),
+            unsaved: HashSet::new(),
+            block_changes: HashSet::new(),
+            behaviors: Arc::new(BlockBehaviors::vanilla()),
+            time: 0,
+            ticks: TickScheduler::new(),
+            waiting_ticks: HashMap::new(),
+            random_tick_speed: DEFAULT_RANDOM_TICK_SPEED,
+            keep: DataKept::ALL,
+            random: StdRng::seed_from_u64(hashed_seed as u64),
+            neighbor_updates: VecDeque::new(),
+            updating_neighbors: false,
+        })
+    }
+
+    /// Replaces the vanilla block behaviour.
+    pub fn with_behaviors(mut self, behaviors: Arc<BlockBehaviors>) -> Level {
+        self.behaviors = behaviors;
+        self
+    }
+
+    /// Loads chunks from region files before generating them, and saves
+    /// them there.
+    pub fn with_storage(mut self, storage: RegionStorage) -> Level {
+        self.storage = Some(Arc::new(Mutex::new(storage)));
+        self
+    }
+
+    pub fn name(&self) -> &McIdentifier {
+        &self.name
+    }
+
+    pub fn dimension_type(&self) -> &McIdentifier {
+        &self.dimension_type
+    }
+
+    pub fn dimension(&self) -> &DimensionType {
+        &self.dimension
+    }
+
+    /// The seed as sent in Join Game.
+    pub fn hashed_seed(&self) -> i64 {
+        self.hashed_seed
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.generator.is_flat()
+    }
+
+    pub fn is_loaded(&self, x: i32, z: i32) -> bool {
+        self.chunks.contains_key(&(x, z))
+    }
+
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// The randomTickSpeed gamerule: how many blocks of each section get a
+    /// random tick every game tick.
+    pub fn random_tick_speed(&self) -> u32 {
+        self.random_tick_speed
+    }
+
+    pub fn set_random_tick_speed(&mut self, speed: u32) {
+        self.random_tick_speed = speed;
+    }
+
+    /// What players keep when they move into the level, sent in Respawn.
+    pub fn keep(&self) -> DataKept {
+        self.keep
+    }
+
+    pub fn set_keep(&mut self, keep: DataKept) {
+        self.keep = keep;
+    }
+
+    /// The level's random numbers, for block behaviour.
+    pub fn random(&mut self) -> &mut StdRng {
+        &mut self.random
+    }
+
+    /// The chunk at `x`, `z`, loaded or generated if it isn't in memory.
+    /// Chunks that fail to load are generated again.
+    pub fn chunk(&mut self, x: i32, z: i32) -> &ChunkColumn {
+        self.ensure_chunk(x, z);
+        &self.chunks[&(x, z)]
+    }
+
+    /// Like `chunk`, and marks the chunk to be saved.
+    pub fn chunk_mut(&mut self, x: i32, z: i32) -> &mut ChunkColumn {
+        self.ensure_chunk(x, z);
+        self.dirty.insert((x, z));
+        self.chunks.get_mut(&(x, z)).unwrap()
+    }
+
+    pub fn get_block(&mut self, x: i32, y: i32, z: i32) -> BlockState {
+        let size = SECTION_SIZE as i32;
+        let column = self.chunk(x.div_euclid(size), z.div_euclid(size));
+        column.get_block(x.rem_euclid(size) as usize, y, z.rem_euclid(size) as usize)
+    }
+
+    /// The Y above the highest block `kind` stops at in column `x`, `z`.
+    pub fn height_at(&mut self, kind: HeightmapKind, x: i32, z: i32) -> i32 {
+        let size = SECTION_SIZE as i32;
+        let column = self.chunk(x.div_euclid(size), z.div_euclid(size));
+        column.height_at(
+            kind,
+            x.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        )
+    }
+
+    /// Sets the block at world coordinates, returning the old one.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, state: BlockState) -> BlockState {
+        let size = SECTION_SIZE as i32;
+        let column = self.chunk_mut(x.div_euclid(size), z.div_euclid(size));
+        let (cx, cz) = (x.rem_euclid(size) as usize, z.rem_euclid(size) as usize);
+        let old = column.set_block(cx, y, cz, state);
+        if old != state {
+            self.block_changes.insert((x, y, z));
+            self.light_engine().update_block(x, y, z);
+            self.mark_light_dirty();
+        }
+        old
+    }
+
+    /// Like `set_block`, and runs the block's behaviour for being placed and
+    /// updates its neighbours.
+    pub fn place_block(&mut self, pos: BlockPos, state: BlockState) -> BlockState {
+        let (x, y, z) = pos;
+        let old = self.set_block(x, y, z, state);
+        if old == state {
+            return old;
+        }
+        if let Some(behavior) = self.behaviors.get(state).cloned() {
+            behavior.placed(self, pos, state, old);
+        }
+        self.update_neighbors(pos);
+        old
+    }
+
+    /// Tells the six blocks around `pos` that it changed. Neighbours in
+    /// chunks that aren't loaded are skipped.
+    pub fn update_neighbors(&mut self, pos: BlockPos) {
+        let (x, y, z) = pos;
+        for (dx, dy, dz) in NEIGHBORS.iter().copied() {
+            self.neighbor_updates
+                .push_back(((x + dx, y + dy, z + dz), pos));
+        }
+        // Updates caused by updates join the queue
+        if self.updating_neighbors {
+            return;
+        }
+        self.updating_neighbors = true;
+        let size = SECTION_SIZE as i32;
+        while let Some((pos, from)) = self.neighbor_updates.pop_front() {
+            let (x, y, z) = pos;
+            if !self.is_loaded(x.div_euclid(size), z.div_euclid(size)) {
+                continue;
+            }
+            let state = self.get_block(x, y, z);
+            if let Some(behavior) = self.behaviors.get(state).cloned() {
+                behavior.neighbor_changed(self, pos, state, from);
+            }
+        }
+        self.updating_neighbors = false;
+    }
+
+    /// Ticks the block of `state` at `pos` after `delay` game ticks, unless
+    /// it already has a tick pending there.
+    pub fn schedule_tick(
+        &mut self,
+        pos: BlockPos,
+        state: BlockState,
+        delay: i64,
+        priority: TickPriority,
+    ) -> bool {
+        self.ticks.schedule(pos, state, self.time + delay, priority)
+    }
+
+    /// The higher of the sky and block light at `pos`, or 0 outside the
+    /// light sections.
+    pub fn brightness(&mut self, (x, y, z): BlockPos) -> u8 {
+        let engine = self.light_engine();
+        let sky = engine.get(LightKind::Sky, x, y, z).unwrap_or(0);
+        let block = engine.get(LightKind::Block, x, y, z).unwrap_or(0);
+        sky.max(block)
+    }
+
+    /// Runs one game tick.
+    pub fn tick(&mut self) {
+        self.time += 1;
+        self.run_scheduled_ticks();
+        self.run_random_ticks();
+    }
+
+    fn run_scheduled_ticks(&mut self) {
+        let size = SECTION_SIZE as i32;
+        for tick in self.ticks.take_due(self.time, MAX_SCHEDULED_TICKS) {
+            let (x, y, z) = tick.pos;
+            let chunk = (x.div_euclid(size), z.div_euclid(size));
+            if !self.is_loaded(chunk.0, chunk.1) {
+                self.waiting_ticks.entry(chunk).or_default().push(tick);
+                continue;
+            }
+            let state = self.get_block(x, y, z);
+            if !tick.is_for(state) {
+                continue;
+            }
+            if let Some(behavior) = self.behaviors.get(state).cloned() {
+                behavior.scheduled_tick(self, tick.pos, state);
+            }
+        }
+    }
+
+    /// Chunks tick once every chunk around them is loaded, so blocks at the
+    /// edge don't load or generate more chunks.
+    fn ticking_chunks(&self) -> Vec<(i32, i32)> {
+        let mut chunks: Vec<(i32, i32)> = self
+            .chunks
+            .keys()
+            .copied()
+            .filter(|&(x, z)| (-1..=1).all(|dx| (-1..=1).all(|dz| self.is_loaded(x + dx, z + dz))))
+            .collect();
+        // In a fixed order, so the same seed ticks the same blocks
+        chunks.sort_unstable();
+        chunks
+    }
+
+    fn run_random_ticks(&mut self) {
+        if self.random_tick_speed == 0 {
+            return;
+        }
+        let size = SECTION_SIZE as i32;
+        for (chunk_x, chunk_z) in self.ticking_chunks() {
+            let (min_y, sections) = {
+                let column = &self.chunks[&(chunk_x, chunk_z)];
+                (column.min_y(), column.sections().len())
+            };
+            for i in 0..sections {
+                if self.chunks[&(chunk_x, chunk_z)].sections()[i].is_empty() {
+                    continue;
+                }
+                for _ in 0..self.random_tick_speed {
+                    let x = self.random.gen_range(0..size);
+                    let y = self.random.gen_range(0..size);
+                    let z = self.random.gen_range(0..size);
+                    let section = &self.chunks[&(chunk_x, chunk_z)].sections()[i];
+                    let state = section.get_block(x as usize, y as usize, z as usize);
+                    let behavior = match self.behaviors.get(state) {
+                        Some(behavior) if behavior.ticks_randomly(state) => behavior.clone(),
+                        _ => continue,
+                    };
+                    let pos = (
+                        chunk_x * size + x,
+                        min_y + i as i32 * size + y,
+                        chunk_z * size + z,
+                    );
+                    behavior.random_tick(self, pos, state);
+                }
+            }
+        }
+    }
+
+    /// The blocks changed since the last call, which players who have
+    /// their chunk need a Block Change for.
+    pub fn take_block_changes(&mut self) -> Vec<BlockPos> {
+        self.block_changes.drain().collect()
+    }
+
+    pub fn light_engine(&mut self) -> LightEngine<'_> {
+        LightEngine::new(&mut self.chunks, &self.dimension, &mut self.light_changes)
+    }
+
+    /// The chunks whose light changed since the last call, which need an
+    /// Update Light.
+    pub fn take_light_changes(&mut self) -> Vec<(i32, i32)> {
+        self.light_changes.drain().collect()
+    }
+
+    /// The block and light changes since the last call.
+    pub fn take_changes(&mut self) -> LevelChanges {
+        LevelChanges {
+            blocks: self.take_block_changes(),
+            light: self.take_light_changes(),
+        }
+    }
+
+    /// Light is saved with the chunks, so relit chunks need saving too.
+    fn mark_light_dirty(&mut self) {
+        if self.storage.is_some() {
+            self.dirty.extend(self.light_changes.iter().copied());
+        }
+    }
+
+    fn ensure_chunk(&mut self, x: i32, z: i32) {
+        if self.chunks.contains_key(&(x, z)) {
+            return;
+        }
+        if let Some(storage) = &self.storage {
+            let biomes = &self.registries.biomes;
+            let loaded = storage
+                .lock()
+                .unwrap()
+                .load_chunk(x, z, &self.dimension, biomes);
+            match loaded {
+                Ok(Some(column)) => {
+                    let lit = column.is_light_on();
+                    self.chunks.insert((x, z), column);
+                    if !lit {
+                        self.light_engine().light_chunk(x, z);
+                        self.mark_light_dirty();
+                    }
+                    self.requeue_waiting_ticks(x, z);
+                    return;
+                }
+                Ok(None) => {
+                    // New chunks are saved so the world looks the same next time
+                    self.dirty.insert((x, z));
+                }
+                Err(e) => {
+                    println!(
+                        "Failed to load chunk {}, {} of {}, it won't be saved: {}",
+                        x, z, self.name, e
+                    );
+                    self.unsaved.insert((x, z));
+                }
+            }
+        }
+        self.chunks.insert((x, z), self.generator.generate(x, z));
+        self.light_engine().light_chunk(x, z);
+        self.mark_light_dirty();
+        self.requeue_waiting_ticks(x, z);
+    }
+
+    /// They run in the next game tick, as they are overdue.
+    fn requeue_waiting_ticks(&mut self, x: i32, z: i32) {
+        for tick in self.waiting_ticks.remove(&(x, z)).unwrap_or_default() {
+            self.ticks.requeue(tick);
+        }
+    }
+
+    /// Converts the changed chunks for `PendingSave::write` and counts
+    /// them as saved. Chunks that failed to load are left out. Converting is quick, so it can happen under the lock
+    /// of a level that is in use; writing is left for later. `None` without
+    /// storage.
+    pub fn take_save(&mut self) -> Option<PendingSave> {
+        let storage = self.storage.clone()?;
+        let unsaved = &self.unsaved;
+        let mut dirty: Vec<(i32, i32)> = self
+            .dirty
+            .drain()
+            .filter(|pos| !unsaved.contains(pos))
+            .collect();
+        dirty.sort_unstable();
+        let biomes = &self.registries.biomes;
+        let chunks = dirty
+            .into_iter()
+            .filter_map(|pos| Some((pos, chunk_to_nbt(self.chunks.get(&pos)?, biomes))))
+            .collect();
+        Some(PendingSave { storage, chunks })
+    }
+
+    /// Marks chunks as changed again after their save failed.
+    pub fn mark_unsaved(&mut self, chunks: impl IntoIterator<Item = (i32, i32)>) {
+        self.dirty.extend(chunks);
+    }
+}
+
+/// Chunks taken from a level by `Level::take_save`.
+pub struct PendingSave {
+    storage: Arc<Mutex<RegionStorage>>,
+    chunks: Vec<((i32, i32), nbt::Value)>,
+}
+
+/// A failed save, with the chunks to give back to `Level::mark_unsaved`.
+#[derive(Debug)]
+pub struct SaveError {
+    pub error: AnvilError,
+    pub unsaved: Vec<(i32, i32)>,
+}
+
+impl PendingSave {
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Writes the chunks to the region files and returns how many there
+    /// were. This blocks on file IO. Saves of a level write one at a time;
+    /// they must be written in the order they were taken, or an older copy
+    /// of a chunk can replace a newer one.
+    pub fn write(self) -> Result<usize, SaveError> {
+        let mut storage = self.storage.lock().unwrap();
+        let positions: Vec<(i32, i32)> = self.chunks.iter().map(|(pos, _)| *pos).collect();
+        for (i, ((x, z), nbt)) in self.chunks.iter().enumerate() {
+            if let Err(error) = storage.write_chunk_nbt(*x, *z, nbt) {
+                let unsaved = positions[i..].to_vec();
+                return Err(SaveError { error, unsaved });
+            }
+        }
+        if let Err(e) = storage.sync() {
+            let error = e.into();
+            return Err(SaveError {
+                error,
+                unsaved: positions,
+            });
+        }
+        Ok(positions.len())
+    }
+}
+
+/// What changed in a level during a game tick, for every player in it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LevelChanges {
+    pub blocks: Vec<BlockPos>,
+    /// Chunks whose light changed.
+    pub light: Vec<(i32, i32)>,
+}
+
+impl LevelChanges {
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.light.is_empty()
+    }
+}
+
+/// Ticks of changes a player can fall behind on before their chunks are
+/// sent again instead.
+pub const CHANGES_BACKLOG: usize = 64;
+
+/// Every world the server hosts. Players join the first one.
+pub struct LevelSet {
+    names: Vec<McIdentifier>,
+    levels: Vec<Arc<Mutex<Level>>>,
+    changes: Vec<broadcast::Sender<Arc<LevelChanges>>>,
+}
+
+impl LevelSet {
+    /// `None` if there are no levels or two share a name.
+    pub fn new(levels: Vec<Level>) -> Option<LevelSet> {
+        let names: Vec<McIdentifier> = levels.iter().map(|l| l.name().clone()).collect();
+        let unique: HashSet<&McIdentifier> = names.iter().collect();
+        if names.is_empty() || unique.len() != names.len() {
+            return None;
+        }
+        Some(LevelSet {
+            changes: names
+                .iter()
+                .map(|_| broadcast::channel(CHANGES_BACKLOG).0)
+                .collect(),
+            names,
+            levels: levels
+                .into_iter()
+                .map(|l| Arc::new(Mutex::new(l)))
+                .collect(),
+        })
+    }
+
+    /// In the order they were added, as listed in Join Game.
+    pub fn names(&self) -> &[McIdentifier] {
+        &self.names
+    }
+
+    pub fn first(&self) -> &Arc<Mutex<Level>> {
+        &self.levels[0]
+    }
+
+    pub fn get(&self, name: &McIdentifier) -> Option<&Arc<Mutex<Level>>> {
+        let index = self.names.iter().position(|n| n == name)?;
+        Some(&self.levels[index])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Mutex<Level>>> {
+        self.levels.iter()
+    }
+
+    /// The changes `tick` sends from the level called `name`.
+    pub fn subscribe(&self, name: &McIdentifier) -> Option<broadcast::Receiver<Arc<LevelChanges>>> {
+        let index = self.names.iter().position(|n| n == name)?;
+        Some(self.changes[index].subscribe())
+    }
+
+    /// Runs a game tick of the level called `name`, then sends everything
+    /// that changed in it since the last tick, including changes made while
+    /// players loaded chunks, to its subscribers.
+    pub fn tick(&self, name: &McIdentifier) {
+        let index = match self.names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => return,
+        };
+        let changes = {
+            let mut level = self.levels[index].lock().unwrap();
+            level.tick();
+            level.take_changes()
+        };
+        if !changes.is_empty() {
+            // Without subscribers nobody needs them
+            self.changes[index].send(Arc::new(changes)).ok();
+        }
+    }
+}
+
+/// A superflat overworld of the embedded registries, grass at Y 3.
+#[cfg(test)]
+pub(crate) fn test_level() -> Level {
+    use super::{
+        generator::flat::{FlatGenerator, FlatSettings},
+        registry::test_overworld,
+    };
+
+    let (registries, dimension) = test_overworld();
+    let generator =
+        FlatGenerator::new(FlatSettings::default(), &dimension, &registries.biomes).unwrap();
+    let name = McIdentifier::new("zipperland", "test").unwrap();
+    let overworld = McIdentifier::minecraft("overworld").unwrap();
+    Level::new(
+        name,
+        overworld,
+        Arc::new(registries),
+        0,
+        Arc::new(generator),
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::behavior::BlockBehavior;
+    use std::path::Path;
+
+    fn stored_level(region_dir: &Path) -> Level {
+        test_level().with_storage(RegionStorage::new(region_dir))
+    }
+
+    /// Turns into stone when its tick runs.
+    struct Hardening;
+
+    impl BlockBehavior for Hardening {
+        fn scheduled_tick(&self, level: &mut Level, pos: BlockPos, _: BlockState) {
+            level.place_block(pos, BlockState::from_name("stone").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_waiting_ticks() {
+        let mut behaviors = BlockBehaviors::new();
+        behaviors.register(
+            &McIdentifier::minecraft("dirt").unwrap(),
+            Arc::new(Hardening),
+        );
+        let mut level = test_level().with_behaviors(Arc::new(behaviors));
+        let dirt = BlockState::from_name("dirt").unwrap();
+
+        // Due before its chunk is loaded, the tick runs once it is
+        level.schedule_tick((100, 1, 5), dirt, 1, TickPriority::Normal);
+        level.tick();
+        level.tick();
+        assert!(!level.is_loaded(6, 0));
+        assert_eq!(level.get_block(100, 1, 5), dirt);
+        level.tick();
+        assert_eq!(
+            level.get_block(100, 1, 5),
+            BlockState::from_name("stone").unwrap()
+        );
+        assert_eq!(level.get_block(100, 2, 5), dirt);
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = std::env::temp_dir().join(format!("zipper-level-save-{}", std::process::id()));
+        let stone = BlockState::from_name("stone").unwrap();
+
+        // A file where the region directory should be makes the save fail
+        std::fs::create_dir_all(&dir).unwrap();
+        let blocked = dir.join("blocked");
+        std::fs::write(&blocked, b"").unwrap();
+        let mut level = stored_level(&blocked.join("region"));
+        level.place_block((1, 10, 1), stone);
+        let save = level.take_save().unwrap();
+        assert_eq!(save.len(), 1);
+        assert!(level.take_save().unwrap().is_empty());
+        let e = save.write().unwrap_err();
+        assert_eq!(e.unsaved, vec![(0, 0)]);
+        level.mark_unsaved(e.unsaved);
+        assert_eq!(level.take_save().unwrap().len(), 1);
+
+        let region = dir.join("region");
+        let mut level = stored_level(&region);
+        level.place_block((1, 10, 1), stone);
+        let written = level.take_save().unwrap().write();
+        let mut loaded = stored_level(&region);
+        let block = loaded.get_block(1, 10, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written.unwrap(), 1);
+        assert_eq!(block, stone);
+    }
+
+    #[test]
+    fn test_keep_unloadable_chunks() {
+        let dir = std::env::temp_dir().join(format!("zipper-level-old-{}", std::process::id()));
+        let stone = BlockState::from_name("stone").unwrap();
+        let mut old = nbt::Map::new();
+        old.insert("DataVersion".to_owned(), nbt::Value::Int(2586));
+        old.insert("Status".to_owned(), nbt::Value::String("full".to_owned()));
+        let old = nbt::Value::Compound(old);
+        let mut storage = RegionStorage::new(&dir);
+        storage.write_chunk_nbt(0, 0, &old).unwrap();
+
+        // The 1.16 chunk is generated again and played in, but not saved
+        let mut level = stored_level(&dir);
+        level.place_block((1, 10, 1), stone);
+        level.place_block((20, 10, 1), stone);
+        let save = level.take_save().unwrap();
+        let saved = save.write();
+        let on_disk = RegionStorage::new(&dir).read_chunk_nbt(0, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(level.get_block(1, 10, 1), stone);
+        assert!(saved.unwrap() >= 1);
+        assert_eq!(on_disk.unwrap(), Some(old));
+    }
+}
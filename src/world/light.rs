@@ -0,0 +1,567 @@
+//! Sky light and block light.
+//!
+//! Light spreads from its sources one block at a time, losing at least one
+//! level per block and more when passing through blocks that filter it. Sky
+//! light is the exception: at full strength it shines straight down through
+//! transparent blocks without getting weaker. Light crosses section and
+//! chunk borders, but only into chunks that are loaded; a chunk that loads
+//! later pulls in the light of its neighbours.
+//!
+//! Block changes are handled incrementally: the light that depended on the
+//! changed block is removed, then the area is lit again from the light
+//! around it.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::OnceLock,
+};
+
+use super::{
+    blocks::{blocks, BlockState},
+    chunk::ChunkColumn,
+    dimensions::DimensionType,
+};
+use crate::mc_types::McIdentifier;
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// The loaded chunks of a world, by chunk coordinates.
+pub type ChunkMap = HashMap<(i32, i32), ChunkColumn>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    Sky,
+    Block,
+}
+
+const DOWN: (i32, i32, i32) = (0, -1, 0);
+const DIRECTIONS: [(i32, i32, i32); 6] = [
+    DOWN,
+    (0, 1, 0),
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+];
+
+fn vanilla_name(state: BlockState) -> Option<&'static str> {
+    let name = &state.block().name;
+    if name.namespace() == McIdentifier::DEFAULT_NAMESPACE {
+        Some(name.name())
+    } else {
+        None
+    }
+}
+
+/// The emission and opacity of every block state, by ID.
+fn light_properties() -> &'static [(u8, u8)] {
+    static PROPERTIES: OnceLock<Vec<(u8, u8)>> = OnceLock::new();
+    PROPERTIES.get_or_init(|| {
+        (0..blocks().state_count() as i32)
+            .map(|id| {
+                let state = BlockState::from_id(id).unwrap();
+                (block_emission(state), block_opacity(state))
+            })
+            .collect()
+    })
+}
+
+/// The light level a block gives off.
+pub fn emission(state: BlockState) -> u8 {
+    light_properties()[state.id() as usize].0
+}
+
+/// How many levels light loses passing into a block: 0 for blocks it
+/// passes freely, 15 for those it can't enter.
+pub fn opacity(state: BlockState) -> u8 {
+    light_properties()[state.id() as usize].1
+}
+
+fn block_emission(state: BlockState) -> u8 {
+    let name = match vanilla_name(state) {
+        Some(name) => name,
+        None => return 0,
+    };
+    // Furnaces, lamps, campfires and the like only glow while lit
+    if state.get("lit") == Some("false") {
+        return 0;
+    }
+    match name {
+        "lava"
+        | "fire"
+        | "glowstone"
+        | "jack_o_lantern"
+        | "sea_lantern"
+        | "beacon"
+        | "conduit"
+        | "lantern"
+        | "shroomlight"
+        | "end_gateway"
+        | "end_portal"
+        | "redstone_lamp"
+        | "campfire"
+        | "respawn_anchor"
+        | "ochre_froglight"
+        | "verdant_froglight"
+        | "pearlescent_froglight" => MAX_LIGHT,
+        "torch" | "wall_torch" | "end_rod" => 14,
+        "furnace" | "blast_furnace" | "smoker" => 13,
+        "nether_portal" | "crying_obsidian" => 11,
+        "soul_fire" | "soul_torch" | "soul_wall_torch" | "soul_lantern" | "soul_campfire" => 10,
+        "enchanting_table"
+        | "ender_chest"
+        | "glow_lichen"
+        | "redstone_ore"
+        | "deepslate_redstone_ore" => 7,
+        "redstone_torch" | "redstone_wall_torch" => 7,
+        "sea_pickle" | "amethyst_cluster" => 5,
+        "magma_block" => 3,
+        "brewing_stand" | "brown_mushroom" | "dragon_egg" | "end_portal_frame" | "sculk_sensor" => {
+            1
+        }
+        _ => 0,
+    }
+}
+
+fn block_opacity(state: BlockState) -> u8 {
+    if state.is_air() {
+        return 0;
+    }
+    let name = match vanilla_name(state) {
+        Some(name) => name,
+        None => return MAX_LIGHT,
+    };
+    let see_through = matches!(
+        name,
+        "glass"
+            | "grass"
+            | "fern"
+            | "tall_grass"
+            | "large_fern"
+            | "dead_bush"
+            | "dandelion"
+            | "poppy"
+            | "blue_orchid"
+            | "allium"
+            | "azure_bluet"
+            | "oxeye_daisy"
+            | "cornflower"
+            | "lily_of_the_valley"
+            | "brown_mushroom"
+            | "red_mushroom"
+            | "sugar_cane"
+            | "vine"
+            | "ladder"
+            | "lever"
+            | "torch"
+            | "redstone_wire"
+            | "tripwire"
+            | "rail"
+            | "end_rod"
+            | "fire"
+            | "soul_fire"
+            | "lantern"
+            | "soul_lantern"
+            | "iron_bars"
+            | "chain"
+            | "wheat"
+            | "carrots"
+            | "potatoes"
+            | "beetroots"
+    );
+    let see_through_kinds = [
+        "_sapling",
+        "_tulip",
+        "_glass",
+        "_pane",
+        "_torch",
+        "_rail",
+        "_button",
+        "_sign",
+        "_fence",
+        "_fence_gate",
+        "_door",
+        "_pressure_plate",
+        "_carpet",
+        "_banner",
+    ];
+    let filtering = matches!(
+        name,
+        "water"
+            | "lava"
+            | "ice"
+            | "frosted_ice"
+            | "slime_block"
+            | "honey_block"
+            | "cobweb"
+            | "snow"
+            | "farmland"
+            | "dirt_path"
+            | "spawner"
+            | "beacon"
+    );
+    let filtering_kinds = ["_leaves", "_slab", "_stairs"];
+    if see_through || see_through_kinds.iter().any(|s| name.ends_with(s)) {
+        0
+    } else if filtering || filtering_kinds.iter().any(|s| name.ends_with(s)) {
+        1
+    } else {
+        MAX_LIGHT
+    }
+}
+
+/// Lights the chunks of one world. `changed` collects the chunks whose
+/// light changed, which need an Update Light.
+pub struct LightEngine<'a> {
+    chunks: &'a mut ChunkMap,
+    dimension: &'a DimensionType,
+    changed: &'a mut HashSet<(i32, i32)>,
+}
+
+impl<'a> LightEngine<'a> {
+    pub fn new(
+        chunks: &'a mut ChunkMap,
+        dimension: &'a DimensionType,
+        changed: &'a mut HashSet<(i32, i32)>,
+    ) -> LightEngine<'a> {
+        LightEngine {
+            chunks,
+            dimension,
+            changed,
+        }
+    }
+
+    /// Light sections span one section more below and above the world.
+    fn light_range(&self) -> (i32, i32) {
+        let min_y = self.dimension.min_y - 16;
+        (min_y, self.dimension.max_y() + 16)
+    }
+
+    /// Whether sky light has a source. A ceiling keeps the sky out, though
+    /// the light arrays are still sent.
+    fn sky_shines(&self) -> bool {
+        self.dimension.has_skylight && !self.dimension.has_ceiling
+    }
+
+    fn kinds(&self) -> &'static [LightKind] {
+        if self.sky_shines() {
+            &[LightKind::Sky, LightKind::Block]
+        } else {
+            &[LightKind::Block]
+        }
+    }
+
+    /// The light at a position, or `None` if its chunk isn't loaded or it
+    /// is out of the light sections.
+    pub fn get(&self, kind: LightKind, x: i32, y: i32, z: i32) -> Option<u8> {
+        let (min_y, max_y) = self.light_range();
+        if y < min_y || y >= max_y {
+            return None;
+        }
+        let column = self.chunks.get(&(x >> 4, z >> 4))?;
+        let index = ((y - min_y) >> 4) as usize;
+        let array = match kind {
+            LightKind::Sky => column.sky_light(index),
+            LightKind::Block => column.block_light(index),
+        };
+        let (lx, ly, lz) = (
+            (x & 15) as usize,
+            ((y - min_y) & 15) as usize,
+            (z & 15) as usize,
+        );
+        Some(array.map_or(0, |a| a.get(lx, ly, lz)))
+    }
+
+    fn set(&mut self, kind: LightKind, x: i32, y: i32, z: i32, level: u8) {
+        let min_y = self.light_range().0;
+        let column = match self.chunks.get_mut(&(x >> 4, z >> 4)) {
+            Some(column) => column,
+            None => return,
+        };
+        let index = ((y - min_y) >> 4) as usize;
+        let array = match kind {
+            LightKind::Sky => column.sky_light_mut(index),
+            LightKind::Block => column.block_light_mut(index),
+        };
+        let (lx, ly, lz) = (
+            (x & 15) as usize,
+            ((y - min_y) & 15) as usize,
+            (z & 15) as usize,
+        );
+        array.set(lx, ly, lz, level);
+        self.changed.insert((x >> 4, z >> 4));
+    }
+
+    fn block(&self, x: i32, y: i32, z: i32) -> BlockState {
+        match self.chunks.get(&(x >> 4, z >> 4)) {
+            Some(column) => column.get_block((x & 15) as usize, y, (z & 15) as usize),
+            None => BlockState::AIR,
+        }
+    }
+
+    /// The light reaching a neighbour in `direction` from a block at
+    /// `level`.
+    fn spread(kind: LightKind, direction: (i32, i32, i32), level: u8, neighbour: BlockState) -> u8 {
+        let opacity = opacity(neighbour);
+        if kind == LightKind::Sky && direction == DOWN && level == MAX_LIGHT {
+            MAX_LIGHT.saturating_sub(opacity)
+        } else {
+            level.saturating_sub(opacity.max(1))
+        }
+    }
+
+    /// Spreads light outward from `queue` until it fades out.
+    fn increase(&mut self, kind: LightKind, mut queue: VecDeque<(i32, i32, i32)>) {
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = self.get(kind, x, y, z).unwrap_or(0);
+            if level <= 1 {
+                continue;
+            }
+            for &(dx, dy, dz) in &DIRECTIONS {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let current = match self.get(kind, nx, ny, nz) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                let new = Self::spread(kind, (dx, dy, dz), level, self.block(nx, ny, nz));
+                if new > current {
+                    self.set(kind, nx, ny, nz, new);
+                    queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+    }
+
+    /// Removes the light that came from the positions in `queue`, each with
+    /// the level it had. Returns the lit positions bordering the removed
+    /// area, from which it is lit again.
+    fn decrease(
+        &mut self,
+        kind: LightKind,
+        mut queue: VecDeque<((i32, i32, i32), u8)>,
+    ) -> VecDeque<(i32, i32, i32)> {
+        let mut relight = VecDeque::new();
+        while let Some(((x, y, z), level)) = queue.pop_front() {
+            for &(dx, dy, dz) in &DIRECTIONS {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let current = match self.get(kind, nx, ny, nz) {
+                    Some(current) if current > 0 => current,
+                    _ => continue,
+                };
+                let straight_down = kind == LightKind::Sky
+                    && (dx, dy, dz) == DOWN
+                    && level == MAX_LIGHT
+                    && current == MAX_LIGHT;
+                if current < level || straight_down {
+                    self.set(kind, nx, ny, nz, 0);
+                    queue.push_back(((nx, ny, nz), current));
+                } else {
+                    relight.push_back((nx, ny, nz));
+                }
+            }
+        }
+        relight
+    }
+
+    /// Computes the light of a newly loaded chunk, taking in the light of
+    /// loaded neighbours and spreading its own into them.
+    pub fn light_chunk(&mut self, chunk_x: i32, chunk_z: i32) {
+        let column = match self.chunks.get_mut(&(chunk_x, chunk_z)) {
+            Some(column) => column,
+            None => return,
+        };
+        for i in 0..column.light_section_count() {
+            column.set_block_light(i, None);
+            column.set_sky_light(i, None);
+            if self.dimension.has_skylight {
+                column.sky_light_mut(i);
+            }
+        }
+        column.set_light_on(true);
+        self.changed.insert((chunk_x, chunk_z));
+        let (base_x, base_z) = (chunk_x * 16, chunk_z * 16);
+        let (min_y, max_y) = self.light_range();
+
+        let mut sky = VecDeque::new();
+        if self.sky_shines() {
+            // Direct sky light only spreads sideways below the highest
+            // surface around, higher up every neighbour is as bright
+            let mut highest = min_y;
+            for (cx, cz) in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)] {
+                if let Some(column) = self.chunks.get(&(chunk_x + cx, chunk_z + cz)) {
+                    for x in 0..16 {
+                        for z in 0..16 {
+                            highest = highest.max(column.surface_y(x, z));
+                        }
+                    }
+                }
+            }
+            for x in base_x..base_x + 16 {
+                for z in base_z..base_z + 16 {
+                    let mut y = max_y - 1;
+                    while y >= min_y && opacity(self.block(x, y, z)) == 0 {
+                        self.set(LightKind::Sky, x, y, z, MAX_LIGHT);
+                        if y <= highest {
+                            sky.push_back((x, y, z));
+                        }
+                        y -= 1;
+                    }
+                    if y + 1 < max_y && y + 1 > highest {
+                        sky.push_back((x, y + 1, z));
+                    }
+                }
+            }
+        }
+
+        let mut block = VecDeque::new();
+        let column = &self.chunks[&(chunk_x, chunk_z)];
+        let column_min_y = column.min_y();
+        let mut emitters = Vec::new();
+        for (i, section) in column.sections().iter().enumerate() {
+            let may_emit = match section.blocks.palette() {
+                Some(palette) => palette
+                    .iter()
+                    .any(|&id| emission(BlockState::from_id(id).unwrap_or_default()) > 0),
+                None => true,
+            };
+            if !may_emit {
+                continue;
+            }
+            for y in 0..16 {
+                for z in 0..16 {
+                    for x in 0..16 {
+                        let level = emission(section.get_block(x, y, z));
+                        if level > 0 {
+                            let y = column_min_y + (i * 16 + y) as i32;
+                            emitters.push((base_x + x as i32, y, base_z + z as i32, level));
+                        }
+                    }
+                }
+            }
+        }
+        for (x, y, z, level) in emitters {
+            self.set(LightKind::Block, x, y, z, level);
+            block.push_back((x, y, z));
+        }
+
+        // Light already in the neighbours flows in across the borders
+        let borders = [
+            (base_x - 1, base_z, 0, 1),
+            (base_x + 16, base_z, 0, 1),
+            (base_x, base_z - 1, 1, 0),
+            (base_x, base_z + 16, 1, 0),
+        ];
+        for (start_x, start_z, step_x, step_z) in borders {
+            if !self.chunks.contains_key(&(start_x >> 4, start_z >> 4)) {
+                continue;
+            }
+            for i in 0..16 {
+                let (x, z) = (start_x + i * step_x, start_z + i * step_z);
+                for y in min_y..max_y {
+                    if self.sky_shines() && self.get(LightKind::Sky, x, y, z) > Some(1) {
+                        sky.push_back((x, y, z));
+                    }
+                    if self.get(LightKind::Block, x, y, z) > Some(1) {
+                        block.push_back((x, y, z));
+                    }
+                }
+            }
+        }
+
+        self.increase(LightKind::Sky, sky);
+        self.increase(LightKind::Block, block);
+    }
+
+    /// Updates the light around a block that just changed.
+    pub fn update_block(&mut self, x: i32, y: i32, z: i32) {
+        for &kind in self.kinds() {
+            let mut removed = VecDeque::new();
+            if let Some(level) = self.get(kind, x, y, z) {
+                if level > 0 {
+                    self.set(kind, x, y, z, 0);
+                    removed.push_back(((x, y, z), level));
+                }
+            } else {
+                continue;
+            }
+            let mut relight = self.decrease(kind, removed);
+            for &(dx, dy, dz) in &DIRECTIONS {
+                relight.push_back((x + dx, y + dy, z + dz));
+            }
+            if kind == LightKind::Block {
+                let level = emission(self.block(x, y, z));
+                if level > 0 {
+                    self.set(kind, x, y, z, level);
+                    relight.push_back((x, y, z));
+                }
+            }
+            self.increase(kind, relight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::registry::test_overworld;
+
+    #[test]
+    fn test_light() {
+        let (registries, dimension) = test_overworld();
+        let stone = BlockState::from_name("stone").unwrap();
+        let lava = BlockState::from_name("lava").unwrap();
+
+        // Two chunks with a stone floor at Y 0
+        let mut chunks = ChunkMap::new();
+        for x in [0, 1] {
+            let mut column = ChunkColumn::new(x, 0, &dimension, 0, registries.biomes.len());
+            for bx in 0..16 {
+                for bz in 0..16 {
+                    column.set_block(bx, 0, bz, stone);
+                }
+            }
+            chunks.insert((x, 0), column);
+        }
+        let mut changed = HashSet::new();
+        let mut engine = LightEngine::new(&mut chunks, &dimension, &mut changed);
+        engine.light_chunk(0, 0);
+        engine.light_chunk(1, 0);
+        assert_eq!(engine.get(LightKind::Sky, 3, 1, 3), Some(15));
+        assert_eq!(engine.get(LightKind::Sky, 3, 0, 3), Some(0));
+        assert_eq!(engine.get(LightKind::Block, 3, 1, 3), Some(0));
+
+        // A roof over one block darkens it, sky light leaks in from the side
+        engine
+            .chunks
+            .get_mut(&(0, 0))
+            .unwrap()
+            .set_block(3, 2, 3, stone);
+        engine.update_block(3, 2, 3);
+        assert_eq!(engine.get(LightKind::Sky, 3, 1, 3), Some(14));
+        engine
+            .chunks
+            .get_mut(&(0, 0))
+            .unwrap()
+            .set_block(3, 2, 3, BlockState::AIR);
+        engine.update_block(3, 2, 3);
+        assert_eq!(engine.get(LightKind::Sky, 3, 1, 3), Some(15));
+
+        // Block light crosses into the next chunk, and goes away again
+        engine
+            .chunks
+            .get_mut(&(0, 0))
+            .unwrap()
+            .set_block(15, 1, 0, lava);
+        engine.update_block(15, 1, 0);
+        assert_eq!(engine.get(LightKind::Block, 15, 1, 0), Some(15));
+        assert_eq!(engine.get(LightKind::Block, 18, 1, 0), Some(12));
+        engine
+            .chunks
+            .get_mut(&(0, 0))
+            .unwrap()
+            .set_block(15, 1, 0, BlockState::AIR);
+        engine.update_block(15, 1, 0);
+        assert_eq!(engine.get(LightKind::Block, 18, 1, 0), Some(0));
+        assert!(changed.contains(&(1, 0)));
+    }
+}
@@ -1 +1,17 @@
+pub mod anvil;
+pub mod behavior;
+pub mod biomes;
+pub mod blocks;
+pub mod chunk;
+pub mod datapack;
 pub mod dimensions;
+pub mod generator;
+pub mod ids;
+pub mod inventory;
+pub mod level;
+pub mod light;
+pub mod player;
+pub mod registry;
+pub mod schema;
+pub mod tick;
+pub mod tracking;
@@ -0,0 +1,135 @@
+//! What the server keeps about a connected player.
+
+use std::ops::BitOr;
+
+use super::inventory::ItemStack;
+use crate::mc_types::McIdentifier;
+
+/// Slots in the player's inventory window: crafting, armor, main inventory,
+/// hotbar and offhand.
+pub const INVENTORY_SIZE: usize = 46;
+
+/// The "data kept" flags of Respawn, which say what survives a respawn.
+/// Protocol 757 only has the metadata flag; the inventory is kept along with
+/// the attributes, since vanilla resets both only when a player dies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DataKept(u8);
+
+impl DataKept {
+    pub const NONE: DataKept = DataKept(0);
+    pub const ATTRIBUTES: DataKept = DataKept(0x01);
+    pub const METADATA: DataKept = DataKept(0x02);
+    pub const ALL: DataKept = DataKept(0x03);
+
+    pub fn from_bits(bits: u8) -> DataKept {
+        DataKept(bits & DataKept::ALL.0)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn keeps_attributes(self) -> bool {
+        self.0 & DataKept::ATTRIBUTES.0 != 0
+    }
+
+    pub fn keeps_metadata(self) -> bool {
+        self.0 & DataKept::METADATA.0 != 0
+    }
+}
+
+impl BitOr for DataKept {
+    type Output = DataKept;
+
+    fn bitor(self, rhs: DataKept) -> DataKept {
+        DataKept(self.0 | rhs.0)
+    }
+}
+
+/// The player entity metadata the server tracks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerMetadata {
+    pub health: f32,
+    pub absorption: f32,
+    pub score: i32,
+    /// Which skin layers are shown, from Client Settings.
+    pub skin_parts: u8,
+}
+
+impl Default for PlayerMetadata {
+    fn default() -> Self {
+        PlayerMetadata {
+            health: 20.0,
+            absorption: 0.0,
+            score: 0,
+            skin_parts: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    pub name: String,
+    /// The world the player is in.
+    pub world: McIdentifier,
+    /// Indexed by inventory window slot.
+    pub inventory: Vec<Option<ItemStack>>,
+    pub metadata: PlayerMetadata,
+}
+
+impl Player {
+    pub fn new(name: String, world: McIdentifier) -> Player {
+        Player {
+            name,
+            world,
+            inventory: vec![None; INVENTORY_SIZE],
+            metadata: PlayerMetadata::default(),
+        }
+    }
+
+    /// Moves the player to `world`, resetting what `kept` doesn't keep.
+    pub fn change_world(&mut self, world: McIdentifier, kept: DataKept) {
+        self.world = world;
+        if !kept.keeps_attributes() {
+            self.inventory = vec![None; INVENTORY_SIZE];
+        }
+        if !kept.keeps_metadata() {
+            // Client settings outlive the entity
+            let skin_parts = self.metadata.skin_parts;
+            self.metadata = PlayerMetadata {
+                skin_parts,
+                ..PlayerMetadata::default()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_world() {
+        let overworld = McIdentifier::minecraft("overworld").unwrap();
+        let nether = McIdentifier::minecraft("the_nether").unwrap();
+        let mut player = Player::new("steve".to_owned(), overworld.clone());
+        player.inventory[36] = Some(ItemStack::from_name("stone", 3).unwrap());
+        player.metadata.health = 7.0;
+        player.metadata.skin_parts = 0x7f;
+
+        player.change_world(nether.clone(), DataKept::ALL);
+        assert_eq!(player.world, nether);
+        assert!(player.inventory[36].is_some());
+        assert_eq!(player.metadata.health, 7.0);
+
+        player.change_world(overworld.clone(), DataKept::ATTRIBUTES);
+        assert!(player.inventory[36].is_some());
+        assert_eq!(player.metadata.health, 20.0);
+        assert_eq!(player.metadata.skin_parts, 0x7f);
+
+        player.change_world(overworld, DataKept::NONE);
+        assert!(player.inventory.iter().all(Option::is_none));
+        assert_eq!(DataKept::from_bits(0xff), DataKept::ALL);
+        assert_eq!(DataKept::ATTRIBUTES | DataKept::METADATA, DataKept::ALL);
+    }
+}
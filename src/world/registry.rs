@@ -0,0 +1,340 @@
+//! Typed registries and their serialization into the dimension codec.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use crate::mc_types::McIdentifier;
+
+use super::{
+    biomes::{biomes_from_entries, load_biomes, parse_biomes, Biome, EMBEDDED_BIOMES},
+    datapack::{load_datapack, PackRegistry},
+    dimensions::{
+        dimension_types_from_entries, load_dimension_types, parse_dimension_types, DimensionType,
+        EMBEDDED_DIMENSION_TYPES,
+    },
+    schema::RegistryErrors,
+};
+
+pub type NbtMap = nbt::Map<String, nbt::Value>;
+
+/// Values that are sent to the client as NBT, such as registry elements.
+pub trait ToNbt {
+    fn to_nbt(&self) -> nbt::Value;
+
+    /// The element as sent in Registry Data, from protocol 766 (1.20.5).
+    /// Only elements whose format changed since 1.18 override this.
+    fn to_registry_data(&self) -> nbt::Value {
+        self.to_nbt()
+    }
+}
+
+pub(super) fn nbt_bool(b: bool) -> nbt::Value {
+    nbt::Value::Byte(if b { 1 } else { 0 })
+}
+
+pub(super) fn insert(map: &mut NbtMap, k: &str, v: nbt::Value) {
+    map.insert(k.to_owned(), v);
+}
+
+pub(super) fn insert_string(map: &mut NbtMap, k: &str, v: &str) {
+    insert(map, k, nbt::Value::String(v.to_owned()));
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryEntry<T> {
+    pub name: McIdentifier,
+    pub id: i32,
+    pub element: T,
+}
+
+impl<T: ToNbt> ToNbt for RegistryEntry<T> {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut entry = nbt::Map::new();
+        insert_string(&mut entry, "name", &self.name.to_string());
+        insert(&mut entry, "id", nbt::Value::Int(self.id));
+        insert(&mut entry, "element", self.element.to_nbt());
+        nbt::Value::Compound(entry)
+    }
+}
+
+/// Entries numbered in the order they were registered. Registering a name
+/// again replaces its element but keeps its ID.
+#[derive(Debug, Clone)]
+pub struct Registry<T> {
+    registry_type: McIdentifier,
+    entries: Vec<RegistryEntry<T>>,
+    ids: HashMap<McIdentifier, usize>,
+    tags: BTreeMap<McIdentifier, Vec<i32>>,
+}
+
+impl<T> Registry<T> {
+    pub fn new(registry_type: McIdentifier) -> Registry<T> {
+        Registry {
+            registry_type,
+            entries: Vec::new(),
+            ids: HashMap::new(),
+            tags: BTreeMap::new(),
+        }
+    }
+
+    pub fn registry_type(&self) -> &McIdentifier {
+        &self.registry_type
+    }
+
+    /// Adds or replaces an entry and returns its ID.
+    pub fn register(&mut self, name: McIdentifier, element: T) -> i32 {
+        if let Some(&index) = self.ids.get(&name) {
+            self.entries[index].element = element;
+            return index as i32;
+        }
+        let id = self.entries.len();
+        self.ids.insert(name.clone(), id);
+        self.entries.push(RegistryEntry {
+            name,
+            id: id as i32,
+            element,
+        });
+        id as i32
+    }
+
+    pub fn get(&self, name: &McIdentifier) -> Option<&RegistryEntry<T>> {
+        self.ids.get(name).map(|&index| &self.entries[index])
+    }
+
+    pub fn get_by_id(&self, id: i32) -> Option<&RegistryEntry<T>> {
+        if id < 0 {
+            return None;
+        }
+        self.entries.get(id as usize)
+    }
+
+    pub fn id_of(&self, name: &McIdentifier) -> Option<i32> {
+        self.ids.get(name).map(|&index| index as i32)
+    }
+
+    pub fn contains(&self, name: &McIdentifier) -> bool {
+        self.ids.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries in ID order.
+    pub fn iter(&self) -> impl Iterator<Item = &RegistryEntry<T>> {
+        self.entries.iter()
+    }
+
+    /// Sets the entries of a tag, replacing any earlier definition. Fails
+    /// with the first name that isn't registered.
+    pub fn set_tag(
+        &mut self,
+        tag: McIdentifier,
+        names: &[McIdentifier],
+    ) -> Result<(), McIdentifier> {
+        let ids = names
+            .iter()
+            .map(|name| self.id_of(name).ok_or_else(|| name.clone()))
+            .collect::<Result<_, _>>()?;
+        self.tags.insert(tag, ids);
+        Ok(())
+    }
+
+    /// Tags and the IDs of their entries, sorted by tag name.
+    pub fn tags(&self) -> impl Iterator<Item = (&McIdentifier, &[i32])> {
+        self.tags.iter().map(|(tag, ids)| (tag, ids.as_slice()))
+    }
+}
+
+impl<T: ToNbt> ToNbt for Registry<T> {
+    fn to_nbt(&self) -> nbt::Value {
+        let mut registry = nbt::Map::new();
+        insert_string(&mut registry, "type", &self.registry_type.to_string());
+        let entries = self.entries.iter().map(ToNbt::to_nbt).collect();
+        insert(&mut registry, "value", nbt::Value::List(entries));
+        nbt::Value::Compound(registry)
+    }
+}
+
+/// Where registry entries are loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistrySource {
+    /// The dimension types and biomes shipped with the server, compiled
+    /// into the binary.
+    Embedded,
+    /// A YAML file of dimension types.
+    DimensionTypes(PathBuf),
+    /// A YAML file of biomes.
+    Biomes(PathBuf),
+    /// A vanilla datapack directory or zip file.
+    Datapack(PathBuf),
+}
+
+/// The registries that make up the dimension codec sent in Join Game.
+#[derive(Debug, Clone)]
+pub struct RegistrySet {
+    pub dimension_types: Registry<DimensionType>,
+    pub biomes: Registry<Biome>,
+}
+
+impl RegistrySet {
+    pub fn new() -> RegistrySet {
+        RegistrySet {
+            dimension_types: Registry::new(McIdentifier::minecraft("dimension_type").unwrap()),
+            biomes: Registry::new(McIdentifier::minecraft("worldgen/biome").unwrap()),
+        }
+    }
+
+    /// Builds registries from `sources` in order. An entry from a later
+    /// source replaces the entry of the same name from an earlier one and
+    /// keeps its ID. Errors from every source are reported together.
+    pub fn load(sources: &[RegistrySource]) -> Result<RegistrySet, RegistryErrors> {
+        let mut registries = RegistrySet::new();
+        let mut errors = Vec::new();
+        for source in sources {
+            if let Err(e) = registries.add_source(source) {
+                errors.extend(e.0);
+            }
+        }
+        if errors.is_empty() {
+            Ok(registries)
+        } else {
+            Err(RegistryErrors(errors))
+        }
+    }
+
+    /// Adds or replaces the entries of one source.
+    pub fn add_source(&mut self, source: &RegistrySource) -> Result<(), RegistryErrors> {
+        match source {
+            RegistrySource::Embedded => {
+                let dimension_types =
+                    parse_dimension_types("<embedded dimensions.yaml>", EMBEDDED_DIMENSION_TYPES)?;
+                for (name, dimension_type) in dimension_types {
+                    self.dimension_types.register(name, dimension_type);
+                }
+                for (name, biome) in parse_biomes("<embedded biomes.yaml>", EMBEDDED_BIOMES)? {
+                    self.biomes.register(name, biome);
+                }
+            }
+            RegistrySource::DimensionTypes(file) => {
+                let file = file.to_string_lossy();
+                for (name, dimension_type) in load_dimension_types(&file)? {
+                    self.dimension_types.register(name, dimension_type);
+                }
+            }
+            RegistrySource::Biomes(file) => {
+                for (name, biome) in load_biomes(&file.to_string_lossy())? {
+                    self.biomes.register(name, biome);
+                }
+            }
+            RegistrySource::Datapack(pack) => {
+                let mut errors = Vec::new();
+                for entry in load_datapack(pack)? {
+                    let entries = [(entry.name, entry.doc)];
+                    let result = match entry.registry {
+                        PackRegistry::DimensionType => {
+                            dimension_types_from_entries(&entry.file, &entries).map(|v| {
+                                for (name, dimension_type) in v {
+                                    self.dimension_types.register(name, dimension_type);
+                                }
+                            })
+                        }
+                        PackRegistry::Biome => {
+                            biomes_from_entries(&entry.file, &entries).map(|v| {
+                                for (name, biome) in v {
+                                    self.biomes.register(name, biome);
+                                }
+                            })
+                        }
+                    };
+                    if let Err(e) = result {
+                        errors.extend(e.0);
+                    }
+                }
+                if !errors.is_empty() {
+                    return Err(RegistryErrors(errors));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The codec compound, keyed by registry type.
+    pub fn to_codec(&self) -> nbt::Value {
+        let mut codec = nbt::Map::new();
+        insert(
+            &mut codec,
+            &self.dimension_types.registry_type().to_string(),
+            self.dimension_types.to_nbt(),
+        );
+        insert(
+            &mut codec,
+            &self.biomes.registry_type().to_string(),
+            self.biomes.to_nbt(),
+        );
+        nbt::Value::Compound(codec)
+    }
+}
+
+impl Default for RegistrySet {
+    fn default() -> Self {
+        RegistrySet::new()
+    }
+}
+
+/// The embedded registries and their overworld, which world tests build on.
+#[cfg(test)]
+pub fn test_overworld() -> (RegistrySet, DimensionType) {
+    let registries = RegistrySet::load(&[RegistrySource::Embedded]).unwrap();
+    let overworld = McIdentifier::minecraft("overworld").unwrap();
+    let dimension = registries.dimension_types.get(&overworld).unwrap();
+    let dimension = dimension.element.clone();
+    (registries, dimension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl ToNbt for i32 {
+        fn to_nbt(&self) -> nbt::Value {
+            nbt::Value::Int(*self)
+        }
+    }
+
+    #[test]
+    fn test_registry_ids() {
+        let mut registry = Registry::new(McIdentifier::minecraft("test").unwrap());
+        let a: McIdentifier = "zipper:a".parse().unwrap();
+        let b: McIdentifier = "zipper:b".parse().unwrap();
+        assert_eq!(registry.register(a.clone(), 10), 0);
+        assert_eq!(registry.register(b.clone(), 20), 1);
+        assert_eq!(registry.register(a.clone(), 30), 0);
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.id_of(&b), Some(1));
+        assert_eq!(registry.get(&a).unwrap().element, 30);
+        assert_eq!(registry.get_by_id(1).unwrap().name, b);
+        assert!(registry.get_by_id(2).is_none());
+
+        let tag: McIdentifier = "zipper:all".parse().unwrap();
+        registry
+            .set_tag(tag.clone(), &[b.clone(), a.clone()])
+            .unwrap();
+        assert_eq!(registry.tags().next(), Some((&tag, &[1, 0][..])));
+        let missing: McIdentifier = "zipper:c".parse().unwrap();
+        assert_eq!(registry.set_tag(tag, &[missing.clone()]), Err(missing));
+
+        let expected = crate::mc_types::snbt::parse(
+            r#"{type:"minecraft:test",value:[{name:"zipper:a",id:0,element:30},
+                {name:"zipper:b",id:1,element:20}]}"#,
+        )
+        .unwrap();
+        assert_eq!(registry.to_nbt(), expected);
+    }
+}
@@ -0,0 +1,318 @@
+//! Checked access to registry entries in YAML files.
+//!
+//! `EntryFields` reads the fields of one registry entry, falling back to
+//! defaults for optional fields and recording an error for every field that
+//! is missing, has the wrong type or is out of range. Unknown keys are
+//! reported too, so that typos don't silently fall back to a default.
+
+use std::{fmt, io};
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::mc_types::McIdentifier;
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io {
+        file: String,
+        error: io::Error,
+    },
+    Syntax {
+        file: String,
+        message: String,
+    },
+    Field {
+        file: String,
+        entry: String,
+        field: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::Io { file, error } => write!(f, "{}: {}", file, error),
+            RegistryError::Syntax { file, message } => write!(f, "{}: {}", file, message),
+            RegistryError::Field {
+                file,
+                entry,
+                field,
+                message,
+            } => write!(f, "{}: {}: {}: {}", file, entry, field, message),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<RegistryError> for io::Error {
+    fn from(e: RegistryError) -> Self {
+        let kind = match &e {
+            RegistryError::Io { error, .. } => error.kind(),
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// Every error found while loading a registry file.
+#[derive(Debug)]
+pub struct RegistryErrors(pub Vec<RegistryError>);
+
+impl fmt::Display for RegistryErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RegistryErrors {}
+
+impl From<RegistryError> for RegistryErrors {
+    fn from(e: RegistryError) -> Self {
+        RegistryErrors(vec![e])
+    }
+}
+
+/// Reads a YAML file whose top level maps entry names to entries, and
+/// returns the entries in file order.
+pub fn load_entries(file: &str) -> Result<Vec<(String, Yaml)>, RegistryError> {
+    let text = std::fs::read_to_string(file).map_err(|error| RegistryError::Io {
+        file: file.to_owned(),
+        error,
+    })?;
+    parse_entries(file, &text)
+}
+
+/// Like `load_entries`, for YAML that is already in memory. `file` is only
+/// used in error messages.
+pub fn parse_entries(file: &str, text: &str) -> Result<Vec<(String, Yaml)>, RegistryError> {
+    let yaml_error = |message: String| RegistryError::Syntax {
+        file: file.to_owned(),
+        message,
+    };
+    let docs = YamlLoader::load_from_str(text).map_err(|e| yaml_error(e.to_string()))?;
+    let doc = match docs.into_iter().next() {
+        Some(doc) => doc,
+        None => return Ok(Vec::new()),
+    };
+    let hash = match doc {
+        Yaml::Hash(hash) => hash,
+        _ => return Err(yaml_error("top level must map names to entries".to_owned())),
+    };
+    let mut entries = Vec::new();
+    for (k, v) in hash {
+        match k {
+            Yaml::String(name) => entries.push((name, v)),
+            k => return Err(yaml_error(format!("entry name {:?} is not a string", k))),
+        }
+    }
+    Ok(entries)
+}
+
+pub struct EntryFields<'a> {
+    file: &'a str,
+    entry: String,
+    doc: &'a Yaml,
+    seen: Vec<String>,
+    errors: &'a mut Vec<RegistryError>,
+}
+
+impl<'a> EntryFields<'a> {
+    pub fn new(
+        file: &'a str,
+        entry: &str,
+        doc: &'a Yaml,
+        errors: &'a mut Vec<RegistryError>,
+    ) -> EntryFields<'a> {
+        let mut fields = EntryFields {
+            file,
+            entry: entry.to_owned(),
+            doc,
+            seen: Vec::new(),
+            errors,
+        };
+        if doc.as_hash().is_none() {
+            fields.error("", "entry must be a mapping");
+        }
+        fields
+    }
+
+    /// Fields of a nested mapping, reported as `parent.child`.
+    pub fn nested<'b>(&'b mut self, k: &str) -> Option<EntryFields<'b>> {
+        let doc = self.get(k)?;
+        let mut entry = self.entry.clone();
+        entry.push('.');
+        entry.push_str(k);
+        if doc.as_hash().is_none() {
+            self.error(k, "expected a mapping");
+            return None;
+        }
+        Some(EntryFields {
+            file: self.file,
+            entry,
+            doc,
+            seen: Vec::new(),
+            errors: self.errors,
+        })
+    }
+
+    pub fn error(&mut self, field: &str, message: &str) {
+        self.errors.push(RegistryError::Field {
+            file: self.file.to_owned(),
+            entry: self.entry.clone(),
+            field: field.to_owned(),
+            message: message.to_owned(),
+        });
+    }
+
+    pub fn has(&self, k: &str) -> bool {
+        !self.doc[k].is_badvalue()
+    }
+
+    /// Whether the field is present and a mapping.
+    pub fn is_mapping(&self, k: &str) -> bool {
+        self.doc[k].as_hash().is_some()
+    }
+
+    /// Reads an optional field that has no default. The outer `Option` is
+    /// `None` if the field is present but invalid.
+    pub fn optional<T>(
+        &mut self,
+        k: &str,
+        read: impl FnOnce(&mut Self, &str) -> Option<T>,
+    ) -> Option<Option<T>> {
+        if self.has(k) {
+            read(self, k).map(Some)
+        } else {
+            Some(None)
+        }
+    }
+
+    /// Accepts a field without reading it.
+    pub fn ignore(&mut self, k: &str) {
+        self.seen.push(k.to_owned());
+    }
+
+    fn get(&mut self, k: &str) -> Option<&'a Yaml> {
+        self.seen.push(k.to_owned());
+        let doc: &'a Yaml = self.doc;
+        let value = &doc[k];
+        if value.is_badvalue() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Reads a field with `read`, using `default` when it is absent. A
+    /// missing field without a default is an error.
+    fn field<T>(
+        &mut self,
+        k: &str,
+        default: Option<T>,
+        expected: &str,
+        read: impl Fn(&Yaml) -> Option<T>,
+    ) -> Option<T> {
+        match self.get(k) {
+            Some(value) => {
+                let parsed = read(value);
+                if parsed.is_none() {
+                    self.error(k, &format!("expected {}, found {:?}", expected, value));
+                }
+                parsed
+            }
+            None => {
+                if default.is_none() {
+                    self.error(k, "missing required field");
+                }
+                default
+            }
+        }
+    }
+
+    pub fn bool(&mut self, k: &str, default: Option<bool>) -> Option<bool> {
+        self.field(k, default, "a boolean", Yaml::as_bool)
+    }
+
+    /// An integer in `min..=max`.
+    pub fn int(&mut self, k: &str, default: Option<i64>, min: i64, max: i64) -> Option<i64> {
+        let value = self.field(k, default, "an integer", Yaml::as_i64)?;
+        if value < min || value > max {
+            self.error(k, &format!("{} is not in {}..={}", value, min, max));
+            return None;
+        }
+        Some(value)
+    }
+
+    /// A number in `min..=max`. Whole numbers written as integers count.
+    pub fn float(&mut self, k: &str, default: Option<f64>, min: f64, max: f64) -> Option<f64> {
+        let value = self.field(k, default, "a number", |y| {
+            y.as_f64().or_else(|| y.as_i64().map(|i| i as f64))
+        })?;
+        if value < min || value > max {
+            self.error(k, &format!("{} is not in {}..={}", value, min, max));
+            return None;
+        }
+        Some(value)
+    }
+
+    pub fn string(&mut self, k: &str, default: Option<&str>) -> Option<String> {
+        self.field(k, default.map(|d| d.to_owned()), "a string", |y| {
+            y.as_str().map(|s| s.to_owned())
+        })
+    }
+
+    /// A string, or a number taken as it is written.
+    pub fn text(&mut self, k: &str, default: Option<&str>) -> Option<String> {
+        self.field(k, default.map(|d| d.to_owned()), "a string", |y| match y {
+            Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+            Yaml::Integer(i) => Some(i.to_string()),
+            _ => None,
+        })
+    }
+
+    /// A resource location. A leading `#` for tag references is kept when
+    /// `allow_tag` is set.
+    pub fn identifier(
+        &mut self,
+        k: &str,
+        default: Option<&str>,
+        allow_tag: bool,
+    ) -> Option<String> {
+        let value = self.string(k, default)?;
+        let id = match value.strip_prefix('#') {
+            Some(tag) if allow_tag => tag,
+            _ => &value,
+        };
+        if let Err(e) = id.parse::<McIdentifier>() {
+            self.error(k, &e.to_string());
+            return None;
+        }
+        Some(value)
+    }
+
+    /// Reports any keys that were never asked for.
+    pub fn finish(mut self) {
+        let unknown: Vec<String> = match self.doc.as_hash() {
+            Some(hash) => hash
+                .keys()
+                .map(|k| match k.as_str() {
+                    Some(s) => s.to_owned(),
+                    None => format!("{:?}", k),
+                })
+                .filter(|k| !self.seen.contains(k))
+                .collect(),
+            None => return,
+        };
+        for k in unknown {
+            self.error(&k, "unknown field");
+        }
+    }
+}
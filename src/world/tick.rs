@@ -0,0 +1,176 @@
+//! Scheduled block ticks.
+//!
+//! Blocks ask to be ticked again after a delay, like sand that falls two
+//! ticks after losing its support. Ticks due in the same game tick run by
+//! priority, then in the order they were scheduled. A block has at most one
+//! pending tick at a position: scheduling another while one is pending does
+//! nothing, as in vanilla. A tick is skipped if the block was replaced by
+//! another block before it ran.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+use super::blocks::BlockState;
+
+/// World block coordinates.
+pub type BlockPos = (i32, i32, i32);
+
+/// Vanilla's tick priorities. Higher priorities run first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum TickPriority {
+    ExtremelyHigh = -3,
+    VeryHigh = -2,
+    High = -1,
+    #[default]
+    Normal = 0,
+    Low = 1,
+    VeryLow = 2,
+    ExtremelyLow = 3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledTick {
+    pub pos: BlockPos,
+    /// The game time the tick runs at.
+    pub time: i64,
+    pub priority: TickPriority,
+    /// The first state of the block the tick is for.
+    block: i32,
+    /// Breaks ties in scheduling order.
+    order: u64,
+}
+
+impl ScheduledTick {
+    /// Whether the tick is for the block of `state`.
+    pub fn is_for(&self, state: BlockState) -> bool {
+        state.block().first_state == self.block
+    }
+}
+
+impl Ord for ScheduledTick {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.priority, self.order).cmp(&(other.time, other.priority, other.order))
+    }
+}
+
+impl PartialOrd for ScheduledTick {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TickScheduler {
+    queue: BinaryHeap<Reverse<ScheduledTick>>,
+    /// Positions and blocks with a tick in `queue`.
+    pending: HashSet<(BlockPos, i32)>,
+    next_order: u64,
+}
+
+impl TickScheduler {
+    pub fn new() -> TickScheduler {
+        TickScheduler::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Whether the block of `state` has a tick pending at `pos`.
+    pub fn is_scheduled(&self, pos: BlockPos, state: BlockState) -> bool {
+        self.pending.contains(&(pos, state.block().first_state))
+    }
+
+    /// Schedules a tick of the block of `state` at game time `time`. Returns
+    /// false if the block already had one pending at `pos`.
+    pub fn schedule(
+        &mut self,
+        pos: BlockPos,
+        state: BlockState,
+        time: i64,
+        priority: TickPriority,
+    ) -> bool {
+        let block = state.block().first_state;
+        if !self.pending.insert((pos, block)) {
+            return false;
+        }
+        self.queue.push(Reverse(ScheduledTick {
+            pos,
+            time,
+            priority,
+            block,
+            order: self.next_order,
+        }));
+        self.next_order += 1;
+        true
+    }
+
+    /// Puts back a tick taken by `take_due`, keeping its time and place in
+    /// the order. Returns false if the block has another tick pending at its
+    /// position by now.
+    pub fn requeue(&mut self, tick: ScheduledTick) -> bool {
+        if !self.pending.insert((tick.pos, tick.block)) {
+            return false;
+        }
+        self.queue.push(Reverse(tick));
+        true
+    }
+
+    /// Up to `limit` ticks due by game time `time`, in the order they run.
+    /// They stop being pending, so running them can schedule them again.
+    pub fn take_due(&mut self, time: i64, limit: usize) -> Vec<ScheduledTick> {
+        let mut due = Vec::new();
+        while due.len() < limit {
+            match self.queue.peek() {
+                Some(Reverse(tick)) if tick.time <= time => {}
+                _ => break,
+            }
+            let Reverse(tick) = self.queue.pop().unwrap();
+            self.pending.remove(&(tick.pos, tick.block));
+            due.push(tick);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduler() {
+        let sand = BlockState::from_name("sand").unwrap();
+        let gravel = BlockState::from_name("gravel").unwrap();
+        let mut ticks = TickScheduler::new();
+        assert!(ticks.schedule((0, 0, 0), sand, 5, TickPriority::Normal));
+        assert!(ticks.schedule((1, 0, 0), sand, 5, TickPriority::High));
+        assert!(ticks.schedule((2, 0, 0), sand, 3, TickPriority::Low));
+        assert!(ticks.schedule((3, 0, 0), sand, 5, TickPriority::Normal));
+        assert!(!ticks.schedule((0, 0, 0), sand, 1, TickPriority::Normal));
+        assert!(ticks.schedule((0, 0, 0), gravel, 9, TickPriority::Normal));
+        assert!(ticks.is_scheduled((0, 0, 0), gravel));
+        assert_eq!(ticks.len(), 5);
+
+        assert!(ticks.take_due(2, 10).is_empty());
+        let order: Vec<BlockPos> = ticks.take_due(5, 3).iter().map(|t| t.pos).collect();
+        assert_eq!(order, vec![(2, 0, 0), (1, 0, 0), (0, 0, 0)]);
+        assert!(!ticks.is_scheduled((0, 0, 0), sand));
+        let last = ticks.take_due(5, 10);
+        assert_eq!(last.len(), 1);
+        assert!(last[0].is_for(sand) && !last[0].is_for(gravel));
+        assert_eq!(ticks.len(), 1);
+
+        // Put back, it runs before ticks scheduled later for the same time
+        assert!(ticks.schedule((5, 0, 0), sand, 5, TickPriority::Normal));
+        assert!(ticks.requeue(last[0].clone()));
+        assert!(!ticks.requeue(last[0].clone()));
+        let order: Vec<BlockPos> = ticks.take_due(5, 10).iter().map(|t| t.pos).collect();
+        assert_eq!(order, vec![(3, 0, 0), (5, 0, 0)]);
+    }
+}
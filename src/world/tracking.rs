@@ -0,0 +1,220 @@
+//! Which chunks a player has been sent.
+//!
+//! Each player sees the square of chunks within their view distance around
+//! the chunk they stand in. Missing chunks are sent nearest first, a few per
+//! tick so a fast moving player doesn't flood the socket, and chunks that
+//! leave the square are unloaded. Changes to the chunks already sent wait
+//! in the tracker until they are sent too.
+
+use std::collections::{HashSet, VecDeque};
+
+use super::{chunk::SECTION_SIZE, level::LevelChanges, tick::BlockPos};
+
+/// The chunks within `radius` of `center`, nearest first.
+pub fn spiral(center: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+    let mut chunks = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+    for dx in -radius..=radius {
+        for dz in -radius..=radius {
+            chunks.push((dx, dz));
+        }
+    }
+    chunks.sort_by_key(|&(dx, dz)| dx * dx + dz * dz);
+    chunks
+        .into_iter()
+        .map(|(dx, dz)| (center.0 + dx, center.1 + dz))
+        .collect()
+}
+
+/// The chunk holding block coordinates `x`, `z`.
+pub fn chunk_at(x: f64, z: f64) -> (i32, i32) {
+    let size = SECTION_SIZE as i32;
+    (
+        (x.floor() as i32).div_euclid(size),
+        (z.floor() as i32).div_euclid(size),
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkTracker {
+    view_distance: i32,
+    chunks_per_tick: usize,
+    center: Option<(i32, i32)>,
+    sent: HashSet<(i32, i32)>,
+    /// Chunks in range that haven't been sent, nearest first.
+    pending: VecDeque<(i32, i32)>,
+    unloads: Vec<(i32, i32)>,
+    /// Blocks changed in sent chunks.
+    changed_blocks: HashSet<BlockPos>,
+    /// Sent chunks whose light changed.
+    relit: HashSet<(i32, i32)>,
+}
+
+impl ChunkTracker {
+    pub fn new(view_distance: i32, chunks_per_tick: usize) -> ChunkTracker {
+        ChunkTracker {
+            view_distance,
+            chunks_per_tick,
+            center: None,
+            sent: HashSet::new(),
+            pending: VecDeque::new(),
+            unloads: Vec::new(),
+            changed_blocks: HashSet::new(),
+            relit: HashSet::new(),
+        }
+    }
+
+    pub fn view_distance(&self) -> i32 {
+        self.view_distance
+    }
+
+    /// The chunk the player is in, once they have a position.
+    pub fn center(&self) -> Option<(i32, i32)> {
+        self.center
+    }
+
+    pub fn is_sent(&self, x: i32, z: i32) -> bool {
+        self.sent.contains(&(x, z))
+    }
+
+    fn in_range(&self, (x, z): (i32, i32)) -> bool {
+        match self.center {
+            Some((cx, cz)) => (x - cx).abs().max((z - cz).abs()) <= self.view_distance,
+            None => false,
+        }
+    }
+
+    /// Moves the player to block coordinates `x`, `z`. Returns the new
+    /// center chunk when they crossed into another chunk, which needs an
+    /// Update View Position.
+    pub fn move_to(&mut self, x: f64, z: f64) -> Option<(i32, i32)> {
+        let center = chunk_at(x, z);
+        if self.center == Some(center) {
+            return None;
+        }
+        self.center = Some(center);
+        self.refresh();
+        Some(center)
+    }
+
+    pub fn set_view_distance(&mut self, view_distance: i32) {
+        if view_distance != self.view_distance {
+            self.view_distance = view_distance;
+            self.refresh();
+        }
+    }
+
+    /// Queues the chunks that came into range and unloads those that left.
+    fn refresh(&mut self) {
+        let out_of_range: Vec<(i32, i32)> = self
+            .sent
+            .iter()
+            .copied()
+            .filter(|&chunk| !self.in_range(chunk))
+            .collect();
+        for chunk in &out_of_range {
+            self.sent.remove(chunk);
+        }
+        self.unloads.extend(out_of_range);
+        self.pending = match self.center {
+            Some(center) => spiral(center, self.view_distance)
+                .into_iter()
+                .filter(|chunk| !self.sent.contains(chunk))
+                .collect(),
+            None => VecDeque::new(),
+        };
+    }
+
+    /// The chunks to send this tick, which count as sent from now on.
+    pub fn next_batch(&mut self) -> Vec<(i32, i32)> {
+        let count = self.chunks_per_tick.min(self.pending.len());
+        let batch: Vec<(i32, i32)> = self.pending.drain(..count).collect();
+        self.sent.extend(batch.iter().copied());
+        batch
+    }
+
+    /// The chunks to unload since the last call.
+    pub fn take_unloads(&mut self) -> Vec<(i32, i32)> {
+        std::mem::take(&mut self.unloads)
+    }
+
+    /// Keeps the changes to chunks that were sent. The others are sent as
+    /// they are now once their turn comes.
+    pub fn add_changes(&mut self, changes: &LevelChanges) {
+        let size = SECTION_SIZE as i32;
+        for &(x, y, z) in &changes.blocks {
+            if self.is_sent(x.div_euclid(size), z.div_euclid(size)) {
+                self.changed_blocks.insert((x, y, z));
+            }
+        }
+        for &(x, z) in &changes.light {
+            if self.is_sent(x, z) {
+                self.relit.insert((x, z));
+            }
+        }
+    }
+
+    /// The changes kept since the last call.
+    pub fn take_changes(&mut self) -> LevelChanges {
+        LevelChanges {
+            blocks: self.changed_blocks.drain().collect(),
+            light: self.relit.drain().collect(),
+        }
+    }
+
+    /// Sends every chunk in range again, for when changes to them were
+    /// missed.
+    pub fn resend_all(&mut self) {
+        self.sent.clear();
+        self.changed_blocks.clear();
+        self.relit.clear();
+        self.refresh();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker() {
+        assert_eq!(spiral((5, 5), 1)[0], (5, 5));
+        assert_eq!(spiral((0, 0), 2).len(), 25);
+        assert_eq!(chunk_at(-0.5, 16.0), (-1, 1));
+
+        let mut tracker = ChunkTracker::new(2, 10);
+        assert!(tracker.next_batch().is_empty());
+        assert_eq!(tracker.move_to(8.5, 8.5), Some((0, 0)));
+        assert_eq!(tracker.move_to(15.9, 0.0), None);
+        let batch = tracker.next_batch();
+        assert_eq!(batch.len(), 10);
+        assert_eq!(batch[0], (0, 0));
+        assert!(batch[1..5].iter().all(|&(x, z)| x.abs() + z.abs() == 1));
+        tracker.next_batch();
+        assert_eq!(tracker.next_batch().len(), 5);
+        assert!(tracker.next_batch().is_empty());
+
+        // Two chunks east the two western columns leave range
+        assert_eq!(tracker.move_to(40.0, 8.0), Some((2, 0)));
+        let unloads = tracker.take_unloads();
+        assert_eq!(unloads.len(), 10);
+        assert!(unloads.iter().all(|&(x, _)| x < 0));
+        assert!(!tracker.is_sent(-2, 0));
+        assert_eq!(tracker.next_batch().len(), 10);
+        assert!(tracker.is_sent(4, 2));
+
+        tracker.set_view_distance(1);
+        assert_eq!(tracker.take_unloads().len(), 16);
+
+        tracker.add_changes(&LevelChanges {
+            blocks: vec![(33, 5, -1), (33, 5, 32), (80, 5, 0)],
+            light: vec![(2, 0), (5, 0)],
+        });
+        let changes = tracker.take_changes();
+        assert_eq!(changes.blocks, vec![(33, 5, -1)]);
+        assert_eq!(changes.light, vec![(2, 0)]);
+        assert!(tracker.take_changes().is_empty());
+        tracker.resend_all();
+        assert!(!tracker.is_sent(2, 0));
+        assert_eq!(tracker.next_batch().len(), 9);
+    }
+}